@@ -0,0 +1,17 @@
+-- Your SQL goes here
+CREATE TABLE sessions (
+  id SERIAL PRIMARY KEY,
+  token_hash CHAR(64) NOT NULL UNIQUE,
+  user_id BIGINT UNSIGNED NOT NULL,
+  created TIMESTAMP NOT NULL,
+  expires TIMESTAMP NOT NULL,
+  revoked TINYINT NOT NULL,
+  FOREIGN KEY (user_id)
+    REFERENCES users(id)
+    ON DELETE CASCADE
+    ON UPDATE CASCADE
+);
+
+INSERT INTO permission (permission_name) VALUES
+  ("GetSessions"),
+  ("RevokeSessions");
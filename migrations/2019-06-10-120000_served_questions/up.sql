@@ -0,0 +1,20 @@
+-- Your SQL goes here
+CREATE TABLE test_session_served_questions (
+    id SERIAL PRIMARY KEY,
+    registration_id BIGINT UNSIGNED NOT NULL,
+    question_id BIGINT UNSIGNED NOT NULL,
+    position INT UNSIGNED NOT NULL,
+    answer_1 TINYINT UNSIGNED NOT NULL,
+    answer_2 TINYINT UNSIGNED NOT NULL,
+    answer_3 TINYINT UNSIGNED NOT NULL,
+    answer_4 TINYINT UNSIGNED NOT NULL,
+    UNIQUE (registration_id, question_id),
+    FOREIGN KEY (registration_id)
+      REFERENCES test_session_registrations(id)
+      ON DELETE CASCADE
+      ON UPDATE CASCADE,
+    FOREIGN KEY (question_id)
+      REFERENCES questions(id)
+      ON DELETE RESTRICT
+      ON UPDATE CASCADE
+);
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DELETE FROM permission WHERE permission_name = "GetTestResponses";
+DROP TABLE test_responses;
@@ -0,0 +1,20 @@
+-- Your SQL goes here
+CREATE TABLE test_responses (
+    id SERIAL PRIMARY KEY,
+    registration_id BIGINT UNSIGNED NOT NULL,
+    question_id BIGINT UNSIGNED NOT NULL,
+    answer VARCHAR(255),
+    correct TINYINT NOT NULL,
+    UNIQUE (registration_id, question_id),
+    FOREIGN KEY (registration_id)
+      REFERENCES test_session_registrations(id)
+      ON DELETE CASCADE
+      ON UPDATE CASCADE,
+    FOREIGN KEY (question_id)
+      REFERENCES questions(id)
+      ON DELETE RESTRICT
+      ON UPDATE CASCADE
+);
+
+INSERT INTO permission (permission_name) VALUES
+  ("GetTestResponses");
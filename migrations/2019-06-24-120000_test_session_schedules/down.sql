@@ -0,0 +1,16 @@
+-- This file should undo anything in `up.sql`
+UPDATE test_sessions SET
+  registrations_enabled = COALESCE(registrations_enabled, 0),
+  opening_enabled = COALESCE(opening_enabled, 0),
+  submissions_enabled = COALESCE(submissions_enabled, 0);
+
+ALTER TABLE test_sessions
+  MODIFY registrations_enabled TINYINT NOT NULL,
+  MODIFY opening_enabled TINYINT NOT NULL,
+  MODIFY submissions_enabled TINYINT NOT NULL,
+  DROP COLUMN registrations_start,
+  DROP COLUMN registrations_end,
+  DROP COLUMN opening_start,
+  DROP COLUMN opening_end,
+  DROP COLUMN submissions_start,
+  DROP COLUMN submissions_end;
@@ -0,0 +1,20 @@
+-- Your SQL goes here
+ALTER TABLE test_sessions
+  MODIFY registrations_enabled TINYINT NULL DEFAULT NULL,
+  MODIFY opening_enabled TINYINT NULL DEFAULT NULL,
+  MODIFY submissions_enabled TINYINT NULL DEFAULT NULL,
+  ADD COLUMN registrations_start TIMESTAMP NULL DEFAULT NULL,
+  ADD COLUMN registrations_end TIMESTAMP NULL DEFAULT NULL,
+  ADD COLUMN opening_start TIMESTAMP NULL DEFAULT NULL,
+  ADD COLUMN opening_end TIMESTAMP NULL DEFAULT NULL,
+  ADD COLUMN submissions_start TIMESTAMP NULL DEFAULT NULL,
+  ADD COLUMN submissions_end TIMESTAMP NULL DEFAULT NULL;
+
+-- Stages that were never enabled by hand follow their schedule from now on.
+-- Without a schedule they stay closed, as they were before.
+UPDATE test_sessions SET registrations_enabled = NULL
+  WHERE registrations_enabled = 0;
+UPDATE test_sessions SET opening_enabled = NULL
+  WHERE opening_enabled = 0;
+UPDATE test_sessions SET submissions_enabled = NULL
+  WHERE submissions_enabled = 0;
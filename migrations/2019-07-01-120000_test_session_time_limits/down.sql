@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE test_sessions
+  DROP COLUMN time_limit_minutes;
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE test_sessions
+  ADD COLUMN time_limit_minutes INT UNSIGNED NULL DEFAULT NULL;
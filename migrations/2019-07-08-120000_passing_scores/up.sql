@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE tests
+  ADD COLUMN passing_score FLOAT NULL DEFAULT NULL;
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE tests
+  DROP COLUMN max_attempts,
+  DROP COLUMN retake_cooldown_hours,
+  DROP COLUMN counted_attempt;
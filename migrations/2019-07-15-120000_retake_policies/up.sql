@@ -0,0 +1,5 @@
+-- Your SQL goes here
+ALTER TABLE tests
+  ADD COLUMN max_attempts INT UNSIGNED NULL DEFAULT NULL,
+  ADD COLUMN retake_cooldown_hours INT UNSIGNED NULL DEFAULT NULL,
+  ADD COLUMN counted_attempt VARCHAR(16) NOT NULL DEFAULT "best";
@@ -0,0 +1,11 @@
+-- This file should undo anything in `up.sql`
+DELETE FROM permission WHERE permission_name IN (
+  "GetCertifications",
+  "CreateCertifications",
+  "DeleteCertifications",
+  "GetUserCertifications"
+);
+ALTER TABLE tests DROP FOREIGN KEY tests_certification_id_fk;
+ALTER TABLE tests DROP COLUMN certification_id;
+DROP TABLE user_certifications;
+DROP TABLE certifications;
@@ -0,0 +1,40 @@
+-- Your SQL goes here
+CREATE TABLE certifications (
+  id SERIAL PRIMARY KEY,
+  name VARCHAR(255) NOT NULL UNIQUE,
+  valid_months INT UNSIGNED NOT NULL
+);
+
+CREATE TABLE user_certifications (
+  id SERIAL PRIMARY KEY,
+  user_id BIGINT UNSIGNED NOT NULL,
+  certification_id BIGINT UNSIGNED NOT NULL,
+  registration_id BIGINT UNSIGNED,
+  issued DATETIME NOT NULL,
+  expires DATETIME NOT NULL,
+  FOREIGN KEY (user_id)
+    REFERENCES users(id)
+    ON DELETE CASCADE
+    ON UPDATE CASCADE,
+  FOREIGN KEY (certification_id)
+    REFERENCES certifications(id)
+    ON DELETE CASCADE
+    ON UPDATE CASCADE,
+  FOREIGN KEY (registration_id)
+    REFERENCES test_session_registrations(id)
+    ON DELETE SET NULL
+    ON UPDATE CASCADE
+);
+
+ALTER TABLE tests
+  ADD COLUMN certification_id BIGINT UNSIGNED NULL DEFAULT NULL,
+  ADD CONSTRAINT tests_certification_id_fk FOREIGN KEY (certification_id)
+    REFERENCES certifications(id)
+    ON DELETE SET NULL
+    ON UPDATE CASCADE;
+
+INSERT INTO permission (permission_name) VALUES
+  ("GetCertifications"),
+  ("CreateCertifications"),
+  ("DeleteCertifications"),
+  ("GetUserCertifications");
@@ -0,0 +1,14 @@
+-- Your SQL goes here
+CREATE TABLE permission_certifications (
+  permission_id BIGINT UNSIGNED NOT NULL,
+  certification_id BIGINT UNSIGNED NOT NULL,
+  PRIMARY KEY (permission_id, certification_id),
+  FOREIGN KEY (permission_id)
+    REFERENCES permission(id)
+    ON DELETE CASCADE
+    ON UPDATE CASCADE,
+  FOREIGN KEY (certification_id)
+    REFERENCES certifications(id)
+    ON DELETE CASCADE
+    ON UPDATE CASCADE
+);
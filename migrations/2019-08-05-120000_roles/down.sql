@@ -0,0 +1,10 @@
+-- This file should undo anything in `up.sql`
+DELETE FROM permission WHERE permission_name IN (
+  "GetRoles",
+  "CreateRoles",
+  "UpdateRoles",
+  "DeleteRoles"
+);
+DROP TABLE user_roles;
+DROP TABLE role_permissions;
+DROP TABLE roles;
@@ -0,0 +1,100 @@
+-- Your SQL goes here
+CREATE TABLE roles (
+  id SERIAL PRIMARY KEY,
+  name VARCHAR(255) NOT NULL UNIQUE
+);
+
+CREATE TABLE role_permissions (
+  role_id BIGINT UNSIGNED NOT NULL,
+  permission_id BIGINT UNSIGNED NOT NULL,
+  PRIMARY KEY (role_id, permission_id),
+  FOREIGN KEY (role_id)
+    REFERENCES roles(id)
+    ON DELETE CASCADE
+    ON UPDATE CASCADE,
+  FOREIGN KEY (permission_id)
+    REFERENCES permission(id)
+    ON DELETE CASCADE
+    ON UPDATE CASCADE
+);
+
+CREATE TABLE user_roles (
+  user_id BIGINT UNSIGNED NOT NULL,
+  role_id BIGINT UNSIGNED NOT NULL,
+  PRIMARY KEY (user_id, role_id),
+  FOREIGN KEY (user_id)
+    REFERENCES users(id)
+    ON DELETE CASCADE
+    ON UPDATE CASCADE,
+  FOREIGN KEY (role_id)
+    REFERENCES roles(id)
+    ON DELETE CASCADE
+    ON UPDATE CASCADE
+);
+
+INSERT INTO permission (permission_name) VALUES
+  ("GetRoles"),
+  ("CreateRoles"),
+  ("UpdateRoles"),
+  ("DeleteRoles");
+
+INSERT INTO roles (name) VALUES
+  ("Student"),
+  ("TA"),
+  ("Lab Manager"),
+  ("Admin");
+
+INSERT INTO role_permissions (role_id, permission_id)
+  SELECT roles.id, permission.id FROM roles, permission
+  WHERE roles.name = "Student" AND permission.permission_name IN (
+    "GetChemical",
+    "GetChemicalInventory",
+    "GetTests",
+    "GetTestSessions",
+    "GetCertifications"
+  );
+
+INSERT INTO role_permissions (role_id, permission_id)
+  SELECT roles.id, permission.id FROM roles, permission
+  WHERE roles.name = "TA" AND permission.permission_name IN (
+    "GetUsers",
+    "GetChemical",
+    "GetChemicalInventory",
+    "GetQuestions",
+    "CreateQuestions",
+    "UpdateQuestions",
+    "GetQuestionCategories",
+    "CreateQuestionCategories",
+    "UpdateQuestionCategories",
+    "GetTests",
+    "GetTestSessions",
+    "CreateTestSessions",
+    "UpdateTestSessions",
+    "GetTestSessionRegistrations",
+    "GetTestResponses",
+    "GetCertifications",
+    "GetUserCertifications"
+  );
+
+INSERT INTO role_permissions (role_id, permission_id)
+  SELECT roles.id, permission.id FROM roles, permission
+  WHERE roles.name = "Lab Manager" AND permission.permission_name IN (
+    "GetUsers",
+    "GetChemical",
+    "CreateChemical",
+    "UpdateChemical",
+    "DeleteChemical",
+    "GetChemicalInventory",
+    "CreateChemicalInventory",
+    "UpdateChemicalInventory",
+    "DeleteChemicalInventory",
+    "GetTests",
+    "GetTestSessions",
+    "GetTestSessionRegistrations",
+    "GetCertifications",
+    "GetUserCertifications"
+  );
+
+INSERT INTO role_permissions (role_id, permission_id)
+  SELECT roles.id, permission.id FROM roles, permission
+  WHERE roles.name = "Admin" AND permission.permission_name != "RootAccess";
@@ -0,0 +1,9 @@
+-- Your SQL goes here
+ALTER TABLE users ADD COLUMN department VARCHAR(255) NULL DEFAULT NULL;
+
+-- Access levels used to be free-form and were never checked, so every grant
+-- was unrestricted. Grants whose access level does not read as a list of
+-- owned, room:<room> and department:<department> scopes keep that.
+UPDATE user_access SET access_level = NULL
+  WHERE BINARY access_level NOT REGEXP
+    "^ *(owned|room:[^,]*[^, ]|department:[^,]*[^, ]) *(, *(owned|room:[^,]*[^, ]|department:[^,]*[^, ]) *)*$";
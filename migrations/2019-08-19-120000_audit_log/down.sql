@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DELETE FROM permission WHERE permission_name = "GetAuditLog";
+DROP TABLE audit_log;
@@ -0,0 +1,21 @@
+-- Your SQL goes here
+CREATE TABLE audit_log (
+  id SERIAL PRIMARY KEY,
+  actor_id BIGINT UNSIGNED,
+  action VARCHAR(255) NOT NULL,
+  target_table VARCHAR(255) NOT NULL,
+  target_id BIGINT UNSIGNED NOT NULL,
+  before_json TEXT,
+  after_json TEXT,
+  recorded TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  INDEX (target_table, target_id),
+  INDEX (actor_id)
+);
+
+INSERT INTO permission (permission_name) VALUES
+  ("GetAuditLog");
+
+INSERT INTO role_permissions (role_id, permission_id)
+  SELECT roles.id, permission.id FROM roles, permission
+  WHERE roles.name = "Admin"
+  AND permission.permission_name = "GetAuditLog";
@@ -0,0 +1,11 @@
+-- This file should undo anything in `up.sql`
+DELETE FROM permission WHERE permission_name IN (
+  "RestoreUsers",
+  "RestoreChemical",
+  "RestoreChemicalInventory",
+  "RestoreTests"
+);
+ALTER TABLE tests DROP COLUMN deleted_at;
+ALTER TABLE chemical_inventory DROP COLUMN deleted_at;
+ALTER TABLE chemical DROP COLUMN deleted_at;
+ALTER TABLE users DROP COLUMN deleted_at;
@@ -0,0 +1,27 @@
+-- Your SQL goes here
+ALTER TABLE users ADD COLUMN deleted_at TIMESTAMP NULL DEFAULT NULL;
+ALTER TABLE chemical ADD COLUMN deleted_at TIMESTAMP NULL DEFAULT NULL;
+ALTER TABLE chemical_inventory ADD COLUMN deleted_at TIMESTAMP NULL DEFAULT NULL;
+ALTER TABLE tests ADD COLUMN deleted_at TIMESTAMP NULL DEFAULT NULL;
+
+INSERT INTO permission (permission_name) VALUES
+  ("RestoreUsers"),
+  ("RestoreChemical"),
+  ("RestoreChemicalInventory"),
+  ("RestoreTests");
+
+INSERT INTO role_permissions (role_id, permission_id)
+  SELECT roles.id, permission.id FROM roles, permission
+  WHERE roles.name = "Lab Manager" AND permission.permission_name IN (
+    "RestoreChemical",
+    "RestoreChemicalInventory"
+  );
+
+INSERT INTO role_permissions (role_id, permission_id)
+  SELECT roles.id, permission.id FROM roles, permission
+  WHERE roles.name = "Admin" AND permission.permission_name IN (
+    "RestoreUsers",
+    "RestoreChemical",
+    "RestoreChemicalInventory",
+    "RestoreTests"
+  );
@@ -0,0 +1,9 @@
+-- This file should undo anything in `up.sql`
+UPDATE chemical_inventory
+  SET unparsed_amount = CONCAT(quantity, " ", unit)
+  WHERE unparsed_amount IS NULL;
+
+ALTER TABLE chemical_inventory
+  CHANGE unparsed_amount amount VARCHAR(255) NOT NULL,
+  DROP COLUMN unit,
+  DROP COLUMN quantity;
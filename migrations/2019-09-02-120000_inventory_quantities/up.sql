@@ -0,0 +1,41 @@
+-- Your SQL goes here
+ALTER TABLE chemical_inventory
+  ADD COLUMN quantity DOUBLE NOT NULL DEFAULT 0 AFTER storage_location,
+  ADD COLUMN unit VARCHAR(8) NOT NULL DEFAULT "mL" AFTER quantity,
+  CHANGE amount unparsed_amount VARCHAR(255) NULL;
+
+-- Amounts like "500 mL", "2.5L" or "1 gallon" are read into a quantity and a
+-- unit. Anything else stays in unparsed_amount, to be fixed by hand.
+CREATE TEMPORARY TABLE unit_spellings (
+  spelling VARCHAR(16) NOT NULL,
+  unit VARCHAR(8) NOT NULL
+);
+
+INSERT INTO unit_spellings (spelling, unit) VALUES
+  ("ml", "mL"), ("milliliter", "mL"), ("milliliters", "mL"),
+  ("l", "L"), ("liter", "L"), ("liters", "L"), ("litre", "L"), ("litres", "L"),
+  ("gal", "gal"), ("gallon", "gal"), ("gallons", "gal"),
+  ("g", "g"), ("gram", "g"), ("grams", "g"),
+  ("kg", "kg"), ("kilogram", "kg"), ("kilograms", "kg"),
+  ("lb", "lb"), ("lbs", "lb"), ("pound", "lb"), ("pounds", "lb");
+
+UPDATE chemical_inventory, unit_spellings
+  SET
+    chemical_inventory.quantity = CAST(TRIM(LEFT(
+      TRIM(chemical_inventory.unparsed_amount),
+      CHAR_LENGTH(TRIM(chemical_inventory.unparsed_amount))
+        - CHAR_LENGTH(unit_spellings.spelling)
+    )) AS DECIMAL(30, 10)),
+    chemical_inventory.unit = unit_spellings.unit
+  WHERE LOWER(TRIM(chemical_inventory.unparsed_amount)) REGEXP
+    CONCAT("^[0-9]+(\\.[0-9]+)? ?", unit_spellings.spelling, "$");
+
+UPDATE chemical_inventory
+  SET unparsed_amount = NULL
+  WHERE EXISTS (
+    SELECT * FROM unit_spellings
+    WHERE LOWER(TRIM(chemical_inventory.unparsed_amount)) REGEXP
+      CONCAT("^[0-9]+(\\.[0-9]+)? ?", unit_spellings.spelling, "$")
+  );
+
+DROP TEMPORARY TABLE unit_spellings;
@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+DELETE FROM permission WHERE permission_name IN (
+  "GetChemicalUsage",
+  "RecordChemicalUsage"
+);
+DROP TABLE chemical_usage;
@@ -0,0 +1,30 @@
+-- Your SQL goes here
+CREATE TABLE chemical_usage (
+  id SERIAL PRIMARY KEY,
+  inventory_id BIGINT UNSIGNED NOT NULL,
+  user_id BIGINT UNSIGNED NOT NULL,
+  quantity DOUBLE NOT NULL,
+  unit VARCHAR(8) NOT NULL,
+  purpose VARCHAR(255) NOT NULL,
+  remaining DOUBLE NOT NULL,
+  used TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  INDEX (inventory_id, used),
+  FOREIGN KEY (inventory_id)
+    REFERENCES chemical_inventory(id)
+    ON UPDATE CASCADE,
+  FOREIGN KEY (user_id)
+    REFERENCES users(id)
+    ON UPDATE CASCADE
+);
+
+INSERT INTO permission (permission_name) VALUES
+  ("GetChemicalUsage"),
+  ("RecordChemicalUsage");
+
+INSERT INTO role_permissions (role_id, permission_id)
+  SELECT roles.id, permission.id FROM roles, permission
+  WHERE roles.name IN ("TA", "Lab Manager", "Admin")
+  AND permission.permission_name IN (
+    "GetChemicalUsage",
+    "RecordChemicalUsage"
+  );
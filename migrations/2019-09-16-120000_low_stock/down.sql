@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+DELETE FROM permission WHERE permission_name = "GetLowStockChemicals";
+ALTER TABLE chemical
+  DROP COLUMN minimum_unit,
+  DROP COLUMN minimum_quantity;
@@ -0,0 +1,12 @@
+-- Your SQL goes here
+ALTER TABLE chemical
+  ADD COLUMN minimum_quantity DOUBLE NULL DEFAULT NULL AFTER manual_link,
+  ADD COLUMN minimum_unit VARCHAR(8) NULL DEFAULT NULL AFTER minimum_quantity;
+
+INSERT INTO permission (permission_name) VALUES
+  ("GetLowStockChemicals");
+
+INSERT INTO role_permissions (role_id, permission_id)
+  SELECT roles.id, permission.id FROM roles, permission
+  WHERE roles.name IN ("Lab Manager", "Admin")
+  AND permission.permission_name = "GetLowStockChemicals";
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE chemical_inventory
+  DROP COLUMN expires,
+  DROP COLUMN opened,
+  DROP COLUMN received;
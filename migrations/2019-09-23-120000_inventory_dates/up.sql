@@ -0,0 +1,5 @@
+-- Your SQL goes here
+ALTER TABLE chemical_inventory
+  ADD COLUMN received DATE NULL DEFAULT NULL AFTER unparsed_amount,
+  ADD COLUMN opened DATE NULL DEFAULT NULL AFTER received,
+  ADD COLUMN expires DATE NULL DEFAULT NULL AFTER opened;
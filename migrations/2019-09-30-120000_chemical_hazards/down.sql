@@ -0,0 +1,8 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE chemical
+  DROP INDEX chemical_cas_number,
+  DROP COLUMN precautionary_statements,
+  DROP COLUMN hazard_statements,
+  DROP COLUMN pictograms,
+  DROP COLUMN signal_word,
+  DROP COLUMN cas_number;
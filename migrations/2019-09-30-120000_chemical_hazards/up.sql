@@ -0,0 +1,10 @@
+-- Your SQL goes here
+ALTER TABLE chemical
+  ADD COLUMN cas_number VARCHAR(12) NULL DEFAULT NULL AFTER manual_link,
+  ADD COLUMN signal_word VARCHAR(8) NULL DEFAULT NULL AFTER cas_number,
+  ADD COLUMN pictograms VARCHAR(64) NOT NULL DEFAULT "" AFTER signal_word,
+  ADD COLUMN hazard_statements VARCHAR(1024) NOT NULL DEFAULT ""
+    AFTER pictograms,
+  ADD COLUMN precautionary_statements VARCHAR(1024) NOT NULL DEFAULT ""
+    AFTER hazard_statements,
+  ADD INDEX chemical_cas_number (cas_number);
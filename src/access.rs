@@ -1,3 +1,4 @@
+pub mod identity;
 pub mod models;
 pub mod requests;
 pub mod schema;
@@ -0,0 +1,107 @@
+use std::collections::HashMap;
+use std::io::Read;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use log::trace;
+use log::warn;
+
+use crate::errors::{Error, ErrorKind};
+
+/// The claims about a user that an identity provider has verified
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct IdentityClaims {
+    pub email: Option<String>,
+    pub given_name: Option<String>,
+    pub family_name: Option<String>,
+}
+
+/// Something that can turn an id token into verified claims about a user
+///
+/// `validate_token` and `first_permission` go through this instead of
+/// talking to an identity provider directly, so that sign in can be swapped
+/// out for a local stand-in when testing or developing offline.
+pub trait IdentityVerifier: Send + Sync {
+    fn verify(&self, id_token: &str) -> Result<IdentityClaims, Error>;
+}
+
+/// Verifies id tokens issued by Google Sign-In
+pub struct GoogleVerifier {
+    client: google_signin::Client,
+}
+
+impl GoogleVerifier {
+    /// Create a verifier that accepts tokens issued for any of the given client ids
+    pub fn new(audiences: Vec<String>) -> GoogleVerifier {
+        let mut client = google_signin::Client::new();
+        client.audiences = audiences;
+        GoogleVerifier { client }
+    }
+}
+
+impl IdentityVerifier for GoogleVerifier {
+    fn verify(&self, id_token: &str) -> Result<IdentityClaims, Error> {
+        let id_info = self.client.verify(id_token)?;
+
+        trace!("Validated token: {:?}", id_info);
+
+        Ok(IdentityClaims {
+            email: id_info.email,
+            given_name: id_info.given_name,
+            family_name: id_info.family_name,
+        })
+    }
+}
+
+/// Verifies id tokens against a fixed table of tokens
+///
+/// This is only meant for tests and development environments, where there
+/// is no way to get a real token from Google.
+pub struct StaticVerifier {
+    tokens: HashMap<String, IdentityClaims>,
+}
+
+impl StaticVerifier {
+    pub fn new(tokens: HashMap<String, IdentityClaims>) -> StaticVerifier {
+        StaticVerifier { tokens }
+    }
+
+    /// Read the table of tokens from JSON, in the form
+    /// `{ "token": { "email": ..., "given_name": ..., "family_name": ... } }`
+    pub fn from_reader<R: Read>(reader: R) -> Result<StaticVerifier, Error> {
+        let tokens = serde_json::from_reader(reader)?;
+        Ok(StaticVerifier { tokens })
+    }
+}
+
+impl IdentityVerifier for StaticVerifier {
+    fn verify(&self, id_token: &str) -> Result<IdentityClaims, Error> {
+        match self.tokens.get(id_token) {
+            Some(claims) => Ok(claims.clone()),
+            None => {
+                warn!("Id token not found in the static token table");
+                Err(Error::new(ErrorKind::AccessDenied))
+            }
+        }
+    }
+}
+
+#[test]
+fn static_verifier_known_token_works() {
+    let verifier = StaticVerifier::from_reader(
+        r#"{ "token": { "email": "smithj1@students.rowan.edu", "given_name": "John", "family_name": "Smith" } }"#.as_bytes(),
+    )
+    .unwrap();
+
+    let claims = verifier.verify("token").unwrap();
+    assert_eq!(claims.email, Some("smithj1@students.rowan.edu".to_owned()));
+    assert_eq!(claims.given_name, Some("John".to_owned()));
+    assert_eq!(claims.family_name, Some("Smith".to_owned()));
+}
+
+#[test]
+fn static_verifier_unknown_token_fails() {
+    let verifier = StaticVerifier::new(HashMap::new());
+    assert!(verifier.verify("token").is_err());
+}
@@ -11,9 +11,14 @@ use log::warn;
 
 use crate::errors::{Error, ErrorKind};
 
-use crate::search::{NullableSearch, Search};
+use crate::search::{Digits, NullableSearch, Page, Search};
 
-use super::schema::{permission, user_access};
+use crate::certifications::models::CertificationList;
+
+use super::schema::{
+    permission, permission_certifications, role_permissions, roles,
+    user_access, user_roles,
+};
 
 #[derive(Queryable, Serialize, Deserialize, Clone, Debug)]
 pub struct Permission {
@@ -33,6 +38,26 @@ pub struct PartialPermission {
     pub permission_name: Option<String>,
 }
 
+/// A certification that has to be held, unexpired, to use a permission
+#[derive(Queryable, Insertable, Serialize, Deserialize, Debug)]
+#[table_name = "permission_certifications"]
+pub struct PermissionCertification {
+    pub permission_id: u64,
+    pub certification_id: u64,
+}
+
+/// The names of the certifications a user is missing for a permission
+#[derive(Debug)]
+pub struct MissingCertifications(pub Vec<String>);
+
+impl std::fmt::Display for MissingCertifications {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Missing certifications: {}", self.0.join(", "))
+    }
+}
+
+impl std::error::Error for MissingCertifications {}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct PermissionList {
     pub permissions: Vec<Permission>,
@@ -44,6 +69,9 @@ pub enum PermissionRequest {
     UpdatePermission(u64, PartialPermission), //Contains id to be changed to new access_name
     DeletePermission(u64),                //if of access to be deleted
     FirstPermission(String),
+    GetRequiredCertifications(u64),
+    AddRequiredCertification(PermissionCertification),
+    RemoveRequiredCertification(PermissionCertification),
 }
 
 impl PermissionRequest {
@@ -73,6 +101,24 @@ impl PermissionRequest {
                 Ok(PermissionRequest::DeletePermission(id))
             },
 
+            (GET) (/{id: u64}/certifications) => {
+                Ok(PermissionRequest::GetRequiredCertifications(id))
+            },
+
+            (POST) (/{id: u64}/certifications/{certification_id: u64}) => {
+                Ok(PermissionRequest::AddRequiredCertification(PermissionCertification {
+                    permission_id: id,
+                    certification_id,
+                }))
+            },
+
+            (DELETE) (/{id: u64}/certifications/{certification_id: u64}) => {
+                Ok(PermissionRequest::RemoveRequiredCertification(PermissionCertification {
+                    permission_id: id,
+                    certification_id,
+                }))
+            },
+
             (GET) (/first) => {
                 if let Some(id_token) = request.header("id_token") {
                     Ok(PermissionRequest::FirstPermission(id_token.to_string()))
@@ -91,6 +137,7 @@ impl PermissionRequest {
 
 pub enum PermissionResponse {
     OnePermission(Permission),
+    ManyCertifications(CertificationList),
     NoResponse,
 }
 
@@ -100,11 +147,152 @@ impl PermissionResponse {
             PermissionResponse::OnePermission(permission) => {
                 rouille::Response::json(&permission)
             }
+            PermissionResponse::ManyCertifications(certifications) => {
+                rouille::Response::json(&certifications)
+            }
             PermissionResponse::NoResponse => rouille::Response::empty_204(),
         }
     }
 }
 
+#[derive(Queryable, Debug)]
+pub struct RawRole {
+    pub id: u64,
+    pub name: String,
+}
+
+#[derive(Insertable, Debug)]
+#[table_name = "roles"]
+pub struct NewRawRole {
+    pub name: String,
+}
+
+/// A named group of permissions, such as Student or Lab Manager
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Role {
+    pub id: u64,
+    pub name: String,
+    pub permissions: Vec<Permission>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct NewRole {
+    pub name: String,
+    #[serde(default)]
+    pub permissions: Vec<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RoleList {
+    pub roles: Vec<Role>,
+}
+
+#[derive(Queryable, Insertable, Serialize, Deserialize, Debug)]
+#[table_name = "role_permissions"]
+pub struct RolePermission {
+    pub role_id: u64,
+    pub permission_id: u64,
+}
+
+#[derive(Queryable, Insertable, Serialize, Deserialize, Debug)]
+#[table_name = "user_roles"]
+pub struct UserRole {
+    pub user_id: u64,
+    pub role_id: u64,
+}
+
+pub enum RoleRequest {
+    GetRoles,
+    GetRole(u64),
+    CreateRole(NewRole),
+    DeleteRole(u64),
+    AddRolePermission(RolePermission),
+    RemoveRolePermission(RolePermission),
+    GetUserRoles(u64),
+    AddUserRole(UserRole),
+    RemoveUserRole(UserRole),
+}
+
+impl RoleRequest {
+    pub fn from_rouille(
+        request: &rouille::Request,
+    ) -> Result<RoleRequest, Error> {
+        router!(request,
+            (GET) (/) => {
+                Ok(RoleRequest::GetRoles)
+            },
+
+            (GET) (/{id: u64}) => {
+                Ok(RoleRequest::GetRole(id))
+            },
+
+            (POST) (/) => {
+                let request_body = request.data()
+                    .ok_or(Error::new(ErrorKind::Body))?;
+                let new_role: NewRole = serde_json::from_reader(request_body)?;
+                Ok(RoleRequest::CreateRole(new_role))
+            },
+
+            (DELETE) (/{id: u64}) => {
+                Ok(RoleRequest::DeleteRole(id))
+            },
+
+            (POST) (/{id: u64}/permissions/{permission_id: u64}) => {
+                Ok(RoleRequest::AddRolePermission(RolePermission {
+                    role_id: id,
+                    permission_id,
+                }))
+            },
+
+            (DELETE) (/{id: u64}/permissions/{permission_id: u64}) => {
+                Ok(RoleRequest::RemoveRolePermission(RolePermission {
+                    role_id: id,
+                    permission_id,
+                }))
+            },
+
+            (GET) (/users/{user_id: u64}) => {
+                Ok(RoleRequest::GetUserRoles(user_id))
+            },
+
+            (POST) (/{id: u64}/users/{user_id: u64}) => {
+                Ok(RoleRequest::AddUserRole(UserRole {
+                    user_id,
+                    role_id: id,
+                }))
+            },
+
+            (DELETE) (/{id: u64}/users/{user_id: u64}) => {
+                Ok(RoleRequest::RemoveUserRole(UserRole {
+                    user_id,
+                    role_id: id,
+                }))
+            },
+
+            _ => {
+                warn!("Could not create a role request for the given rouille request");
+                Err(Error::new(ErrorKind::NotFound))
+            }
+        ) //end router
+    }
+}
+
+pub enum RoleResponse {
+    OneRole(Role),
+    ManyRoles(RoleList),
+    NoResponse,
+}
+
+impl RoleResponse {
+    pub fn to_rouille(self) -> rouille::Response {
+        match self {
+            RoleResponse::OneRole(role) => rouille::Response::json(&role),
+            RoleResponse::ManyRoles(roles) => rouille::Response::json(&roles),
+            RoleResponse::NoResponse => rouille::Response::empty_204(),
+        }
+    }
+}
+
 #[derive(Queryable, Serialize, Deserialize, Debug)]
 pub struct UserAccess {
     pub access_id: u64,
@@ -129,10 +317,136 @@ pub struct PartialUserAccess {
     pub access_level: Option<Option<String>>,
 }
 
+/// The record a permission is being used on, so scoped grants can be checked
+#[derive(Default, Debug)]
+pub struct Resource {
+    /// The user responsible for the record, e.g. an inventory custodian
+    pub owner_id: Option<u64>,
+    /// The lab room the record is kept in
+    pub room: Option<String>,
+}
+
+/// Part of a scoped grant, limiting it to some records
+#[derive(PartialEq, Debug)]
+pub enum AccessScope {
+    Owned,
+    Room(String),
+    Department(String),
+}
+
+/// How far a grant reaches, read from `user_access.access_level`
+///
+/// A grant without an access level is unrestricted. Otherwise the access level
+/// is a comma separated list of `owned`, `room:<room>` and
+/// `department:<department>`, and the grant covers a record matching any of
+/// them. Departments are those of the record's owner.
+#[derive(PartialEq, Debug)]
+pub enum AccessLevel {
+    Unrestricted,
+    Scoped(Vec<AccessScope>),
+}
+
+impl AccessLevel {
+    pub fn from_access_level(
+        access_level: Option<&str>,
+    ) -> Result<AccessLevel, Error> {
+        let access_level = match access_level {
+            Some(access_level) => access_level,
+            None => return Ok(AccessLevel::Unrestricted),
+        };
+
+        let mut scopes = Vec::new();
+
+        for scope in access_level.split(',').map(|s| s.trim()) {
+            let scope = match scope.find(':') {
+                Some(i) => match (&scope[..i], &scope[i + 1..]) {
+                    ("room", room) if !room.is_empty() => {
+                        AccessScope::Room(room.to_owned())
+                    }
+                    ("department", department) if !department.is_empty() => {
+                        AccessScope::Department(department.to_owned())
+                    }
+                    _ => return Err(Error::new(ErrorKind::Body)),
+                },
+                None if scope == "owned" => AccessScope::Owned,
+                None => return Err(Error::new(ErrorKind::Body)),
+            };
+
+            scopes.push(scope);
+        }
+
+        Ok(AccessLevel::Scoped(scopes))
+    }
+
+    /// Whether the owner's department is needed to check this access level
+    pub fn needs_department(&self) -> bool {
+        match self {
+            AccessLevel::Unrestricted => false,
+            AccessLevel::Scoped(scopes) => scopes
+                .iter()
+                .any(|scope| matches!(scope, AccessScope::Department(_))),
+        }
+    }
+
+    /// The widest access level of several grants of the same permission
+    pub fn combine(access_levels: Vec<AccessLevel>) -> AccessLevel {
+        let mut combined = Vec::new();
+
+        for access_level in access_levels {
+            match access_level {
+                AccessLevel::Unrestricted => return AccessLevel::Unrestricted,
+                AccessLevel::Scoped(scopes) => combined.extend(scopes),
+            }
+        }
+
+        AccessLevel::Scoped(combined)
+    }
+
+    /// Whether a grant with this access level lets the user use it on the
+    /// resource
+    ///
+    /// Scoped grants never cover requests without a resource. Searches filter
+    /// their results by the access level instead.
+    pub fn allows(
+        &self,
+        user_id: u64,
+        resource: Option<&Resource>,
+        owner_department: Option<&str>,
+    ) -> bool {
+        let scopes = match self {
+            AccessLevel::Unrestricted => return true,
+            AccessLevel::Scoped(scopes) => scopes,
+        };
+
+        let resource = match resource {
+            Some(resource) => resource,
+            None => return false,
+        };
+
+        scopes.iter().any(|scope| match scope {
+            AccessScope::Owned => resource.owner_id == Some(user_id),
+            AccessScope::Room(room) => {
+                resource.room.as_deref() == Some(room)
+            }
+            AccessScope::Department(department) => {
+                owner_department == Some(department)
+            }
+        })
+    }
+}
+
+/// A user allowed to search with a permission, and how far their grants reach
+#[derive(Debug)]
+pub struct SearchAccess {
+    pub user_id: u64,
+    pub access_level: AccessLevel,
+}
+
 pub struct SearchUserAccess {
-    pub permission_id: Search<u64>,
-    pub user_id: Search<u64>,
+    pub permission_id: Search<Digits<u64>>,
+    pub user_id: Search<Digits<u64>>,
     pub access_level: NullableSearch<String>,
+    pub page: Page,
 }
 
 pub enum UserAccessRequest {
@@ -158,6 +472,7 @@ impl UserAccessRequest {
                 let mut access_id_search = Search::NoSearch;
                 let mut user_id_search = Search::NoSearch;
                 let mut permission_level_search = NullableSearch::NoSearch;
+                let mut page = Page::default();
 
                 for (field, query) in url_queries {
                     match field.as_ref() as &str {
@@ -167,6 +482,8 @@ impl UserAccessRequest {
                             Search::from_query(query.as_ref())?,
                         "permission_level" => permission_level_search =
                             NullableSearch::from_query(query.as_ref())?,
+                        "limit" | "offset" | "sort" =>
+                            page.add_query(field.as_ref(), query.as_ref())?,
                         _ => return Err(Error::new(ErrorKind::Url)),
                     }
                 }
@@ -175,6 +492,7 @@ impl UserAccessRequest {
                     permission_id: access_id_search,
                     user_id: user_id_search,
                     access_level: permission_level_search,
+                    page,
                 }))
             },
 
@@ -256,7 +574,92 @@ pub struct JoinedUserAccess {
     pub banner_id: u32,
 }
 
+/// One page of user accesses, out of `total` matching a search
+///
+/// `next` is the offset of the page after this one, if there is one.
 #[derive(Serialize, Deserialize)]
 pub struct JoinedUserAccessList {
     pub entries: Vec<JoinedUserAccess>,
+    pub total: u64,
+    pub next: Option<u64>,
+}
+
+#[test]
+fn missing_certifications_names_each_certification() {
+    let missing = MissingCertifications(vec![
+        "Lab Safety".to_owned(),
+        "Fire Safety".to_owned(),
+    ]);
+
+    assert_eq!(
+        missing.to_string(),
+        "Missing certifications: Lab Safety, Fire Safety"
+    );
+}
+
+#[test]
+fn access_level_from_access_level_works() {
+    assert_eq!(
+        AccessLevel::from_access_level(None).unwrap(),
+        AccessLevel::Unrestricted
+    );
+    assert_eq!(
+        AccessLevel::from_access_level(Some("owned, room:B-101")).unwrap(),
+        AccessLevel::Scoped(vec![
+            AccessScope::Owned,
+            AccessScope::Room("B-101".to_owned()),
+        ])
+    );
+    assert!(AccessLevel::from_access_level(Some("room:")).is_err());
+    assert!(AccessLevel::from_access_level(Some("everything")).is_err());
+}
+
+#[test]
+fn access_level_combine_works() {
+    assert_eq!(
+        AccessLevel::combine(vec![
+            AccessLevel::Scoped(vec![AccessScope::Owned]),
+            AccessLevel::Scoped(vec![AccessScope::Room("B-101".to_owned())]),
+        ]),
+        AccessLevel::Scoped(vec![
+            AccessScope::Owned,
+            AccessScope::Room("B-101".to_owned()),
+        ])
+    );
+    assert_eq!(
+        AccessLevel::combine(vec![
+            AccessLevel::Scoped(vec![AccessScope::Owned]),
+            AccessLevel::Unrestricted,
+        ]),
+        AccessLevel::Unrestricted
+    );
+}
+
+#[test]
+fn access_level_allows_matching_resources() {
+    let access_level = AccessLevel::Scoped(vec![
+        AccessScope::Owned,
+        AccessScope::Room("B-101".to_owned()),
+        AccessScope::Department("Chemistry".to_owned()),
+    ]);
+
+    let owned = Resource {
+        owner_id: Some(7),
+        room: None,
+    };
+    let in_room = Resource {
+        owner_id: Some(8),
+        room: Some("B-101".to_owned()),
+    };
+    let elsewhere = Resource {
+        owner_id: Some(8),
+        room: Some("B-102".to_owned()),
+    };
+
+    assert!(access_level.allows(7, Some(&owned), None));
+    assert!(access_level.allows(7, Some(&in_room), None));
+    assert!(access_level.allows(7, Some(&elsewhere), Some("Chemistry")));
+    assert!(!access_level.allows(7, Some(&elsewhere), Some("Physics")));
+    assert!(!access_level.allows(7, None, None));
+    assert!(AccessLevel::Unrestricted.allows(7, None, None));
 }
@@ -1,14 +1,13 @@
 use diesel;
+use diesel::dsl::exists;
 use diesel::mysql::types::Unsigned;
 use diesel::mysql::Mysql;
 use diesel::mysql::MysqlConnection;
 use diesel::sql_types;
+use diesel::Connection;
 use diesel::ExpressionMethods;
 use diesel::QueryDsl;
 use diesel::RunQueryDsl;
-use diesel::TextExpressionMethods;
-
-use google_signin;
 
 use log::debug;
 use log::trace;
@@ -16,43 +15,55 @@ use log::warn;
 
 use crate::errors::{Error, ErrorKind};
 
-use crate::search::{NullableSearch, Search};
+use crate::search::{Page, Search};
+
+use super::identity::IdentityVerifier;
+
+use crate::audit::requests::record_change;
+
+use chrono::offset::Local;
 
 use super::models::{
-    Permission, PermissionList, PermissionRequest, PermissionResponse, JoinedUserAccess,
-    JoinedUserAccessList, NewPermission, NewUserAccess, PartialPermission,
-    PartialUserAccess, SearchUserAccess, UserAccess, UserAccessRequest,
-    UserAccessResponse,
+    AccessLevel, JoinedUserAccess, JoinedUserAccessList, MissingCertifications,
+    NewPermission, NewRawRole, NewRole, NewUserAccess, PartialPermission,
+    PartialUserAccess, Permission, PermissionCertification, PermissionList,
+    PermissionRequest, PermissionResponse, RawRole, Resource, Role, RoleList,
+    RolePermission, RoleRequest, RoleResponse, SearchAccess, SearchUserAccess,
+    UserAccess, UserAccessRequest, UserAccessResponse, UserRole,
 };
 
 use crate::users::models::{NewUser, SearchUser};
 use crate::users::requests::create_user;
 use crate::users::requests::search_users;
 
+use crate::certifications::models::{Certification, CertificationList};
+
 use super::schema::permission as permission_schema;
+use super::schema::permission_certifications as permission_certifications_schema;
+use super::schema::role_permissions as role_permissions_schema;
+use super::schema::roles as roles_schema;
 use super::schema::user_access as user_access_schema;
+use super::schema::user_roles as user_roles_schema;
+use crate::certifications::schema::certifications as certifications_schema;
+use crate::certifications::schema::user_certifications as user_certifications_schema;
 use crate::users::schema::users as users_schema;
 
 pub fn validate_token(
     id_token: &str,
+    identity_verifier: &dyn IdentityVerifier,
     database_connection: &MysqlConnection,
 ) -> Result<u64, Error> {
-    let mut client = google_signin::Client::new();
-    client.audiences.push(String::from(
-        "918184954544-jm1aufr31fi6sdjs1140p7p3rouaka14.apps.googleusercontent.com",
-    ));
-
-    let id_info = client.verify(id_token)?;
+    let claims = identity_verifier.verify(id_token)?;
 
-    trace!("Validated token: {:?}", id_info);
-
-    if let Some(email) = id_info.email {
+    if let Some(email) = claims.email {
         let mut found_users = search_users(
             SearchUser {
                 first_name: Search::NoSearch,
                 last_name: Search::NoSearch,
                 banner_id: Search::NoSearch,
                 email: Search::Exact(email),
+                include_deleted: false,
+                page: Page::default(),
             },
             database_connection,
         )?;
@@ -68,52 +79,184 @@ pub fn validate_token(
     }
 }
 
+/// Check that the requesting user may use a permission, on the given resource
+/// if there is one
+///
+/// Scoped grants only count when the resource falls within their scope, so
+/// requests without a resource need an unrestricted grant.
 pub fn check_to_run(
     requesting_user_id: Option<u64>,
     permission_name: &str,
+    resource: Option<&Resource>,
     database_connection: &MysqlConnection,
 ) -> Result<(), Error> {
     trace!(
-        "Checking if user {:?} has {}",
+        "Checking if user {:?} has {} on {:?}",
         requesting_user_id,
-        permission_name
+        permission_name,
+        resource
     );
     match requesting_user_id {
         Some(user_id) => {
-            match check_user_access(
+            let access_levels = get_access_levels(
                 user_id,
-                String::from(permission_name),
+                permission_name,
                 database_connection,
-            ) {
-                Ok(access) => {
-                    if access {
-                        debug!("Access granted!");
-                        Ok(())
-                    } else {
-                        Err(Error::new(ErrorKind::AccessDenied))
-                    }
+            )?;
+
+            let owner_department = match resource.and_then(|r| r.owner_id) {
+                Some(owner_id)
+                    if access_levels.iter().any(|a| a.needs_department()) =>
+                {
+                    users_schema::table
+                        .filter(users_schema::id.eq(owner_id))
+                        .select(users_schema::department)
+                        .first::<Option<String>>(database_connection)?
                 }
-                Err(e) => Err(e),
+                _ => None,
+            };
+
+            let owner_department = owner_department.as_deref();
+            let access = access_levels.iter().any(|access_level| {
+                access_level.allows(user_id, resource, owner_department)
+            });
+
+            if access {
+                check_certifications(
+                    user_id,
+                    permission_name,
+                    database_connection,
+                )
+            } else {
+                Err(Error::new(ErrorKind::AccessDenied))
             }
         }
         None => Err(Error::new(ErrorKind::AccessDenied)),
     }
 }
 
+/// Check that the requesting user may search with a permission
+///
+/// Unlike `check_to_run`, scoped grants are enough here, so the search has to
+/// be filtered down to the records the returned access level covers.
+pub fn check_to_search(
+    requesting_user_id: Option<u64>,
+    permission_name: &str,
+    database_connection: &MysqlConnection,
+) -> Result<SearchAccess, Error> {
+    trace!(
+        "Checking if user {:?} can search with {}",
+        requesting_user_id,
+        permission_name
+    );
+    let user_id = requesting_user_id
+        .ok_or_else(|| Error::new(ErrorKind::AccessDenied))?;
+
+    let access_levels =
+        get_access_levels(user_id, permission_name, database_connection)?;
+
+    if access_levels.is_empty() {
+        return Err(Error::new(ErrorKind::AccessDenied));
+    }
+
+    check_certifications(user_id, permission_name, database_connection)?;
+
+    Ok(SearchAccess {
+        user_id,
+        access_level: AccessLevel::combine(access_levels),
+    })
+}
+
+/// Check that a user holds the certifications a permission requires
+fn check_certifications(
+    user_id: u64,
+    permission_name: &str,
+    database_connection: &MysqlConnection,
+) -> Result<(), Error> {
+    let missing_certifications =
+        missing_certifications(user_id, permission_name, database_connection)?;
+
+    if missing_certifications.is_empty() {
+        debug!("Access granted!");
+        Ok(())
+    } else {
+        debug!(
+            "Access denied, missing certifications: {:?}",
+            missing_certifications
+        );
+        Err(Error::with_source(
+            ErrorKind::MissingCertification,
+            Box::new(MissingCertifications(missing_certifications)),
+        ))
+    }
+}
+
+/// Get the access level of every grant a user has of a permission
+///
+/// Grants through roles are unrestricted. Access levels that can not be read
+/// are skipped, so they grant nothing.
+pub(crate) fn get_access_levels(
+    user_id: u64,
+    permission_name: &str,
+    database_connection: &MysqlConnection,
+) -> Result<Vec<AccessLevel>, Error> {
+    let mut access_levels: Vec<AccessLevel> = user_access_schema::table
+        .inner_join(permission_schema::table)
+        .select(user_access_schema::access_level)
+        .filter(user_access_schema::user_id.eq(user_id))
+        .filter(permission_schema::permission_name.eq(permission_name))
+        .load::<Option<String>>(database_connection)?
+        .into_iter()
+        .filter_map(|access_level| {
+            match AccessLevel::from_access_level(access_level.as_deref()) {
+                Ok(access_level) => Some(access_level),
+                Err(_) => {
+                    warn!(
+                        "Ignoring unreadable access level {:?} of user {}",
+                        access_level, user_id
+                    );
+                    None
+                }
+            }
+        })
+        .collect();
+
+    let has_role_permission = diesel::select(exists(
+        permission_schema::table
+            .inner_join(role_permissions_schema::table)
+            .filter(
+                role_permissions_schema::role_id.eq_any(user_role_ids(user_id)),
+            )
+            .filter(permission_schema::permission_name.eq(permission_name)),
+    ))
+    .get_result::<bool>(database_connection)?;
+
+    if has_role_permission {
+        access_levels.push(AccessLevel::Unrestricted);
+    }
+
+    Ok(access_levels)
+}
+
 pub fn handle_permission(
     request: PermissionRequest,
     requesting_user: Option<u64>,
+    identity_verifier: &dyn IdentityVerifier,
     database_connection: &MysqlConnection,
 ) -> Result<PermissionResponse, Error> {
     match request {
-        PermissionRequest::FirstPermission(id_token) => {
-            first_permission(requesting_user, &id_token, database_connection)
-                .map(|_| PermissionResponse::NoResponse)
-        }
+        PermissionRequest::FirstPermission(id_token) => first_permission(
+            requesting_user,
+            &id_token,
+            identity_verifier,
+            database_connection,
+        )
+        .map(|_| PermissionResponse::NoResponse),
         PermissionRequest::GetPermission(id) => {
             match check_to_run(
                 requesting_user,
                 "GetPermission",
+                None,
                 database_connection,
             ) {
                 Ok(()) => get_permission(id, database_connection)
@@ -125,9 +268,24 @@ pub fn handle_permission(
             match check_to_run(
                 requesting_user,
                 "CreatePermission",
+                None,
                 database_connection,
             ) {
-                Ok(()) => create_permission(permission, database_connection)
+                Ok(()) => database_connection
+                    .transaction::<_, Error, _>(|| {
+                        let created =
+                            create_permission(permission, database_connection)?;
+                        record_change(
+                            requesting_user,
+                            "CreatePermission",
+                            "permission",
+                            created.id,
+                            None,
+                            Some(&created),
+                            database_connection,
+                        )?;
+                        Ok(created)
+                    })
                     .map(|a| PermissionResponse::OnePermission(a)),
                 Err(e) => Err(e),
             }
@@ -136,20 +294,111 @@ pub fn handle_permission(
             match check_to_run(
                 requesting_user,
                 "UpdatePermission",
+                None,
                 database_connection,
             ) {
-                Ok(()) => update_permission(id, permission, database_connection)
+                Ok(()) => database_connection
+                    .transaction::<_, Error, _>(|| {
+                        let before = get_permission(id, database_connection)?;
+                        update_permission(id, permission, database_connection)?;
+                        let after = get_permission(id, database_connection)?;
+                        record_change(
+                            requesting_user,
+                            "UpdatePermission",
+                            "permission",
+                            id,
+                            Some(&before),
+                            Some(&after),
+                            database_connection,
+                        )
+                    })
                     .map(|_| PermissionResponse::NoResponse),
                 Err(e) => Err(e),
             }
         }
+        PermissionRequest::GetRequiredCertifications(id) => {
+            check_to_run(
+                requesting_user,
+                "GetPermission",
+                None,
+                database_connection,
+            )?;
+            get_required_certifications(id, database_connection)
+                .map(|c| PermissionResponse::ManyCertifications(c))
+        }
+        PermissionRequest::AddRequiredCertification(permission_certification) => {
+            check_to_run(
+                requesting_user,
+                "UpdatePermission",
+                None,
+                database_connection,
+            )?;
+            database_connection
+                .transaction::<_, Error, _>(|| {
+                    record_change(
+                        requesting_user,
+                        "UpdatePermission",
+                        "permission_certifications",
+                        permission_certification.permission_id,
+                        None,
+                        Some(&permission_certification),
+                        database_connection,
+                    )?;
+                    add_required_certification(
+                        permission_certification,
+                        database_connection,
+                    )
+                })
+                .map(|_| PermissionResponse::NoResponse)
+        }
+        PermissionRequest::RemoveRequiredCertification(
+            permission_certification,
+        ) => {
+            check_to_run(
+                requesting_user,
+                "UpdatePermission",
+                None,
+                database_connection,
+            )?;
+            database_connection
+                .transaction::<_, Error, _>(|| {
+                    record_change(
+                        requesting_user,
+                        "UpdatePermission",
+                        "permission_certifications",
+                        permission_certification.permission_id,
+                        Some(&permission_certification),
+                        None,
+                        database_connection,
+                    )?;
+                    remove_required_certification(
+                        permission_certification,
+                        database_connection,
+                    )
+                })
+                .map(|_| PermissionResponse::NoResponse)
+        }
         PermissionRequest::DeletePermission(id) => {
             match check_to_run(
                 requesting_user,
                 "DeletePermission",
+                None,
                 database_connection,
             ) {
-                Ok(()) => delete_permission(id, database_connection)
+                Ok(()) => database_connection
+                    .transaction::<_, Error, _>(|| {
+                        let before = get_permission(id, database_connection)?;
+                        delete_permission(id, database_connection)?;
+                        record_change(
+                            requesting_user,
+                            "DeletePermission",
+                            "permission",
+                            id,
+                            Some(&before),
+                            None,
+                            database_connection,
+                        )
+                    })
                     .map(|_| PermissionResponse::NoResponse),
                 Err(e) => Err(e),
             }
@@ -160,6 +409,7 @@ pub fn handle_permission(
 pub(crate) fn first_permission(
     requesting_user: Option<u64>,
     id_token: &str,
+    identity_verifier: &dyn IdentityVerifier,
     database_connection: &MysqlConnection,
 ) -> Result<(), Error> {
     trace!(
@@ -168,79 +418,91 @@ pub(crate) fn first_permission(
         id_token
     );
 
-    let search = SearchUserAccess {
-        permission_id: Search::NoSearch,
-        user_id: Search::NoSearch,
-        access_level: NullableSearch::NoSearch,
+    // Verify the token before the transaction, so that it is not held open
+    // while waiting on the identity provider
+    let claims = match requesting_user {
+        Some(_) => None,
+        None => {
+            let claims = identity_verifier.verify(id_token)?;
+            trace!("Token verified: {:?}", claims);
+            Some(claims)
+        }
     };
 
-    let non_root_accesses = search_user_access(search, &database_connection)?
-        .entries
-        .into_iter()
-        .filter(|access| access.permission_id != 1)
-        .count();
-
-    trace!("Found {} non-root accesses", non_root_accesses);
-
-    if non_root_accesses == 0 {
-        let user_id = if let Some(user_id) = requesting_user {
-            user_id
-        } else {
-            let mut client = google_signin::Client::new();
-            client.audiences.push(String::from(
-                    "918184954544-jm1aufr31fi6sdjs1140p7p3rouaka14.apps.googleusercontent.com",
-                    ));
-
-            let info = client.verify(id_token)?;
-
-            trace!("Token verified: {:?}", info);
-
-            let email = match info.email {
-                Some(email) => email,
-                None => {
-                    return Err(Error::new(ErrorKind::AccessDenied));
-                }
-            };
-
-            let new_user = NewUser {
-                first_name: info
-                    .given_name
-                    .unwrap_or("Not supplied by Google".to_owned()),
-                last_name: info
-                    .family_name
-                    .unwrap_or("Not supplied by Google".to_owned()),
-                email: email,
-                banner_id: 0,
-                accesses: Vec::new(),
+    database_connection.transaction(|| {
+        let non_root_accesses = user_access_schema::table
+            .filter(user_access_schema::permission_id.ne(1))
+            .count()
+            .get_result::<i64>(database_connection)?;
+
+        let assigned_roles = user_roles_schema::table
+            .count()
+            .get_result::<i64>(database_connection)?;
+
+        trace!(
+            "Found {} non-root accesses and {} assigned roles",
+            non_root_accesses,
+            assigned_roles
+        );
+
+        if non_root_accesses == 0 && assigned_roles == 0 {
+            let user_id = if let Some(user_id) = requesting_user {
+                user_id
+            } else if let Some(claims) = claims {
+                let email = match claims.email {
+                    Some(email) => email,
+                    None => {
+                        return Err(Error::new(ErrorKind::AccessDenied));
+                    }
+                };
+
+                let new_user = NewUser {
+                    first_name: claims
+                        .given_name
+                        .unwrap_or("Not supplied by provider".to_owned()),
+                    last_name: claims
+                        .family_name
+                        .unwrap_or("Not supplied by provider".to_owned()),
+                    email: email,
+                    department: None,
+                    banner_id: 0,
+                    accesses: Vec::new(),
+                    roles: Vec::new(),
+                };
+
+                trace!("New user: {:#?}", new_user);
+
+                create_user(new_user, database_connection)?.id
+            } else {
+                return Err(Error::new(ErrorKind::AccessDenied));
             };
 
-            trace!("New user: {:#?}", new_user);
-
-            create_user(new_user, database_connection)?.id
-        };
+            let admin_role_id = roles_schema::table
+                .filter(roles_schema::name.eq("Admin"))
+                .select(roles_schema::id)
+                .first::<u64>(database_connection)?;
 
-        let permissions = permission_schema::table
-            .filter(permission_schema::permission_name.ne("RootAccess"))
-            .load::<Permission>(database_connection)?;
-
-        let new_user_accesses: Vec<_> = permissions
-            .into_iter()
-            .map(|permission| NewUserAccess {
-                permission_id: permission.id,
-                user_id: user_id,
-                access_level: None,
-            })
-            .collect();
+            let user_role = UserRole {
+                user_id,
+                role_id: admin_role_id,
+            };
 
-        diesel::insert_into(user_access_schema::table)
-            .values(new_user_accesses)
-            .execute(database_connection)?;
+            record_change(
+                Some(user_id),
+                "CreateUserAccess",
+                "user_roles",
+                user_id,
+                None,
+                Some(&user_role),
+                database_connection,
+            )?;
 
-        Ok(())
-    } else {
-        warn!("First permission request attempted, but permission has already been setup.");
-        Err(Error::new(ErrorKind::AccessDenied))
-    }
+            add_user_role(user_role, database_connection)
+        } else {
+            warn!("First permission request attempted, but permission has already been setup.");
+            Err(Error::new(ErrorKind::AccessDenied))
+        }
+    })
 }
 
 pub(crate) fn get_permission(
@@ -311,6 +573,7 @@ pub fn handle_user_access(
             match check_to_run(
                 requesting_user,
                 "GetUserAccess",
+                None,
                 database_connection,
             ) {
                 Ok(()) => search_user_access(user_access, database_connection)
@@ -326,6 +589,7 @@ pub fn handle_user_access(
             match check_to_run(
                 requesting_user,
                 "GetUserAccess",
+                None,
                 database_connection,
             ) {
                 Ok(()) => get_user_access(permission_id, database_connection)
@@ -341,9 +605,26 @@ pub fn handle_user_access(
             match check_to_run(
                 requesting_user,
                 "CreateUserAccess",
+                None,
                 database_connection,
             ) {
-                Ok(()) => create_user_access(user_access, database_connection)
+                Ok(()) => database_connection
+                    .transaction::<_, Error, _>(|| {
+                        let created = create_user_access(
+                            user_access,
+                            database_connection,
+                        )?;
+                        record_change(
+                            requesting_user,
+                            "CreateUserAccess",
+                            "user_access",
+                            created.access_id,
+                            None,
+                            Some(&created),
+                            database_connection,
+                        )?;
+                        Ok(created)
+                    })
                     .map(|a| UserAccessResponse::OneUserAccess(a)),
                 Err(e) => Err(e),
             }
@@ -352,12 +633,29 @@ pub fn handle_user_access(
             match check_to_run(
                 requesting_user,
                 "UpdateUserAccess",
+                None,
                 database_connection,
             ) {
-                Ok(()) => {
-                    update_user_access(id, user_access, database_connection)
-                        .map(|_| UserAccessResponse::NoResponse)
-                }
+                Ok(()) => database_connection
+                    .transaction::<_, Error, _>(|| {
+                        let before = get_user_access(id, database_connection)?;
+                        update_user_access(
+                            id,
+                            user_access,
+                            database_connection,
+                        )?;
+                        let after = get_user_access(id, database_connection)?;
+                        record_change(
+                            requesting_user,
+                            "UpdateUserAccess",
+                            "user_access",
+                            before.access_id,
+                            Some(&before),
+                            Some(&after),
+                            database_connection,
+                        )
+                    })
+                    .map(|_| UserAccessResponse::NoResponse),
                 Err(e) => Err(e),
             }
         }
@@ -365,9 +663,23 @@ pub fn handle_user_access(
             match check_to_run(
                 requesting_user,
                 "DeleteUserAccess",
+                None,
                 database_connection,
             ) {
-                Ok(()) => delete_user_access(id, database_connection)
+                Ok(()) => database_connection
+                    .transaction::<_, Error, _>(|| {
+                        let before = get_user_access(id, database_connection)?;
+                        delete_user_access(id, database_connection)?;
+                        record_change(
+                            requesting_user,
+                            "DeleteUserAccess",
+                            "user_access",
+                            before.access_id,
+                            Some(&before),
+                            None,
+                            database_connection,
+                        )
+                    })
                     .map(|_| UserAccessResponse::NoResponse),
                 Err(e) => Err(e),
             }
@@ -379,7 +691,11 @@ pub(crate) fn search_user_access(
     user_access_search: SearchUserAccess,
     database_connection: &MysqlConnection,
 ) -> Result<JoinedUserAccessList, Error> {
-    let mut user_access_query = user_access_schema::table
+    let total = user_access_query(&user_access_search)
+        .count()
+        .get_result::<i64>(database_connection)? as u64;
+
+    let mut user_access_query = user_access_query(&user_access_search)
         .inner_join(permission_schema::table)
         .inner_join(users_schema::table)
         .select((
@@ -389,69 +705,71 @@ pub(crate) fn search_user_access(
             users_schema::first_name,
             users_schema::last_name,
             users_schema::banner_id,
-        ))
-        .into_boxed::<Mysql>();
-
-    match user_access_search.permission_id {
-        Search::Partial(s) => {
-            user_access_query =
-                user_access_query.filter(user_access_schema::access_id.eq(s))
-        }
-
-        Search::Exact(s) => {
-            user_access_query =
-                user_access_query.filter(user_access_schema::access_id.eq(s))
-        }
-
-        Search::NoSearch => {}
-    }
-
-    match user_access_search.user_id {
-        Search::Partial(s) => {
-            user_access_query =
-                user_access_query.filter(user_access_schema::user_id.eq(s))
-        }
-
-        Search::Exact(s) => {
-            user_access_query =
-                user_access_query.filter(user_access_schema::user_id.eq(s))
-        }
-
-        Search::NoSearch => {}
+        ));
+
+    if let Some(sort) = &user_access_search.page.sort {
+        user_access_query = match sort.field.as_ref() {
+            "access_id" => sort
+                .order
+                .order_by(user_access_query, user_access_schema::access_id),
+            "permission_id" => sort
+                .order
+                .order_by(user_access_query, user_access_schema::permission_id),
+            "user_id" => sort
+                .order
+                .order_by(user_access_query, user_access_schema::user_id),
+            "access_level" => sort
+                .order
+                .order_by(user_access_query, user_access_schema::access_level),
+            "first_name" => sort
+                .order
+                .order_by(user_access_query, users_schema::first_name),
+            "last_name" => sort
+                .order
+                .order_by(user_access_query, users_schema::last_name),
+            "banner_id" => sort
+                .order
+                .order_by(user_access_query, users_schema::banner_id),
+            _ => return Err(Error::new(ErrorKind::Url)),
+        };
     }
 
-    match user_access_search.access_level {
-        NullableSearch::Partial(s) => {
-            user_access_query = user_access_query.filter(
-                user_access_schema::access_level.like(format!("%{}%", s)),
-            )
-        }
+    let found_access_entries = user_access_search
+        .page
+        .paginate(
+            user_access_query
+                .then_order_by(user_access_schema::permission_id.asc()),
+        )
+        .load::<JoinedUserAccess>(database_connection)?;
+    let joined_list = JoinedUserAccessList {
+        entries: found_access_entries,
+        total,
+        next: user_access_search.page.next(total),
+    };
 
-        NullableSearch::Exact(s) => {
-            user_access_query = user_access_query
-                .filter(user_access_schema::access_level.eq(s))
-        }
+    Ok(joined_list)
+}
 
-        NullableSearch::Some => {
-            user_access_query = user_access_query
-                .filter(user_access_schema::access_level.is_not_null());
-        }
+/// Build a query for the user accesses matching a search, in no particular
+/// order
+fn user_access_query(
+    user_access_search: &SearchUserAccess,
+) -> user_access_schema::BoxedQuery<'_, Mysql> {
+    let mut user_access_query = user_access_schema::table.into_boxed::<Mysql>();
 
-        NullableSearch::None => {
-            user_access_query = user_access_query
-                .filter(user_access_schema::access_level.is_null());
-        }
+    user_access_query = user_access_search
+        .permission_id
+        .filter(user_access_query, user_access_schema::access_id);
 
-        NullableSearch::NoSearch => {}
-    }
+    user_access_query = user_access_search
+        .user_id
+        .filter(user_access_query, user_access_schema::user_id);
 
-    let found_access_entries =
-        user_access_query.load::<JoinedUserAccess>(database_connection)?;
-    let joined_list = JoinedUserAccessList {
-        entries: found_access_entries,
-    };
+    user_access_query = user_access_search
+        .access_level
+        .filter_text(user_access_query, user_access_schema::access_level);
 
-    Ok(joined_list)
+    user_access_query
 }
 
 pub(crate) fn get_current_user_access(
@@ -459,11 +777,7 @@ pub(crate) fn get_current_user_access(
     database_connection: &MysqlConnection,
 ) -> Result<PermissionList, Error> {
     if let Some(user_id) = requesting_user {
-        let permissions = permission_schema::table
-            .inner_join(user_access_schema::table)
-            .select((permission_schema::id, permission_schema::permission_name))
-            .filter(user_access_schema::user_id.eq(user_id))
-            .load::<Permission>(database_connection)?;
+        let permissions = effective_permissions(user_id, database_connection)?;
 
         Ok(PermissionList { permissions })
     } else {
@@ -490,18 +804,516 @@ pub(crate) fn check_user_access(
     permission_name: String,
     database_connection: &MysqlConnection,
 ) -> Result<bool, Error> {
-    let found_user_accesses = user_access_schema::table
-        .inner_join(permission_schema::table)
-        .select((user_access_schema::user_id, permission_schema::permission_name))
+    let has_user_access = diesel::select(exists(
+        user_access_schema::table
+            .inner_join(permission_schema::table)
+            .filter(user_access_schema::user_id.eq(user_id))
+            .filter(permission_schema::permission_name.eq(&permission_name)),
+    ))
+    .get_result::<bool>(database_connection)?;
+
+    if has_user_access {
+        return Ok(true);
+    }
+
+    let has_role_permission = diesel::select(exists(
+        permission_schema::table
+            .inner_join(role_permissions_schema::table)
+            .filter(
+                role_permissions_schema::role_id.eq_any(user_role_ids(user_id)),
+            )
+            .filter(permission_schema::permission_name.eq(&permission_name)),
+    ))
+    .get_result::<bool>(database_connection)?;
+
+    Ok(has_role_permission)
+}
+
+/// Get every permission a user has, whether granted directly or through one
+/// of their roles
+pub(crate) fn effective_permissions(
+    user_id: u64,
+    database_connection: &MysqlConnection,
+) -> Result<Vec<Permission>, Error> {
+    let direct_permissions = permission_schema::table
+        .inner_join(user_access_schema::table)
+        .select((permission_schema::id, permission_schema::permission_name))
         .filter(user_access_schema::user_id.eq(user_id))
-        .filter(permission_schema::permission_name.eq(permission_name))
+        .load::<Permission>(database_connection)?;
+
+    let role_permissions = permission_schema::table
+        .inner_join(role_permissions_schema::table)
+        .select((permission_schema::id, permission_schema::permission_name))
+        .filter(role_permissions_schema::role_id.eq_any(user_role_ids(user_id)))
+        .load::<Permission>(database_connection)?;
+
+    Ok(merge_permissions(direct_permissions, role_permissions))
+}
+
+/// Combine permissions from several sources, keeping one of each
+fn merge_permissions(
+    direct_permissions: Vec<Permission>,
+    role_permissions: Vec<Permission>,
+) -> Vec<Permission> {
+    let mut permissions: Vec<Permission> = direct_permissions
+        .into_iter()
+        .chain(role_permissions)
+        .collect();
+
+    permissions.sort_by_key(|permission| permission.id);
+    permissions.dedup_by_key(|permission| permission.id);
+
+    permissions
+}
+
+fn user_role_ids(
+    user_id: u64,
+) -> diesel::dsl::Select<
+    diesel::dsl::Filter<
+        user_roles_schema::table,
+        diesel::dsl::Eq<user_roles_schema::user_id, u64>,
+    >,
+    user_roles_schema::role_id,
+> {
+    user_roles_schema::table
+        .filter(user_roles_schema::user_id.eq(user_id))
+        .select(user_roles_schema::role_id)
+}
+
+pub fn handle_role(
+    request: RoleRequest,
+    requesting_user: Option<u64>,
+    database_connection: &MysqlConnection,
+) -> Result<RoleResponse, Error> {
+    match request {
+        RoleRequest::GetRoles => {
+            check_to_run(
+                requesting_user,
+                "GetRoles",
+                None,
+                database_connection,
+            )?;
+            get_roles(database_connection)
+                .map(|r| RoleResponse::ManyRoles(r))
+        }
+        RoleRequest::GetRole(id) => {
+            check_to_run(
+                requesting_user,
+                "GetRoles",
+                None,
+                database_connection,
+            )?;
+            get_role(id, database_connection)
+                .map(|r| RoleResponse::OneRole(r))
+        }
+        RoleRequest::CreateRole(role) => {
+            check_to_run(
+                requesting_user,
+                "CreateRoles",
+                None,
+                database_connection,
+            )?;
+            database_connection
+                .transaction::<_, Error, _>(|| {
+                    let created = create_role(role, database_connection)?;
+                    record_change(
+                        requesting_user,
+                        "CreateRoles",
+                        "roles",
+                        created.id,
+                        None,
+                        Some(&created),
+                        database_connection,
+                    )?;
+                    Ok(created)
+                })
+                .map(|r| RoleResponse::OneRole(r))
+        }
+        RoleRequest::DeleteRole(id) => {
+            check_to_run(
+                requesting_user,
+                "DeleteRoles",
+                None,
+                database_connection,
+            )?;
+            database_connection
+                .transaction::<_, Error, _>(|| {
+                    let before = get_role(id, database_connection)?;
+                    delete_role(id, database_connection)?;
+                    record_change(
+                        requesting_user,
+                        "DeleteRoles",
+                        "roles",
+                        id,
+                        Some(&before),
+                        None,
+                        database_connection,
+                    )
+                })
+                .map(|_| RoleResponse::NoResponse)
+        }
+        RoleRequest::AddRolePermission(role_permission) => {
+            check_to_run(
+                requesting_user,
+                "UpdateRoles",
+                None,
+                database_connection,
+            )?;
+            database_connection
+                .transaction::<_, Error, _>(|| {
+                    record_change(
+                        requesting_user,
+                        "UpdateRoles",
+                        "role_permissions",
+                        role_permission.role_id,
+                        None,
+                        Some(&role_permission),
+                        database_connection,
+                    )?;
+                    add_role_permission(role_permission, database_connection)
+                })
+                .map(|_| RoleResponse::NoResponse)
+        }
+        RoleRequest::RemoveRolePermission(role_permission) => {
+            check_to_run(
+                requesting_user,
+                "UpdateRoles",
+                None,
+                database_connection,
+            )?;
+            database_connection
+                .transaction::<_, Error, _>(|| {
+                    record_change(
+                        requesting_user,
+                        "UpdateRoles",
+                        "role_permissions",
+                        role_permission.role_id,
+                        Some(&role_permission),
+                        None,
+                        database_connection,
+                    )?;
+                    remove_role_permission(role_permission, database_connection)
+                })
+                .map(|_| RoleResponse::NoResponse)
+        }
+        RoleRequest::GetUserRoles(user_id) => {
+            if requesting_user != Some(user_id) {
+                check_to_run(
+                    requesting_user,
+                    "GetUserAccess",
+                    None,
+                    database_connection,
+                )?;
+            }
+            get_user_roles(user_id, database_connection)
+                .map(|r| RoleResponse::ManyRoles(r))
+        }
+        RoleRequest::AddUserRole(user_role) => {
+            check_to_run(
+                requesting_user,
+                "CreateUserAccess",
+                None,
+                database_connection,
+            )?;
+            database_connection
+                .transaction::<_, Error, _>(|| {
+                    record_change(
+                        requesting_user,
+                        "CreateUserAccess",
+                        "user_roles",
+                        user_role.user_id,
+                        None,
+                        Some(&user_role),
+                        database_connection,
+                    )?;
+                    add_user_role(user_role, database_connection)
+                })
+                .map(|_| RoleResponse::NoResponse)
+        }
+        RoleRequest::RemoveUserRole(user_role) => {
+            check_to_run(
+                requesting_user,
+                "DeleteUserAccess",
+                None,
+                database_connection,
+            )?;
+            database_connection
+                .transaction::<_, Error, _>(|| {
+                    record_change(
+                        requesting_user,
+                        "DeleteUserAccess",
+                        "user_roles",
+                        user_role.user_id,
+                        Some(&user_role),
+                        None,
+                        database_connection,
+                    )?;
+                    remove_user_role(user_role, database_connection)
+                })
+                .map(|_| RoleResponse::NoResponse)
+        }
+    }
+}
+
+pub(crate) fn get_roles(
+    database_connection: &MysqlConnection,
+) -> Result<RoleList, Error> {
+    let raw_roles = roles_schema::table.load::<RawRole>(database_connection)?;
+
+    let roles = with_permissions(raw_roles, database_connection)?;
+
+    Ok(RoleList { roles })
+}
+
+pub(crate) fn get_role(
+    id: u64,
+    database_connection: &MysqlConnection,
+) -> Result<Role, Error> {
+    let raw_roles = roles_schema::table
+        .filter(roles_schema::id.eq(id))
+        .load::<RawRole>(database_connection)?;
+
+    match with_permissions(raw_roles, database_connection)?.pop() {
+        Some(role) => Ok(role),
+        None => Err(Error::new(ErrorKind::NotFound)),
+    }
+}
+
+pub(crate) fn get_user_roles(
+    user_id: u64,
+    database_connection: &MysqlConnection,
+) -> Result<RoleList, Error> {
+    let raw_roles = roles_schema::table
+        .filter(roles_schema::id.eq_any(user_role_ids(user_id)))
+        .load::<RawRole>(database_connection)?;
+
+    let roles = with_permissions(raw_roles, database_connection)?;
+
+    Ok(RoleList { roles })
+}
+
+fn with_permissions(
+    raw_roles: Vec<RawRole>,
+    database_connection: &MysqlConnection,
+) -> Result<Vec<Role>, Error> {
+    let role_ids: Vec<u64> = raw_roles.iter().map(|role| role.id).collect();
+
+    let role_permissions = role_permissions_schema::table
+        .inner_join(permission_schema::table)
+        .filter(role_permissions_schema::role_id.eq_any(role_ids))
+        .select((
+            role_permissions_schema::role_id,
+            (permission_schema::id, permission_schema::permission_name),
+        ))
+        .load::<(u64, Permission)>(database_connection)?;
+
+    Ok(raw_roles
+        .into_iter()
+        .map(|raw_role| Role {
+            permissions: role_permissions
+                .iter()
+                .filter(|(role_id, _)| *role_id == raw_role.id)
+                .map(|(_, permission)| permission.clone())
+                .collect(),
+            id: raw_role.id,
+            name: raw_role.name,
+        })
+        .collect())
+}
+
+pub(crate) fn create_role(
+    role: NewRole,
+    database_connection: &MysqlConnection,
+) -> Result<Role, Error> {
+    database_connection.transaction(|| {
+        diesel::insert_into(roles_schema::table)
+            .values(NewRawRole { name: role.name })
+            .execute(database_connection)?;
+
+        let mut inserted_roles = roles_schema::table
+            .filter(diesel::dsl::sql("id = LAST_INSERT_ID()"))
+            .load::<RawRole>(database_connection)?;
+
+        if let Some(inserted_role) = inserted_roles.pop() {
+            let new_role_permissions: Vec<_> = role
+                .permissions
+                .into_iter()
+                .map(|permission_id| RolePermission {
+                    role_id: inserted_role.id,
+                    permission_id,
+                })
+                .collect();
+
+            diesel::insert_into(role_permissions_schema::table)
+                .values(new_role_permissions)
+                .execute(database_connection)?;
+
+            get_role(inserted_role.id, database_connection)
+        } else {
+            Err(Error::new(ErrorKind::Database))
+        }
+    })
+}
+
+pub(crate) fn delete_role(
+    id: u64,
+    database_connection: &MysqlConnection,
+) -> Result<(), Error> {
+    diesel::delete(roles_schema::table.filter(roles_schema::id.eq(id)))
         .execute(database_connection)?;
 
-    if found_user_accesses != 0 {
-        Ok(true)
-    } else {
-        Ok(false)
+    Ok(())
+}
+
+pub(crate) fn add_role_permission(
+    role_permission: RolePermission,
+    database_connection: &MysqlConnection,
+) -> Result<(), Error> {
+    diesel::insert_into(role_permissions_schema::table)
+        .values(role_permission)
+        .execute(database_connection)?;
+
+    Ok(())
+}
+
+pub(crate) fn remove_role_permission(
+    role_permission: RolePermission,
+    database_connection: &MysqlConnection,
+) -> Result<(), Error> {
+    diesel::delete(
+        role_permissions_schema::table
+            .filter(
+                role_permissions_schema::role_id.eq(role_permission.role_id),
+            )
+            .filter(
+                role_permissions_schema::permission_id
+                    .eq(role_permission.permission_id),
+            ),
+    )
+    .execute(database_connection)?;
+
+    Ok(())
+}
+
+pub(crate) fn add_user_role(
+    user_role: UserRole,
+    database_connection: &MysqlConnection,
+) -> Result<(), Error> {
+    diesel::insert_into(user_roles_schema::table)
+        .values(user_role)
+        .execute(database_connection)?;
+
+    Ok(())
+}
+
+pub(crate) fn remove_user_role(
+    user_role: UserRole,
+    database_connection: &MysqlConnection,
+) -> Result<(), Error> {
+    diesel::delete(
+        user_roles_schema::table
+            .filter(user_roles_schema::user_id.eq(user_role.user_id))
+            .filter(user_roles_schema::role_id.eq(user_role.role_id)),
+    )
+    .execute(database_connection)?;
+
+    Ok(())
+}
+
+#[test]
+fn merge_permissions_keeps_one_of_each() {
+    let permission = |id: u64, permission_name: &str| Permission {
+        id,
+        permission_name: permission_name.to_owned(),
+    };
+
+    let permissions = merge_permissions(
+        vec![permission(13, "GetChemical"), permission(1, "GetUsers")],
+        vec![permission(13, "GetChemical"), permission(17, "GetTests")],
+    );
+
+    let ids: Vec<u64> = permissions.iter().map(|p| p.id).collect();
+    assert_eq!(ids, vec![1, 13, 17]);
+}
+
+/// Find the certifications a permission requires that the user does not hold,
+/// or holds only expired
+pub(crate) fn missing_certifications(
+    user_id: u64,
+    permission_name: &str,
+    database_connection: &MysqlConnection,
+) -> Result<Vec<String>, Error> {
+    let required_certifications = permission_certifications_schema::table
+        .inner_join(permission_schema::table)
+        .inner_join(certifications_schema::table)
+        .filter(permission_schema::permission_name.eq(permission_name))
+        .select((certifications_schema::id, certifications_schema::name))
+        .load::<(u64, String)>(database_connection)?;
+
+    if required_certifications.is_empty() {
+        return Ok(Vec::new());
     }
+
+    let held_certifications = user_certifications_schema::table
+        .filter(user_certifications_schema::user_id.eq(user_id))
+        .filter(
+            user_certifications_schema::expires
+                .gt(Local::now().naive_local()),
+        )
+        .select(user_certifications_schema::certification_id)
+        .load::<u64>(database_connection)?;
+
+    Ok(required_certifications
+        .into_iter()
+        .filter(|(id, _)| !held_certifications.contains(id))
+        .map(|(_, name)| name)
+        .collect())
+}
+
+pub(crate) fn get_required_certifications(
+    permission_id: u64,
+    database_connection: &MysqlConnection,
+) -> Result<CertificationList, Error> {
+    let certifications = permission_certifications_schema::table
+        .inner_join(certifications_schema::table)
+        .filter(
+            permission_certifications_schema::permission_id.eq(permission_id),
+        )
+        .select(certifications_schema::all_columns)
+        .load::<Certification>(database_connection)?;
+
+    Ok(CertificationList { certifications })
+}
+
+pub(crate) fn add_required_certification(
+    permission_certification: PermissionCertification,
+    database_connection: &MysqlConnection,
+) -> Result<(), Error> {
+    diesel::insert_into(permission_certifications_schema::table)
+        .values(permission_certification)
+        .execute(database_connection)?;
+
+    Ok(())
+}
+
+pub(crate) fn remove_required_certification(
+    permission_certification: PermissionCertification,
+    database_connection: &MysqlConnection,
+) -> Result<(), Error> {
+    diesel::delete(
+        permission_certifications_schema::table
+            .filter(
+                permission_certifications_schema::permission_id
+                    .eq(permission_certification.permission_id),
+            )
+            .filter(
+                permission_certifications_schema::certification_id
+                    .eq(permission_certification.certification_id),
+            ),
+    )
+    .execute(database_connection)?;
+
+    Ok(())
 }
 
 pub(crate) fn create_user_access(
@@ -518,6 +1330,8 @@ pub(crate) fn create_user_access(
         return Err(Error::new(ErrorKind::Database));
     }
 
+    AccessLevel::from_access_level(user_access.access_level.as_deref())?;
+
     //permission most definitely does not exist at this point
 
     diesel::insert_into(user_access_schema::table)
@@ -543,6 +1357,10 @@ pub(crate) fn update_user_access(
     user_access: PartialUserAccess,
     database_connection: &MysqlConnection,
 ) -> Result<(), Error> {
+    if let Some(access_level) = &user_access.access_level {
+        AccessLevel::from_access_level(access_level.as_deref())?;
+    }
+
     diesel::update(user_access_schema::table)
         .filter(user_access_schema::permission_id.eq(id))
         .set(&user_access)
@@ -563,3 +1381,167 @@ pub(crate) fn delete_user_access(
 
     Ok(())
 }
+
+#[test]
+#[ignore]
+fn first_permission_with_bad_user_creates_nothing() {
+    use std::collections::HashMap;
+
+    use super::identity::StaticVerifier;
+    use crate::audit::schema::audit_log as audit_log_schema;
+
+    let database_connection = crate::testing::database_connection();
+
+    database_connection.test_transaction::<_, Error, _>(|| {
+        // Start from a database where permissions have not been set up yet
+        diesel::delete(
+            user_access_schema::table
+                .filter(user_access_schema::permission_id.ne(1)),
+        )
+        .execute(&database_connection)?;
+        diesel::delete(user_roles_schema::table)
+            .execute(&database_connection)?;
+
+        // The audit entry is written, but the user does not exist, so adding
+        // their role fails
+        assert!(first_permission(
+            Some(u64::max_value()),
+            "",
+            &StaticVerifier::new(HashMap::new()),
+            &database_connection,
+        )
+        .is_err());
+
+        let found_entries = audit_log_schema::table
+            .filter(audit_log_schema::target_table.eq("user_roles"))
+            .filter(audit_log_schema::target_id.eq(u64::max_value()))
+            .count()
+            .get_result::<i64>(&database_connection)?;
+
+        assert_eq!(found_entries, 0);
+
+        Ok(())
+    });
+}
+
+#[test]
+#[ignore]
+fn check_to_run_requires_unexpired_certifications() {
+    use chrono::Duration;
+
+    use crate::certifications::models::NewCertification;
+    use crate::certifications::requests::{
+        create_certification, issue_certification,
+    };
+
+    let database_connection = crate::testing::database_connection();
+
+    database_connection.test_transaction::<_, Error, _>(|| {
+        let user =
+            create_user(crate::testing::new_user(), &database_connection)?;
+        let permission = create_permission(
+            NewPermission {
+                permission_name: "TransactionTest".to_owned(),
+            },
+            &database_connection,
+        )?;
+        diesel::insert_into(user_access_schema::table)
+            .values(NewUserAccess {
+                permission_id: permission.id,
+                user_id: user.id,
+                access_level: None,
+            })
+            .execute(&database_connection)?;
+
+        let lab_safety = create_certification(
+            NewCertification {
+                name: "Transaction Test Lab Safety".to_owned(),
+                valid_months: 12,
+            },
+            &database_connection,
+        )?;
+        let fire_safety = create_certification(
+            NewCertification {
+                name: "Transaction Test Fire Safety".to_owned(),
+                valid_months: 12,
+            },
+            &database_connection,
+        )?;
+        for certification in &[&lab_safety, &fire_safety] {
+            add_required_certification(
+                PermissionCertification {
+                    permission_id: permission.id,
+                    certification_id: certification.id,
+                },
+                &database_connection,
+            )?;
+        }
+
+        let missing = || -> Result<Vec<String>, Error> {
+            let mut missing = missing_certifications(
+                user.id,
+                "TransactionTest",
+                &database_connection,
+            )?;
+            missing.sort();
+            Ok(missing)
+        };
+        let check = || {
+            check_to_run(
+                Some(user.id),
+                "TransactionTest",
+                None,
+                &database_connection,
+            )
+        };
+
+        // An expired certification does not count
+        let now = Local::now().naive_local();
+        issue_certification(
+            user.id,
+            lab_safety.id,
+            None,
+            now - Duration::days(400),
+            &database_connection,
+        )?;
+        assert_eq!(
+            missing()?,
+            vec![
+                "Transaction Test Fire Safety",
+                "Transaction Test Lab Safety"
+            ]
+        );
+
+        // Every required certification has to be held
+        issue_certification(
+            user.id,
+            lab_safety.id,
+            None,
+            now,
+            &database_connection,
+        )?;
+        assert_eq!(missing()?, vec!["Transaction Test Fire Safety"]);
+
+        let error = check().unwrap_err();
+        match error.kind() {
+            ErrorKind::MissingCertification => {}
+            _ => panic!("Expected a missing certification"),
+        }
+        assert!(error
+            .to_string_with_source()
+            .contains("Transaction Test Fire Safety"));
+        assert_eq!(rouille::Response::from(error).status_code, 403);
+
+        issue_certification(
+            user.id,
+            fire_safety.id,
+            None,
+            now,
+            &database_connection,
+        )?;
+        assert!(missing()?.is_empty());
+        assert!(check().is_ok());
+
+        Ok(())
+    });
+}
@@ -1,3 +1,4 @@
+use crate::certifications::schema::certifications;
 use crate::users::schema::users;
 
 table! {
@@ -16,7 +17,56 @@ table! {
     }
 }
 
+table! {
+    permission_certifications (permission_id, certification_id) {
+        permission_id -> Unsigned<Bigint>,
+        certification_id -> Unsigned<Bigint>,
+    }
+}
+
+table! {
+    roles (id) {
+        id -> Unsigned<Bigint>,
+        name -> Varchar,
+    }
+}
+
+table! {
+    role_permissions (role_id, permission_id) {
+        role_id -> Unsigned<Bigint>,
+        permission_id -> Unsigned<Bigint>,
+    }
+}
+
+table! {
+    user_roles (user_id, role_id) {
+        user_id -> Unsigned<Bigint>,
+        role_id -> Unsigned<Bigint>,
+    }
+}
+
 joinable!(user_access -> permission (permission_id));
 joinable!(user_access -> users (user_id));
 
+joinable!(permission_certifications -> permission (permission_id));
+joinable!(permission_certifications -> certifications (certification_id));
+
+joinable!(role_permissions -> roles (role_id));
+joinable!(role_permissions -> permission (permission_id));
+joinable!(user_roles -> roles (role_id));
+joinable!(user_roles -> users (user_id));
+
 allow_tables_to_appear_in_same_query!(permission, user_access, users,);
+allow_tables_to_appear_in_same_query!(
+    permission,
+    permission_certifications,
+    certifications,
+);
+allow_tables_to_appear_in_same_query!(
+    permission,
+    roles,
+    role_permissions,
+    user_roles,
+);
+allow_tables_to_appear_in_same_query!(roles, users);
+allow_tables_to_appear_in_same_query!(user_roles, users);
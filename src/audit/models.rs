@@ -0,0 +1,147 @@
+use rouille;
+use rouille::router;
+use serde::Deserialize;
+use serde::Serialize;
+
+use url::form_urlencoded;
+
+use chrono::offset::Local;
+use chrono::DateTime;
+use chrono::NaiveDateTime;
+
+use log::warn;
+
+use crate::errors::Error;
+use crate::errors::ErrorKind;
+
+use crate::search::{Digits, Page, Search};
+
+use super::schema::audit_log;
+
+#[derive(Queryable, Debug)]
+pub struct RawAuditEntry {
+    pub id: u64,
+    pub actor_id: Option<u64>,
+    pub action: String,
+    pub target_table: String,
+    pub target_id: u64,
+    pub before_json: Option<String>,
+    pub after_json: Option<String>,
+    pub recorded: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug)]
+#[table_name = "audit_log"]
+pub struct NewRawAuditEntry {
+    pub actor_id: Option<u64>,
+    pub action: String,
+    pub target_table: String,
+    pub target_id: u64,
+    pub before_json: Option<String>,
+    pub after_json: Option<String>,
+    pub recorded: NaiveDateTime,
+}
+
+/// A record of one change, made by `actor_id` using the `action` permission
+///
+/// Changes made while taking a test need no permission, so their `action` is
+/// one of `RegisterForTest`, `OpenTest`, `SubmitTest`, `IssueCertification`
+/// and `RevokeCertification` instead.
+///
+/// `before` is missing for creates and `after` is missing for deletes that
+/// remove the row, rather than marking it deleted.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AuditEntry {
+    pub id: u64,
+    pub actor_id: Option<u64>,
+    pub action: String,
+    pub target_table: String,
+    pub target_id: u64,
+    pub before: Option<serde_json::Value>,
+    pub after: Option<serde_json::Value>,
+    pub recorded: DateTime<Local>,
+}
+
+/// One page of audit entries, out of `total`
+///
+/// `next` is the offset of the page after this one, if there is one.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AuditEntryList {
+    pub entries: Vec<AuditEntry>,
+    pub total: u64,
+    pub next: Option<u64>,
+}
+
+pub struct SearchAuditEntry {
+    pub actor_id: Search<Digits<u64>>,
+    pub action: Search<String>,
+    pub target_table: Search<String>,
+    pub target_id: Search<Digits<u64>>,
+    pub page: Page,
+}
+
+pub enum AuditRequest {
+    SearchAuditLog(SearchAuditEntry),
+}
+
+impl AuditRequest {
+    pub fn from_rouille(
+        request: &rouille::Request,
+    ) -> Result<AuditRequest, Error> {
+        let url_queries =
+            form_urlencoded::parse(request.raw_query_string().as_bytes());
+
+        router!(request,
+            (GET) (/) => {
+                let mut actor_id_search = Search::NoSearch;
+                let mut action_search = Search::NoSearch;
+                let mut target_table_search = Search::NoSearch;
+                let mut target_id_search = Search::NoSearch;
+                let mut page = Page::default();
+
+                for (field, query) in url_queries {
+                    match field.as_ref() {
+                        "actor_id" => actor_id_search =
+                            Search::from_query(query.as_ref())?,
+                        "action" => action_search =
+                            Search::from_query(query.as_ref())?,
+                        "target_table" => target_table_search =
+                            Search::from_query(query.as_ref())?,
+                        "target_id" => target_id_search =
+                            Search::from_query(query.as_ref())?,
+                        "limit" | "offset" | "sort" =>
+                            page.add_query(field.as_ref(), query.as_ref())?,
+                        _ => return Err(Error::new(ErrorKind::Url)),
+                    }
+                }
+
+                Ok(AuditRequest::SearchAuditLog(SearchAuditEntry {
+                    actor_id: actor_id_search,
+                    action: action_search,
+                    target_table: target_table_search,
+                    target_id: target_id_search,
+                    page,
+                }))
+            },
+
+            _ => {
+                warn!("Could not create an audit request for the given rouille request");
+                Err(Error::new(ErrorKind::NotFound))
+            }
+        )
+    }
+}
+
+pub enum AuditResponse {
+    ManyAuditEntries(AuditEntryList),
+}
+
+impl AuditResponse {
+    pub fn to_rouille(self) -> rouille::Response {
+        match self {
+            AuditResponse::ManyAuditEntries(entries) => {
+                rouille::Response::json(&entries)
+            }
+        }
+    }
+}
@@ -0,0 +1,270 @@
+use diesel;
+use diesel::mysql::Mysql;
+use diesel::mysql::MysqlConnection;
+use diesel::ExpressionMethods;
+use diesel::QueryDsl;
+use diesel::RunQueryDsl;
+
+use chrono::offset::Local;
+
+use serde::Serialize;
+
+use log::error;
+use log::trace;
+
+use crate::dates::to_local;
+
+use crate::errors::Error;
+use crate::errors::ErrorKind;
+
+use crate::search::DEFAULT_LIMIT;
+
+use crate::access::requests::check_to_run;
+
+use super::models::{
+    AuditEntry, AuditEntryList, AuditRequest, AuditResponse, NewRawAuditEntry,
+    RawAuditEntry, SearchAuditEntry,
+};
+
+use super::schema::audit_log as audit_log_schema;
+
+pub fn handle_audit(
+    request: AuditRequest,
+    requested_user: Option<u64>,
+    database_connection: &MysqlConnection,
+) -> Result<AuditResponse, Error> {
+    match request {
+        AuditRequest::SearchAuditLog(audit_entry) => {
+            check_to_run(
+                requested_user,
+                "GetAuditLog",
+                None,
+                database_connection,
+            )?;
+            search_audit_log(audit_entry, database_connection)
+                .map(|a| AuditResponse::ManyAuditEntries(a))
+        }
+    }
+}
+
+/// Record a change to a row, with snapshots of it before and after
+///
+/// This should run in the same transaction as the change, so that a change is
+/// never made without its record.
+pub(crate) fn record_change<T: Serialize>(
+    actor_id: Option<u64>,
+    action: &str,
+    target_table: &str,
+    target_id: u64,
+    before: Option<&T>,
+    after: Option<&T>,
+    database_connection: &MysqlConnection,
+) -> Result<(), Error> {
+    trace!(
+        "Recording {} on {} {} by {:?}",
+        action,
+        target_table,
+        target_id,
+        actor_id
+    );
+
+    let new_raw_audit_entry = NewRawAuditEntry {
+        actor_id,
+        action: action.to_owned(),
+        target_table: target_table.to_owned(),
+        target_id,
+        before_json: before.map(serde_json::to_string).transpose()?,
+        after_json: after.map(serde_json::to_string).transpose()?,
+        recorded: Local::now().naive_local(),
+    };
+
+    diesel::insert_into(audit_log_schema::table)
+        .values(new_raw_audit_entry)
+        .execute(database_connection)?;
+
+    Ok(())
+}
+
+/// Search the audit log, newest entries first unless sorted otherwise
+///
+/// The audit log only grows, so a page holds at most `DEFAULT_LIMIT` entries
+/// unless a limit is given.
+pub(crate) fn search_audit_log(
+    audit_entry_search: SearchAuditEntry,
+    database_connection: &MysqlConnection,
+) -> Result<AuditEntryList, Error> {
+    let page = audit_entry_search.page.clone().or_limit(DEFAULT_LIMIT);
+
+    let total = audit_log_query(&audit_entry_search)
+        .count()
+        .get_result::<i64>(database_connection)? as u64;
+
+    let mut audit_log_query = audit_log_query(&audit_entry_search);
+
+    audit_log_query = match &page.sort {
+        Some(sort) => match sort.field.as_ref() {
+            "id" => sort.order.order_by(audit_log_query, audit_log_schema::id),
+            "actor_id" => sort
+                .order
+                .order_by(audit_log_query, audit_log_schema::actor_id),
+            "action" => sort
+                .order
+                .order_by(audit_log_query, audit_log_schema::action),
+            "target_table" => sort
+                .order
+                .order_by(audit_log_query, audit_log_schema::target_table),
+            "target_id" => sort
+                .order
+                .order_by(audit_log_query, audit_log_schema::target_id),
+            "recorded" => sort
+                .order
+                .order_by(audit_log_query, audit_log_schema::recorded),
+            _ => return Err(Error::new(ErrorKind::Url)),
+        },
+        None => audit_log_query.order(audit_log_schema::recorded.desc()),
+    };
+
+    let entries = page
+        .paginate(audit_log_query.then_order_by(audit_log_schema::id.desc()))
+        .load::<RawAuditEntry>(database_connection)?
+        .into_iter()
+        .map(to_audit_entry)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(AuditEntryList {
+        entries,
+        total,
+        next: page.next(total),
+    })
+}
+
+/// Build a query for the audit entries matching a search, in no particular
+/// order
+fn audit_log_query(
+    audit_entry_search: &SearchAuditEntry,
+) -> audit_log_schema::BoxedQuery<'_, Mysql> {
+    let mut audit_log_query = audit_log_schema::table.into_boxed::<Mysql>();
+
+    audit_log_query = audit_entry_search
+        .actor_id
+        .filter(audit_log_query, audit_log_schema::actor_id);
+
+    audit_log_query = audit_entry_search
+        .action
+        .filter_text(audit_log_query, audit_log_schema::action);
+
+    audit_log_query = audit_entry_search
+        .target_table
+        .filter_text(audit_log_query, audit_log_schema::target_table);
+
+    audit_log_query = audit_entry_search
+        .target_id
+        .filter(audit_log_query, audit_log_schema::target_id);
+
+    audit_log_query
+}
+
+fn to_audit_entry(raw_audit_entry: RawAuditEntry) -> Result<AuditEntry, Error> {
+    Ok(AuditEntry {
+        id: raw_audit_entry.id,
+        actor_id: raw_audit_entry.actor_id,
+        action: raw_audit_entry.action,
+        target_table: raw_audit_entry.target_table,
+        target_id: raw_audit_entry.target_id,
+        before: from_snapshot(raw_audit_entry.before_json)?,
+        after: from_snapshot(raw_audit_entry.after_json)?,
+        recorded: to_local(raw_audit_entry.recorded)?,
+    })
+}
+
+fn from_snapshot(
+    snapshot: Option<String>,
+) -> Result<Option<serde_json::Value>, Error> {
+    match snapshot {
+        Some(snapshot) => match serde_json::from_str(&snapshot) {
+            Ok(value) => Ok(Some(value)),
+            Err(e) => {
+                error!("Could not read an audit snapshot! {}", e);
+                Err(Error::new(ErrorKind::Database))
+            }
+        },
+        None => Ok(None),
+    }
+}
+
+#[test]
+fn from_snapshot_reads_recorded_snapshots() {
+    let snapshot = serde_json::to_string(&vec![1, 2, 3]).unwrap();
+
+    assert_eq!(
+        from_snapshot(Some(snapshot)).unwrap(),
+        Some(serde_json::json!([1, 2, 3]))
+    );
+    assert_eq!(from_snapshot(None).unwrap(), None);
+    assert!(from_snapshot(Some("{".to_owned())).is_err());
+}
+
+#[test]
+#[ignore]
+fn record_change_is_rolled_back_with_the_change() {
+    use diesel::Connection;
+
+    use crate::testing::new_user;
+    use crate::users::models::NewUser;
+    use crate::users::requests::create_user;
+
+    let database_connection = crate::testing::database_connection();
+
+    let entries = |target_id: u64| {
+        audit_log_schema::table
+            .filter(audit_log_schema::target_table.eq("users"))
+            .filter(audit_log_schema::target_id.eq(target_id))
+            .load::<RawAuditEntry>(&database_connection)
+    };
+
+    database_connection.test_transaction::<_, Error, _>(|| {
+        let created = create_user(new_user(), &database_connection)?;
+        record_change(
+            None,
+            "CreateUsers",
+            "users",
+            created.id,
+            None,
+            Some(&created),
+            &database_connection,
+        )?;
+
+        let recorded = entries(created.id)?;
+        assert_eq!(recorded.len(), 1);
+        assert_eq!(recorded[0].action, "CreateUsers");
+        assert!(recorded[0].before_json.is_none());
+        assert!(recorded[0].after_json.is_some());
+
+        let mut rolled_back_id = None;
+        let result = database_connection.transaction::<(), Error, _>(|| {
+            let created = create_user(
+                NewUser {
+                    email: "rolled_back_test@rowan.edu".to_owned(),
+                    ..new_user()
+                },
+                &database_connection,
+            )?;
+            rolled_back_id = Some(created.id);
+            record_change(
+                None,
+                "CreateUsers",
+                "users",
+                created.id,
+                None,
+                Some(&created),
+                &database_connection,
+            )?;
+            Err(Error::new(ErrorKind::Database))
+        });
+
+        assert!(result.is_err());
+        assert!(entries(rolled_back_id.unwrap())?.is_empty());
+
+        Ok(())
+    });
+}
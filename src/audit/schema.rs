@@ -0,0 +1,12 @@
+table! {
+    audit_log (id) {
+        id -> Unsigned<Bigint>,
+        actor_id -> Nullable<Unsigned<Bigint>>,
+        action -> Varchar,
+        target_table -> Varchar,
+        target_id -> Unsigned<Bigint>,
+        before_json -> Nullable<Text>,
+        after_json -> Nullable<Text>,
+        recorded -> Timestamp,
+    }
+}
@@ -94,7 +94,9 @@ fn main() {
             last_name: csv_user.last_name,
             email: csv_user.email,
             banner_id: csv_user.banner_id as u32,
+            department: Some(csv_user.department),
             accesses: Vec::new(),
+            roles: Vec::new(),
         };
         //Import new user into database
         let import_user = UserRequest::CreateUser(new_user);
@@ -0,0 +1,314 @@
+use std::env;
+use std::fs::File;
+
+use diesel::mysql::MysqlConnection;
+use diesel::r2d2::ConnectionManager;
+use diesel::r2d2::Pool;
+use dotenv::dotenv;
+use log::debug;
+use log::error;
+use log::info;
+use log::warn;
+
+use webdev_lib::errors::Error;
+
+use webdev_lib::access::identity::{
+    GoogleVerifier, IdentityVerifier, StaticVerifier,
+};
+use webdev_lib::access::models::{
+    PermissionRequest, RoleRequest, UserAccessRequest,
+};
+use webdev_lib::access::requests::{
+    handle_permission, handle_role, handle_user_access, validate_token,
+};
+use webdev_lib::audit::models::AuditRequest;
+use webdev_lib::audit::requests::handle_audit;
+use webdev_lib::certifications::models::CertificationRequest;
+use webdev_lib::certifications::requests::handle_certification;
+use webdev_lib::chemicals::models::{
+    ChemicalInventoryRequest, ChemicalRequest,
+};
+use webdev_lib::chemicals::requests::{
+    handle_chemical, handle_chemical_inventory,
+};
+use webdev_lib::sessions::models::{session_token, SessionRequest};
+use webdev_lib::sessions::requests::{handle_session, validate_session};
+use webdev_lib::tests::question_categories::models::QuestionCategoryRequest;
+use webdev_lib::tests::question_categories::requests::handle_question_category;
+use webdev_lib::tests::questions::models::QuestionRequest;
+use webdev_lib::tests::questions::requests::handle_question;
+use webdev_lib::tests::test_sessions::models::TestSessionRequest;
+use webdev_lib::tests::test_sessions::requests::handle_test_session;
+use webdev_lib::tests::tests::models::TestRequest;
+use webdev_lib::tests::tests::requests::handle_test;
+use webdev_lib::users::models::UserRequest;
+use webdev_lib::users::requests::handle_user;
+
+/// Every module is mounted under this prefix, e.g. `/api/v1/users`
+const API_PREFIX: &str = "/api/v1";
+
+/// Address to listen on if `WEBDEV_ADDRESS` is not set
+const DEFAULT_ADDRESS: &str = "0.0.0.0:8000";
+
+fn main() {
+    dotenv().ok();
+
+    simplelog::TermLogger::init(
+        simplelog::LevelFilter::Trace,
+        simplelog::Config::default(),
+    )
+    .unwrap();
+
+    info!("Connecting to database");
+
+    let database_url = match env::var("DATABASE_URL") {
+        Ok(url) => url,
+        Err(_e) => {
+            error!("Could not read DATABASE_URL environment variable");
+            return;
+        }
+    };
+
+    debug!("Connecting to {}", database_url);
+
+    let connection_manager =
+        ConnectionManager::<MysqlConnection>::new(database_url);
+
+    let connection_pool = match Pool::builder().build(connection_manager) {
+        Ok(pool) => pool,
+        Err(e) => {
+            error!("Could not create database connection pool: {}", e);
+            return;
+        }
+    };
+
+    debug!("Connected to database");
+
+    let identity_verifier = match identity_verifier_from_env() {
+        Ok(verifier) => verifier,
+        Err(e) => {
+            error!("Could not set up the identity verifier: {}", e);
+            return;
+        }
+    };
+
+    let address =
+        env::var("WEBDEV_ADDRESS").unwrap_or(DEFAULT_ADDRESS.to_owned());
+
+    info!("Listening on {}", address);
+
+    rouille::start_server(address, move |request| {
+        debug!("Handling request: {} {}", request.method(), request.url());
+
+        let database_connection = match connection_pool.get() {
+            Ok(connection) => connection,
+            Err(e) => return rouille::Response::from(Error::from(e)),
+        };
+
+        match request.remove_prefix(API_PREFIX) {
+            Some(api_request) => {
+                handle_request(
+                    &api_request,
+                    identity_verifier.as_ref(),
+                    &database_connection,
+                )
+            }
+            None => rouille::Response::empty_404(),
+        }
+    });
+}
+
+/// Pick the identity verifier from the environment
+///
+/// `IDENTITY_VERIFIER` selects the kind of verifier, and is `google` if unset.
+/// The `google` verifier reads its accepted client ids from the comma
+/// separated `GOOGLE_AUDIENCES`. The `static` verifier is for development
+/// only, and reads a JSON table of tokens from the file at `STATIC_ID_TOKENS`.
+fn identity_verifier_from_env() -> Result<Box<dyn IdentityVerifier>, String> {
+    let kind = env::var("IDENTITY_VERIFIER").unwrap_or("google".to_owned());
+
+    match kind.as_ref() {
+        "google" => {
+            let audiences: Vec<String> = env::var("GOOGLE_AUDIENCES")
+                .map_err(|_| "GOOGLE_AUDIENCES is not set".to_owned())?
+                .split(',')
+                .map(|audience| audience.trim().to_owned())
+                .filter(|audience| !audience.is_empty())
+                .collect();
+
+            if audiences.is_empty() {
+                return Err("GOOGLE_AUDIENCES is empty".to_owned());
+            }
+
+            info!("Verifying id tokens with Google for {:?}", audiences);
+
+            Ok(Box::new(GoogleVerifier::new(audiences)))
+        }
+        "static" => {
+            let path = env::var("STATIC_ID_TOKENS")
+                .map_err(|_| "STATIC_ID_TOKENS is not set".to_owned())?;
+
+            let file = File::open(&path)
+                .map_err(|e| format!("Could not open {}: {}", path, e))?;
+
+            let verifier = StaticVerifier::from_reader(file)
+                .map_err(|e| e.to_string_with_source())?;
+
+            warn!("Verifying id tokens against the static tokens in {}", path);
+
+            Ok(Box::new(verifier))
+        }
+        _ => Err(format!("Unknown IDENTITY_VERIFIER: {}", kind)),
+    }
+}
+
+fn handle_request(
+    request: &rouille::Request,
+    identity_verifier: &dyn IdentityVerifier,
+    database_connection: &MysqlConnection,
+) -> rouille::Response {
+    let session_user = session_token(request).and_then(|token| {
+        match validate_session(&token, database_connection) {
+            Ok(user_id) => Some(user_id),
+            Err(e) => {
+                warn!("Could not validate session: {}", e);
+                None
+            }
+        }
+    });
+
+    // An expired or revoked session should not hide a valid id_token
+    let requested_user = session_user.or_else(|| {
+        request.header("id_token").and_then(|id_token| {
+            match validate_token(
+                id_token,
+                identity_verifier,
+                database_connection,
+            ) {
+                Ok(user_id) => Some(user_id),
+                Err(e) => {
+                    warn!("Could not validate id_token: {}", e);
+                    None
+                }
+            }
+        })
+    });
+
+    debug!("Requested by user {:?}", requested_user);
+
+    let response = if let Some(user_request) = request.remove_prefix("/users")
+    {
+        UserRequest::from_rouille(&user_request)
+            .and_then(|r| {
+                handle_user(r, requested_user, database_connection)
+            })
+            .map(|r| r.to_rouille())
+    } else if let Some(session_request) = request.remove_prefix("/sessions") {
+        SessionRequest::from_rouille(&session_request)
+            .and_then(|r| {
+                handle_session(
+                    r,
+                    requested_user,
+                    identity_verifier,
+                    database_connection,
+                )
+            })
+            .map(|r| r.to_rouille())
+    } else if let Some(permission_request) = request.remove_prefix("/access")
+    {
+        PermissionRequest::from_rouille(&permission_request)
+            .and_then(|r| {
+                handle_permission(
+                    r,
+                    requested_user,
+                    identity_verifier,
+                    database_connection,
+                )
+            })
+            .map(|r| r.to_rouille())
+    } else if let Some(user_access_request) =
+        request.remove_prefix("/user_access")
+    {
+        UserAccessRequest::from_rouille(&user_access_request)
+            .and_then(|r| {
+                handle_user_access(r, requested_user, database_connection)
+            })
+            .map(|r| r.to_rouille())
+    } else if let Some(role_request) = request.remove_prefix("/roles") {
+        RoleRequest::from_rouille(&role_request)
+            .and_then(|r| handle_role(r, requested_user, database_connection))
+            .map(|r| r.to_rouille())
+    } else if let Some(chemical_request) = request.remove_prefix("/chemicals")
+    {
+        ChemicalRequest::from_rouille(&chemical_request)
+            .and_then(|r| {
+                handle_chemical(r, requested_user, database_connection)
+            })
+            .map(|r| r.to_rouille())
+    } else if let Some(chemical_inventory_request) =
+        request.remove_prefix("/chemical_inventory")
+    {
+        ChemicalInventoryRequest::from_rouille(&chemical_inventory_request)
+            .and_then(|r| {
+                handle_chemical_inventory(
+                    r,
+                    requested_user,
+                    database_connection,
+                )
+            })
+            .map(|r| r.to_rouille())
+    } else if let Some(audit_request) = request.remove_prefix("/audit") {
+        AuditRequest::from_rouille(&audit_request)
+            .and_then(|r| handle_audit(r, requested_user, database_connection))
+            .map(|r| r.to_rouille())
+    } else if let Some(certification_request) =
+        request.remove_prefix("/certifications")
+    {
+        CertificationRequest::from_rouille(&certification_request)
+            .and_then(|r| {
+                handle_certification(r, requested_user, database_connection)
+            })
+            .map(|r| r.to_rouille())
+    } else if let Some(question_request) = request.remove_prefix("/questions")
+    {
+        QuestionRequest::from_rouille(&question_request)
+            .and_then(|r| {
+                handle_question(r, requested_user, database_connection)
+            })
+            .map(|r| r.to_rouille())
+    } else if let Some(question_category_request) =
+        request.remove_prefix("/question_categories")
+    {
+        QuestionCategoryRequest::from_rouille(&question_category_request)
+            .and_then(|r| {
+                handle_question_category(
+                    r,
+                    requested_user,
+                    database_connection,
+                )
+            })
+            .map(|r| r.to_rouille())
+    } else if let Some(test_request) = request.remove_prefix("/tests") {
+        TestRequest::from_rouille(&test_request)
+            .and_then(|r| {
+                handle_test(r, requested_user, database_connection)
+            })
+            .map(|r| r.to_rouille())
+    } else if let Some(test_session_request) =
+        request.remove_prefix("/test_sessions")
+    {
+        TestSessionRequest::from_rouille(&test_session_request)
+            .and_then(|r| {
+                handle_test_session(r, requested_user, database_connection)
+            })
+            .map(|r| r.to_rouille())
+    } else {
+        warn!("No module for the request: {}", request.url());
+        return rouille::Response::empty_404();
+    };
+
+    match response {
+        Ok(response) => response,
+        Err(e) => rouille::Response::from(e),
+    }
+}
@@ -0,0 +1,178 @@
+use rouille;
+use rouille::router;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json;
+
+use url::form_urlencoded;
+
+use chrono::offset::Local;
+use chrono::DateTime;
+use chrono::NaiveDateTime;
+
+use log::warn;
+
+use crate::errors::Error;
+use crate::errors::ErrorKind;
+
+use super::schema::certifications;
+use super::schema::user_certifications;
+
+/// How many days ahead to look for expiring certifications by default
+pub const DEFAULT_EXPIRING_DAYS: u32 = 30;
+
+/// The longest a certification can be valid for
+///
+/// Expiry dates are stored as `DATETIME` rather than `TIMESTAMP`, so they are
+/// not cut off at 2038.
+pub const MAX_VALID_MONTHS: u32 = 1200;
+
+/// Something a user earns by passing a test, such as lab safety training
+#[derive(Queryable, Serialize, Deserialize, Clone, Debug)]
+pub struct Certification {
+    pub id: u64,
+    pub name: String,
+    pub valid_months: u32,
+}
+
+#[derive(Insertable, Serialize, Deserialize, Debug)]
+#[table_name = "certifications"]
+pub struct NewCertification {
+    pub name: String,
+    pub valid_months: u32,
+}
+
+impl NewCertification {
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.valid_months > MAX_VALID_MONTHS {
+            return Err(Error::new(ErrorKind::InvalidValidMonths));
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CertificationList {
+    pub certifications: Vec<Certification>,
+}
+
+#[derive(Queryable, Serialize, Debug)]
+pub struct RawUserCertification {
+    pub id: u64,
+    pub user_id: u64,
+    pub certification_id: u64,
+    pub registration_id: Option<u64>,
+    pub issued: NaiveDateTime,
+    pub expires: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug)]
+#[table_name = "user_certifications"]
+pub struct NewRawUserCertification {
+    pub user_id: u64,
+    pub certification_id: u64,
+    pub registration_id: Option<u64>,
+    pub issued: NaiveDateTime,
+    pub expires: NaiveDateTime,
+}
+
+/// A certification issued to a user
+///
+/// `registration_id` is the test attempt that earned it, if it was earned by
+/// taking a test.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct UserCertification {
+    pub id: u64,
+    pub user_id: u64,
+    pub certification_id: u64,
+    pub name: String,
+    pub registration_id: Option<u64>,
+    pub issued: DateTime<Local>,
+    pub expires: DateTime<Local>,
+    pub expired: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct UserCertificationList {
+    pub user_certifications: Vec<UserCertification>,
+}
+
+pub enum CertificationRequest {
+    GetCertifications,
+    CreateCertification(NewCertification),
+    DeleteCertification(u64),
+    GetUserCertifications(u64),
+    GetExpiringCertifications(u32),
+}
+
+impl CertificationRequest {
+    pub fn from_rouille(
+        request: &rouille::Request,
+    ) -> Result<CertificationRequest, Error> {
+        let mut url_queries =
+            form_urlencoded::parse(request.raw_query_string().as_bytes());
+
+        router!(request,
+            (GET) (/) => {
+                Ok(CertificationRequest::GetCertifications)
+            },
+
+            (POST) (/) => {
+                let request_body = request.data()
+                    .ok_or(Error::new(ErrorKind::Body))?;
+                let new_certification: NewCertification =
+                    serde_json::from_reader(request_body)?;
+                new_certification.validate()?;
+
+                Ok(CertificationRequest::CreateCertification(new_certification))
+            },
+
+            (DELETE) (/{id: u64}) => {
+                Ok(CertificationRequest::DeleteCertification(id))
+            },
+
+            (GET) (/users/{user_id: u64}) => {
+                Ok(CertificationRequest::GetUserCertifications(user_id))
+            },
+
+            (GET) (/expiring) => {
+                let days = match url_queries.find(|q| q.0 == "days") {
+                    Some((_, days)) => days.parse()?,
+                    None => DEFAULT_EXPIRING_DAYS,
+                };
+
+                Ok(CertificationRequest::GetExpiringCertifications(days))
+            },
+
+            _ => {
+                warn!("Could not create a certification request for the given rouille request");
+                Err(Error::new(ErrorKind::NotFound))
+            }
+        )
+    }
+}
+
+pub enum CertificationResponse {
+    OneCertification(Certification),
+    ManyCertifications(CertificationList),
+    ManyUserCertifications(UserCertificationList),
+    NoResponse,
+}
+
+impl CertificationResponse {
+    pub fn to_rouille(self) -> rouille::Response {
+        match self {
+            CertificationResponse::OneCertification(certification) => {
+                rouille::Response::json(&certification)
+            }
+            CertificationResponse::ManyCertifications(certifications) => {
+                rouille::Response::json(&certifications)
+            }
+            CertificationResponse::ManyUserCertifications(
+                user_certifications,
+            ) => rouille::Response::json(&user_certifications),
+            CertificationResponse::NoResponse => rouille::Response::empty_204(),
+        }
+    }
+}
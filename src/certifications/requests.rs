@@ -0,0 +1,467 @@
+use diesel;
+use diesel::mysql::MysqlConnection;
+use diesel::sql_types::Bool;
+use diesel::Connection;
+use diesel::ExpressionMethods;
+use diesel::QueryDsl;
+use diesel::RunQueryDsl;
+
+use chrono::offset::Local;
+use chrono::Datelike;
+use chrono::Duration;
+use chrono::NaiveDate;
+use chrono::NaiveDateTime;
+
+use log::trace;
+
+use crate::dates::to_local;
+
+use crate::errors::Error;
+use crate::errors::ErrorKind;
+
+use crate::access::requests::check_to_run;
+
+use crate::audit::requests::record_change;
+
+use super::models::{
+    Certification, CertificationList, CertificationRequest,
+    CertificationResponse, NewCertification, NewRawUserCertification,
+    RawUserCertification, UserCertification, UserCertificationList,
+};
+
+use super::schema::certifications as certifications_schema;
+use super::schema::user_certifications as user_certifications_schema;
+
+pub fn handle_certification(
+    request: CertificationRequest,
+    requested_user: Option<u64>,
+    database_connection: &MysqlConnection,
+) -> Result<CertificationResponse, Error> {
+    match request {
+        CertificationRequest::GetCertifications => {
+            check_to_run(
+                requested_user,
+                "GetCertifications",
+                None,
+                database_connection,
+            )?;
+            get_certifications(database_connection)
+                .map(|c| CertificationResponse::ManyCertifications(c))
+        }
+        CertificationRequest::CreateCertification(certification) => {
+            check_to_run(
+                requested_user,
+                "CreateCertifications",
+                None,
+                database_connection,
+            )?;
+            database_connection
+                .transaction::<_, Error, _>(|| {
+                    let created = create_certification(
+                        certification,
+                        database_connection,
+                    )?;
+                    record_change(
+                        requested_user,
+                        "CreateCertifications",
+                        "certifications",
+                        created.id,
+                        None,
+                        Some(&created),
+                        database_connection,
+                    )?;
+                    Ok(created)
+                })
+                .map(|c| CertificationResponse::OneCertification(c))
+        }
+        CertificationRequest::DeleteCertification(id) => {
+            check_to_run(
+                requested_user,
+                "DeleteCertifications",
+                None,
+                database_connection,
+            )?;
+            database_connection
+                .transaction::<_, Error, _>(|| {
+                    let before = get_certification(id, database_connection)?;
+                    delete_certification(id, database_connection)?;
+                    record_change(
+                        requested_user,
+                        "DeleteCertifications",
+                        "certifications",
+                        id,
+                        Some(&before),
+                        None,
+                        database_connection,
+                    )
+                })
+                .map(|_| CertificationResponse::NoResponse)
+        }
+        CertificationRequest::GetUserCertifications(user_id) => {
+            if requested_user != Some(user_id) {
+                check_to_run(
+                    requested_user,
+                    "GetUserCertifications",
+                    None,
+                    database_connection,
+                )?;
+            }
+            get_users_certifications(&[user_id], database_connection)
+                .map(|user_certifications| UserCertificationList {
+                    user_certifications,
+                })
+                .map(|c| CertificationResponse::ManyUserCertifications(c))
+        }
+        CertificationRequest::GetExpiringCertifications(days) => {
+            check_to_run(
+                requested_user,
+                "GetUserCertifications",
+                None,
+                database_connection,
+            )?;
+            get_expiring_certifications(days, database_connection)
+                .map(|c| CertificationResponse::ManyUserCertifications(c))
+        }
+    }
+}
+
+pub(crate) fn get_certifications(
+    database_connection: &MysqlConnection,
+) -> Result<CertificationList, Error> {
+    let certifications = certifications_schema::table
+        .load::<Certification>(database_connection)?;
+
+    Ok(CertificationList { certifications })
+}
+
+pub(crate) fn get_certification(
+    id: u64,
+    database_connection: &MysqlConnection,
+) -> Result<Certification, Error> {
+    let mut found_certifications = certifications_schema::table
+        .filter(certifications_schema::id.eq(id))
+        .load::<Certification>(database_connection)?;
+
+    match found_certifications.pop() {
+        Some(certification) => Ok(certification),
+        None => Err(Error::new(ErrorKind::NotFound)),
+    }
+}
+
+pub(crate) fn create_certification(
+    certification: NewCertification,
+    database_connection: &MysqlConnection,
+) -> Result<Certification, Error> {
+    diesel::insert_into(certifications_schema::table)
+        .values(certification)
+        .execute(database_connection)?;
+
+    let mut inserted_certifications = certifications_schema::table
+        .filter(diesel::dsl::sql("id = LAST_INSERT_ID()"))
+        .load::<Certification>(database_connection)?;
+
+    if let Some(inserted_certification) = inserted_certifications.pop() {
+        Ok(inserted_certification)
+    } else {
+        Err(Error::new(ErrorKind::Database))
+    }
+}
+
+pub(crate) fn delete_certification(
+    id: u64,
+    database_connection: &MysqlConnection,
+) -> Result<(), Error> {
+    diesel::delete(
+        certifications_schema::table.filter(certifications_schema::id.eq(id)),
+    )
+    .execute(database_connection)?;
+
+    Ok(())
+}
+
+/// Issue a certification to a user, valid from `issued` for as many months as
+/// the certification lasts
+pub(crate) fn issue_certification(
+    user_id: u64,
+    certification_id: u64,
+    registration_id: Option<u64>,
+    issued: NaiveDateTime,
+    database_connection: &MysqlConnection,
+) -> Result<RawUserCertification, Error> {
+    let certification =
+        get_certification(certification_id, database_connection)?;
+
+    let new_raw_user_certification = NewRawUserCertification {
+        user_id,
+        certification_id,
+        registration_id,
+        issued,
+        expires: add_months(issued, certification.valid_months)?,
+    };
+
+    trace!(
+        "Issuing {} to user {} until {}",
+        certification.name,
+        user_id,
+        new_raw_user_certification.expires
+    );
+
+    diesel::insert_into(user_certifications_schema::table)
+        .values(new_raw_user_certification)
+        .execute(database_connection)?;
+
+    let mut inserted_user_certifications = user_certifications_schema::table
+        .filter(diesel::dsl::sql("id = LAST_INSERT_ID()"))
+        .load::<RawUserCertification>(database_connection)?;
+
+    match inserted_user_certifications.pop() {
+        Some(inserted_user_certification) => Ok(inserted_user_certification),
+        None => Err(Error::new(ErrorKind::Database)),
+    }
+}
+
+/// Withdraw a certification from any of the given registrations it was
+/// issued for, returning what was withdrawn
+pub(crate) fn revoke_certifications(
+    certification_id: u64,
+    registration_ids: &[u64],
+    database_connection: &MysqlConnection,
+) -> Result<Vec<RawUserCertification>, Error> {
+    let revoked = user_certifications_schema::table
+        .filter(
+            user_certifications_schema::certification_id.eq(certification_id),
+        )
+        .filter(
+            user_certifications_schema::registration_id
+                .eq_any(registration_ids),
+        )
+        .load::<RawUserCertification>(database_connection)?;
+
+    let revoked_ids: Vec<u64> = revoked.iter().map(|r| r.id).collect();
+
+    diesel::delete(
+        user_certifications_schema::table
+            .filter(user_certifications_schema::id.eq_any(revoked_ids)),
+    )
+    .execute(database_connection)?;
+
+    Ok(revoked)
+}
+
+/// Get every certification issued to any of the given users, newest first
+pub(crate) fn get_users_certifications(
+    user_ids: &[u64],
+    database_connection: &MysqlConnection,
+) -> Result<Vec<UserCertification>, Error> {
+    let now = Local::now().naive_local();
+
+    user_certifications_schema::table
+        .inner_join(certifications_schema::table)
+        .filter(user_certifications_schema::user_id.eq_any(user_ids))
+        .order(user_certifications_schema::issued.desc())
+        .load::<(RawUserCertification, Certification)>(database_connection)?
+        .into_iter()
+        .map(|(raw_user_certification, certification)| {
+            to_user_certification(raw_user_certification, certification, now)
+        })
+        .collect()
+}
+
+/// Get the certifications that have expired, or will within `days`
+///
+/// Only the latest certification a user holds of each kind is considered, so
+/// renewed certifications do not show up.
+pub(crate) fn get_expiring_certifications(
+    days: u32,
+    database_connection: &MysqlConnection,
+) -> Result<UserCertificationList, Error> {
+    let now = Local::now().naive_local();
+    let cutoff = now
+        .checked_add_signed(Duration::days(i64::from(days)))
+        .ok_or_else(|| Error::new(ErrorKind::Url))?;
+
+    let user_certifications = user_certifications_schema::table
+        .inner_join(certifications_schema::table)
+        .filter(user_certifications_schema::expires.lt(cutoff))
+        .filter(diesel::dsl::sql::<Bool>(LATEST_CERTIFICATION))
+        .order(user_certifications_schema::expires.asc())
+        .load::<(RawUserCertification, Certification)>(database_connection)?
+        .into_iter()
+        .map(|(raw_user_certification, certification)| {
+            to_user_certification(raw_user_certification, certification, now)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(UserCertificationList {
+        user_certifications,
+    })
+}
+
+/// Keeps only the latest expiring certification of each kind for each user,
+/// breaking ties on expiry by the newest id
+const LATEST_CERTIFICATION: &str = "NOT EXISTS (
+    SELECT 1 FROM user_certifications AS newer
+    WHERE newer.user_id = user_certifications.user_id
+    AND newer.certification_id = user_certifications.certification_id
+    AND (newer.expires > user_certifications.expires
+        OR (newer.expires = user_certifications.expires
+            AND newer.id > user_certifications.id))
+)";
+
+/// Move a datetime forward by whole months, keeping the time of day
+///
+/// Days past the end of the new month are clamped to its last day, so one
+/// month after January 31st is the end of February. Datetimes moved past the
+/// last representable year are an error.
+pub(crate) fn add_months(
+    datetime: NaiveDateTime,
+    months: u32,
+) -> Result<NaiveDateTime, Error> {
+    let out_of_range = || Error::new(ErrorKind::InvalidValidMonths);
+
+    let total_months = datetime
+        .month0()
+        .checked_add(months)
+        .ok_or_else(out_of_range)?;
+    let year = datetime
+        .year()
+        .checked_add((total_months / 12) as i32)
+        .ok_or_else(out_of_range)?;
+    let month = total_months % 12 + 1;
+
+    let date = (1..=datetime.day())
+        .rev()
+        .find_map(|day| NaiveDate::from_ymd_opt(year, month, day))
+        .ok_or_else(out_of_range)?;
+
+    Ok(date.and_time(datetime.time()))
+}
+
+fn to_user_certification(
+    raw_user_certification: RawUserCertification,
+    certification: Certification,
+    now: NaiveDateTime,
+) -> Result<UserCertification, Error> {
+    Ok(UserCertification {
+        id: raw_user_certification.id,
+        user_id: raw_user_certification.user_id,
+        certification_id: raw_user_certification.certification_id,
+        name: certification.name,
+        registration_id: raw_user_certification.registration_id,
+        issued: to_local(raw_user_certification.issued)?,
+        expires: to_local(raw_user_certification.expires)?,
+        expired: raw_user_certification.expires <= now,
+    })
+}
+
+#[test]
+fn add_months_works() {
+    let datetime = NaiveDate::from_ymd_opt(2019, 7, 22)
+        .unwrap()
+        .and_hms_opt(9, 30, 0)
+        .unwrap();
+
+    assert_eq!(
+        add_months(datetime, 12).unwrap(),
+        NaiveDate::from_ymd_opt(2020, 7, 22)
+            .unwrap()
+            .and_hms_opt(9, 30, 0)
+            .unwrap()
+    );
+    assert_eq!(
+        add_months(datetime, 6).unwrap(),
+        NaiveDate::from_ymd_opt(2020, 1, 22)
+            .unwrap()
+            .and_hms_opt(9, 30, 0)
+            .unwrap()
+    );
+}
+
+#[test]
+fn add_months_clamps_to_end_of_month() {
+    let datetime = NaiveDate::from_ymd_opt(2019, 1, 31)
+        .unwrap()
+        .and_hms_opt(0, 0, 0)
+        .unwrap();
+
+    assert_eq!(
+        add_months(datetime, 1).unwrap(),
+        NaiveDate::from_ymd_opt(2019, 2, 28)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap()
+    );
+    assert_eq!(
+        add_months(datetime, 13).unwrap(),
+        NaiveDate::from_ymd_opt(2020, 2, 29)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap()
+    );
+}
+
+#[test]
+fn add_months_rejects_dates_out_of_range() {
+    let datetime = NaiveDate::from_ymd_opt(2019, 7, 22)
+        .unwrap()
+        .and_hms_opt(9, 30, 0)
+        .unwrap();
+
+    assert!(add_months(datetime, super::models::MAX_VALID_MONTHS).is_ok());
+    assert!(add_months(datetime, u32::MAX).is_err());
+}
+
+#[test]
+#[ignore]
+fn issue_certification_stores_long_validity() {
+    use crate::users::requests::create_user;
+
+    use super::models::MAX_VALID_MONTHS;
+
+    let database_connection = crate::testing::database_connection();
+
+    database_connection.test_transaction::<_, Error, _>(|| {
+        let user =
+            create_user(crate::testing::new_user(), &database_connection)?;
+        let certification = create_certification(
+            NewCertification {
+                name: "Long Validity Test".to_owned(),
+                valid_months: MAX_VALID_MONTHS,
+            },
+            &database_connection,
+        )?;
+
+        let issued = Local::now().naive_local();
+        issue_certification(
+            user.id,
+            certification.id,
+            None,
+            issued - Duration::days(400),
+            &database_connection,
+        )?;
+        issue_certification(
+            user.id,
+            certification.id,
+            None,
+            issued,
+            &database_connection,
+        )?;
+
+        let held = get_users_certifications(&[user.id], &database_connection)?;
+        assert_eq!(held.len(), 2);
+        assert_eq!(
+            held[0].expires.naive_local().date(),
+            add_months(issued, MAX_VALID_MONTHS)?.date()
+        );
+        assert!(!held[0].expired);
+
+        let expiring =
+            get_expiring_certifications(36500, &database_connection)?;
+        assert!(expiring
+            .user_certifications
+            .iter()
+            .all(|expiring| expiring.user_id != user.id));
+
+        Ok(())
+    });
+}
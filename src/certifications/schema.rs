@@ -0,0 +1,28 @@
+use crate::users::schema::users;
+
+table! {
+    certifications (id) {
+        id -> Unsigned<Bigint>,
+        name -> Varchar,
+        valid_months -> Unsigned<Integer>,
+    }
+}
+
+table! {
+    user_certifications (id) {
+        id -> Unsigned<Bigint>,
+        user_id -> Unsigned<Bigint>,
+        certification_id -> Unsigned<Bigint>,
+        registration_id -> Nullable<Unsigned<Bigint>>,
+        issued -> Datetime,
+        expires -> Datetime,
+    }
+}
+
+joinable!(user_certifications -> certifications (certification_id));
+joinable!(user_certifications -> users (user_id));
+allow_tables_to_appear_in_same_query!(
+    certifications,
+    user_certifications,
+    users
+);
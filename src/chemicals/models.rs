@@ -1,5 +1,14 @@
+use std::io::Write;
+
+use diesel::deserialize::{self, FromSql};
+use diesel::mysql::Mysql;
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::Varchar;
 use diesel::Queryable;
 
+use chrono::NaiveDate;
+use chrono::NaiveDateTime;
+
 use rouille::router;
 
 use serde::Deserialize;
@@ -11,10 +20,24 @@ use log::warn;
 
 use crate::errors::{Error, ErrorKind};
 
-use crate::search::Search;
+use crate::nullable::deserialize_nullable;
+
+use crate::search::{Digits, NullableSearch, Page, Search};
+
+use crate::access::models::Resource;
+
+use super::schema::{chemical, chemical_inventory, chemical_usage};
 
-use super::schema::{chemical, chemical_inventory};
+/// How many days ahead to look for expiring inventory entries by default
+pub const DEFAULT_EXPIRING_DAYS: u32 = 30;
 
+/// A chemical, which is hidden from searches once `deleted_at` is set
+///
+/// `signal_word`, `pictograms` and the statements are the chemical's GHS
+/// hazard classification, as printed on its label.
+///
+/// The chemical is low on stock when its inventory adds up to less than
+/// `minimum_quantity` of `minimum_unit`.
 #[derive(Queryable, Serialize, Deserialize)]
 pub struct Chemical {
     pub id: u64,
@@ -23,6 +46,14 @@ pub struct Chemical {
     pub company_name: String,
     pub ingredients: String,
     pub manual_link: String,
+    pub cas_number: Option<String>,
+    pub signal_word: Option<SignalWord>,
+    pub pictograms: Pictograms,
+    pub hazard_statements: Statements,
+    pub precautionary_statements: Statements,
+    pub minimum_quantity: Option<f64>,
+    pub minimum_unit: Option<Unit>,
+    pub deleted_at: Option<NaiveDateTime>,
 }
 
 #[derive(Insertable, Serialize, Deserialize)]
@@ -33,6 +64,16 @@ pub struct NewChemical {
     pub company_name: String,
     pub ingredients: String,
     pub manual_link: String,
+    pub cas_number: Option<String>,
+    pub signal_word: Option<SignalWord>,
+    #[serde(default)]
+    pub pictograms: Pictograms,
+    #[serde(default)]
+    pub hazard_statements: Statements,
+    #[serde(default)]
+    pub precautionary_statements: Statements,
+    pub minimum_quantity: Option<f64>,
+    pub minimum_unit: Option<Unit>,
 }
 
 #[derive(AsChangeset, Serialize, Deserialize)]
@@ -43,27 +84,141 @@ pub struct PartialChemical {
     pub company_name: Option<String>,
     pub ingredients: Option<String>,
     pub manual_link: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_nullable")]
+    pub cas_number: Option<Option<String>>,
+    #[serde(default, deserialize_with = "deserialize_nullable")]
+    pub signal_word: Option<Option<SignalWord>>,
+    pub pictograms: Option<Pictograms>,
+    pub hazard_statements: Option<Statements>,
+    pub precautionary_statements: Option<Statements>,
+    #[serde(default, deserialize_with = "deserialize_nullable")]
+    pub minimum_quantity: Option<Option<f64>>,
+    #[serde(default, deserialize_with = "deserialize_nullable")]
+    pub minimum_unit: Option<Option<Unit>>,
+}
+
+impl Chemical {
+    /// The least of the chemical that should be kept in stock, if there is a
+    /// minimum
+    pub fn minimum(&self) -> Option<Amount> {
+        match (self.minimum_quantity, self.minimum_unit) {
+            (Some(quantity), Some(unit)) => Some(Amount { quantity, unit }),
+            _ => None,
+        }
+    }
+}
+
+impl NewChemical {
+    /// Check the chemical, dropping any pictogram it lists twice
+    pub fn validate(&mut self) -> Result<(), Error> {
+        self.pictograms.dedup();
+        validate_minimum(self.minimum_quantity, self.minimum_unit)?;
+        if let Some(cas_number) = &self.cas_number {
+            validate_cas_number(cas_number)?;
+        }
+        self.hazard_statements.validate(HAZARD_PREFIXES)?;
+        self.precautionary_statements
+            .validate(PRECAUTIONARY_PREFIXES)
+    }
+}
+
+impl PartialChemical {
+    /// Check the update, which has to set or clear both parts of the minimum
+    /// together, if either, dropping any pictogram it lists twice
+    pub fn validate(&mut self) -> Result<(), Error> {
+        if let Some(pictograms) = &mut self.pictograms {
+            pictograms.dedup();
+        }
+        match (self.minimum_quantity, self.minimum_unit) {
+            (Some(quantity), Some(unit)) => validate_minimum(quantity, unit)?,
+            (None, None) => {}
+            _ => return Err(Error::new(ErrorKind::InvalidMinimum)),
+        }
+        if let Some(Some(cas_number)) = &self.cas_number {
+            validate_cas_number(cas_number)?;
+        }
+        if let Some(hazard_statements) = &self.hazard_statements {
+            hazard_statements.validate(HAZARD_PREFIXES)?;
+        }
+        if let Some(precautionary_statements) = &self.precautionary_statements {
+            precautionary_statements.validate(PRECAUTIONARY_PREFIXES)?;
+        }
+        Ok(())
+    }
 }
 
+/// Search for chemicals
+///
+/// `pictograms`, `hazard_statements` and `precautionary_statements` are
+/// searched by their members, so `exact,flammable` matches every chemical
+/// with the flammable pictogram, whichever others it has. Pictograms are
+/// searched by code or name. `storage_location` matches chemicals with an
+/// inventory entry kept somewhere matching the search.
 pub struct SearchChemical {
     pub name: Search<String>,
     pub purpose: Search<String>,
     pub company_name: Search<String>,
     pub ingredients: Search<String>,
     pub manual_link: Search<String>,
+    pub cas_number: NullableSearch<String>,
+    pub signal_word: NullableSearch<SignalWord>,
+    pub pictograms: Search<Pictogram>,
+    pub hazard_statements: Search<String>,
+    pub precautionary_statements: Search<String>,
+    pub storage_location: Search<String>,
+    pub include_deleted: bool,
+    pub page: Page,
+}
+
+/// An inventory entry of a chemical that is low on stock, along with who is
+/// responsible for it
+#[derive(Serialize, Deserialize)]
+pub struct LowStockEntry {
+    pub inventory_id: u64,
+    pub storage_location: String,
+    pub amount: Amount,
+    pub unparsed_amount: Option<String>,
+    pub custodian_id: u64,
+    pub custodian_first_name: String,
+    pub custodian_last_name: String,
+    pub custodian_email: String,
 }
 
+/// A chemical with less in stock than its minimum
+///
+/// `total` is in the unit of `minimum`. Entries in units that can not be
+/// converted to it, or with an unparsed amount, are listed, but not counted.
+#[derive(Serialize, Deserialize)]
+pub struct LowStockChemical {
+    pub chemical: Chemical,
+    pub minimum: Amount,
+    pub total: Amount,
+    pub entries: Vec<LowStockEntry>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct LowStockChemicalList {
+    pub chemicals: Vec<LowStockChemical>,
+}
+
+/// One page of chemicals, out of `total` matching a search
+///
+/// `next` is the offset of the page after this one, if there is one.
 #[derive(Serialize, Deserialize)]
 pub struct ChemicalList {
     pub chemicals: Vec<Chemical>,
+    pub total: u64,
+    pub next: Option<u64>,
 }
 
 pub enum ChemicalRequest {
-    Search(SearchChemical),
+    Search(Box<SearchChemical>),
     GetChemical(u64),            //id of access name searched
     CreateChemical(NewChemical), //new access type of some name to be created
     UpdateChemical(u64, PartialChemical), //Contains id to be changed to new access_name
     DeleteChemical(u64),                  //if of access to be deleted
+    RestoreChemical(u64),
+    GetLowStock,
 }
 
 impl ChemicalRequest {
@@ -80,6 +235,14 @@ impl ChemicalRequest {
                 let mut company_name_search = Search::NoSearch;
                 let mut ingredients_search = Search::NoSearch;
                 let mut manual_link_search = Search::NoSearch;
+                let mut cas_number_search = NullableSearch::NoSearch;
+                let mut signal_word_search = NullableSearch::NoSearch;
+                let mut pictograms_search = Search::NoSearch;
+                let mut hazard_statements_search = Search::NoSearch;
+                let mut precautionary_statements_search = Search::NoSearch;
+                let mut storage_location_search = Search::NoSearch;
+                let mut include_deleted = false;
+                let mut page = Page::default();
 
                 for (field, query) in url_queries {
                     match field.as_ref() as &str {
@@ -88,17 +251,38 @@ impl ChemicalRequest {
                         "company_name" => company_name_search = Search::from_query(query.as_ref())?,
                         "ingredients" => ingredients_search = Search::from_query(query.as_ref())?,
                         "manual_link" => manual_link_search = Search::from_query(query.as_ref())?,
+                        "cas_number" => cas_number_search = NullableSearch::from_query(query.as_ref())?,
+                        "signal_word" => signal_word_search = NullableSearch::from_query(query.as_ref())?,
+                        "pictograms" => pictograms_search = Search::from_list_query(query.as_ref())?,
+                        "hazard_statements" => hazard_statements_search = Search::from_list_query(query.as_ref())?,
+                        "precautionary_statements" => precautionary_statements_search = Search::from_list_query(query.as_ref())?,
+                        "storage_location" => storage_location_search = Search::from_query(query.as_ref())?,
+                        "include_deleted" => include_deleted = query.parse()?,
+                        "limit" | "offset" | "sort" =>
+                            page.add_query(field.as_ref(), query.as_ref())?,
                         _ => return Err(Error::new(ErrorKind::Url)),
                     }
                 }
 
-                Ok(ChemicalRequest::Search(SearchChemical {
+                Ok(ChemicalRequest::Search(Box::new(SearchChemical {
                     name: name_search,
                     purpose: purpose_search,
                     company_name: company_name_search,
                     ingredients: ingredients_search,
                     manual_link: manual_link_search,
-                }))
+                    cas_number: cas_number_search,
+                    signal_word: signal_word_search,
+                    pictograms: pictograms_search,
+                    hazard_statements: hazard_statements_search,
+                    precautionary_statements: precautionary_statements_search,
+                    storage_location: storage_location_search,
+                    include_deleted,
+                    page,
+                })))
+            },
+
+            (GET) (/low_stock) => {
+                Ok(ChemicalRequest::GetLowStock)
             },
 
             (GET) (/{id: u64}) => {
@@ -107,14 +291,16 @@ impl ChemicalRequest {
 
             (POST) (/) => {
                 let request_body = request.data().ok_or(Error::new(ErrorKind::Body))?;
-                let new_chemical: NewChemical = serde_json::from_reader(request_body)?;
+                let mut new_chemical: NewChemical = serde_json::from_reader(request_body)?;
+                new_chemical.validate()?;
 
                 Ok(ChemicalRequest::CreateChemical(new_chemical))
             },
 
             (POST) (/{id: u64}) => {
                 let request_body = request.data().ok_or(Error::new(ErrorKind::Body))?;
-                let update_chemical: PartialChemical = serde_json::from_reader(request_body)?;
+                let mut update_chemical: PartialChemical = serde_json::from_reader(request_body)?;
+                update_chemical.validate()?;
 
                 Ok(ChemicalRequest::UpdateChemical(id, update_chemical))
             },
@@ -123,6 +309,10 @@ impl ChemicalRequest {
                 Ok(ChemicalRequest::DeleteChemical(id))
             },
 
+            (POST) (/{id: u64}/restore) => {
+                Ok(ChemicalRequest::RestoreChemical(id))
+            },
+
             _ => {
                 warn!("Could not create a chemical request for the given rouille request");
                 Err(Error::new(ErrorKind::NotFound))
@@ -132,8 +322,9 @@ impl ChemicalRequest {
 }
 
 pub enum ChemicalResponse {
-    OneChemical(Chemical),
+    OneChemical(Box<Chemical>),
     ManyChemical(ChemicalList),
+    ManyLowStock(LowStockChemicalList),
     NoResponse,
 }
 
@@ -146,11 +337,22 @@ impl ChemicalResponse {
             ChemicalResponse::ManyChemical(chemicals) => {
                 rouille::Response::json(&chemicals)
             }
+            ChemicalResponse::ManyLowStock(chemicals) => {
+                rouille::Response::json(&chemicals)
+            }
             ChemicalResponse::NoResponse => rouille::Response::empty_204(),
         }
     }
 }
 
+/// An amount of a chemical kept somewhere, which is hidden from searches once
+/// `deleted_at` is set
+///
+/// `unparsed_amount` keeps the old free-form amount of entries whose amount
+/// could not be read into a quantity and unit, so that they can be fixed.
+///
+/// `received`, `opened` and `expires` are the dates the container arrived, was
+/// first opened and should no longer be used, if they are known.
 #[derive(Queryable, Serialize, Deserialize)]
 pub struct ChemicalInventory {
     pub id: u64,
@@ -158,7 +360,13 @@ pub struct ChemicalInventory {
     pub custodian_id: u64,
     pub chemical_id: u64,
     pub storage_location: String,
-    pub amount: String,
+    pub quantity: f64,
+    pub unit: Unit,
+    pub unparsed_amount: Option<String>,
+    pub received: Option<NaiveDate>,
+    pub opened: Option<NaiveDate>,
+    pub expires: Option<NaiveDate>,
+    pub deleted_at: Option<NaiveDateTime>,
 }
 
 #[derive(Insertable, Serialize, Deserialize)]
@@ -168,7 +376,11 @@ pub struct NewChemicalInventory {
     pub custodian_id: u64,
     pub chemical_id: u64,
     pub storage_location: String,
-    pub amount: String,
+    pub quantity: f64,
+    pub unit: Unit,
+    pub received: Option<NaiveDate>,
+    pub opened: Option<NaiveDate>,
+    pub expires: Option<NaiveDate>,
 }
 
 #[derive(AsChangeset, Serialize, Deserialize)]
@@ -178,28 +390,259 @@ pub struct PartialChemicalInventory {
     pub custodian_id: Option<u64>,
     pub chemical_id: Option<u64>,
     pub storage_location: Option<String>,
-    pub amount: Option<String>,
+    pub quantity: Option<f64>,
+    pub unit: Option<Unit>,
+    #[serde(default, deserialize_with = "deserialize_nullable")]
+    pub received: Option<Option<NaiveDate>>,
+    #[serde(default, deserialize_with = "deserialize_nullable")]
+    pub opened: Option<Option<NaiveDate>>,
+    #[serde(default, deserialize_with = "deserialize_nullable")]
+    pub expires: Option<Option<NaiveDate>>,
+}
+
+impl ChemicalInventory {
+    pub fn amount(&self) -> Amount {
+        Amount {
+            quantity: self.quantity,
+            unit: self.unit,
+        }
+    }
+
+    /// The inventory entry as a resource, owned by its custodian and kept in
+    /// its storage location
+    pub fn resource(&self) -> Resource {
+        Resource {
+            owner_id: Some(self.custodian_id),
+            room: Some(self.storage_location.clone()),
+        }
+    }
+}
+
+impl NewChemicalInventory {
+    pub fn validate(&self) -> Result<(), Error> {
+        validate_quantity(self.quantity)?;
+        validate_dates(self.received, self.opened)
+    }
+
+    pub fn resource(&self) -> Resource {
+        Resource {
+            owner_id: Some(self.custodian_id),
+            room: Some(self.storage_location.clone()),
+        }
+    }
+}
+
+impl PartialChemicalInventory {
+    pub fn validate(&self) -> Result<(), Error> {
+        match self.quantity {
+            Some(quantity) => validate_quantity(quantity),
+            None => Ok(()),
+        }
+    }
+
+    /// Check the dates an existing entry ends up with once updated
+    pub fn validate_dates(
+        &self,
+        existing: &ChemicalInventory,
+    ) -> Result<(), Error> {
+        validate_dates(
+            self.received.unwrap_or(existing.received),
+            self.opened.unwrap_or(existing.opened),
+        )
+    }
+
+    /// The resource an existing entry becomes once updated
+    pub fn resource(&self, existing: &ChemicalInventory) -> Resource {
+        Resource {
+            owner_id: Some(self.custodian_id.unwrap_or(existing.custodian_id)),
+            room: Some(
+                self.storage_location
+                    .clone()
+                    .unwrap_or_else(|| existing.storage_location.clone()),
+            ),
+        }
+    }
+}
+
+/// Quantities can not be negative, or infinite
+fn validate_quantity(quantity: f64) -> Result<(), Error> {
+    if quantity.is_finite() && quantity >= 0.0 {
+        Ok(())
+    } else {
+        Err(Error::new(ErrorKind::InvalidQuantity))
+    }
+}
+
+/// A minimum needs both a quantity and a unit, or neither
+fn validate_minimum(
+    quantity: Option<f64>,
+    unit: Option<Unit>,
+) -> Result<(), Error> {
+    match (quantity, unit) {
+        (Some(quantity), Some(_)) => validate_quantity(quantity),
+        (None, None) => Ok(()),
+        _ => Err(Error::new(ErrorKind::InvalidMinimum)),
+    }
+}
+
+/// CAS numbers are two to seven digits, then two digits, then a check digit,
+/// joined by hyphens, such as `7732-18-5`
+///
+/// The check digit is the last digit of the sum of the other digits, each
+/// multiplied by its position counting from the right.
+fn validate_cas_number(cas_number: &str) -> Result<(), Error> {
+    let parts: Vec<&str> = cas_number.split('-').collect();
+    let well_formed = match parts.as_slice() {
+        [first, second, check] => {
+            (2..=7).contains(&first.len())
+                && second.len() == 2
+                && check.len() == 1
+                && parts
+                    .iter()
+                    .all(|part| part.chars().all(|c| c.is_ascii_digit()))
+        }
+        _ => false,
+    };
+
+    let digits: Vec<u32> =
+        cas_number.chars().filter_map(|c| c.to_digit(10)).collect();
+
+    match digits.split_last() {
+        Some((check, rest)) if well_formed => {
+            let sum: u32 =
+                rest.iter().rev().zip(1..).map(|(digit, i)| digit * i).sum();
+
+            if sum % 10 == *check {
+                Ok(())
+            } else {
+                Err(Error::new(ErrorKind::InvalidCasNumber))
+            }
+        }
+        _ => Err(Error::new(ErrorKind::InvalidCasNumber)),
+    }
+}
+
+/// Containers can not be opened before they are received
+fn validate_dates(
+    received: Option<NaiveDate>,
+    opened: Option<NaiveDate>,
+) -> Result<(), Error> {
+    match (received, opened) {
+        (Some(received), Some(opened)) if opened < received => {
+            Err(Error::new(ErrorKind::InvalidDates))
+        }
+        _ => Ok(()),
+    }
 }
 
+/// Search for inventory entries
+///
+/// `amount` is searched with amounts like `gt,500 mL`, which match entries in
+/// any unit the amount can be converted to, such as `1 L`. Dates are searched
+/// like `between,2019-01-01,2019-06-30`.
 pub struct SearchChemicalInventory {
-    pub purchaser_id: Search<u64>,
-    pub custodian_id: Search<u64>,
-    pub chemical_id: Search<u64>,
+    pub purchaser_id: Search<Digits<u64>>,
+    pub custodian_id: Search<Digits<u64>>,
+    pub chemical_id: Search<Digits<u64>>,
     pub storage_location: Search<String>,
-    pub amount: Search<String>,
+    pub amount: Search<Amount>,
+    pub received: NullableSearch<NaiveDate>,
+    pub opened: NullableSearch<NaiveDate>,
+    pub expires: NullableSearch<NaiveDate>,
+    pub include_deleted: bool,
+    pub page: Page,
 }
 
+/// One page of inventory entries, out of `total` matching a search
+///
+/// `next` is the offset of the page after this one, if there is one.
 #[derive(Serialize, Deserialize)]
 pub struct ChemicalInventoryList {
     pub entries: Vec<ChemicalInventory>,
+    pub total: u64,
+    pub next: Option<u64>,
+}
+
+/// The inventory entries in one storage location that have expired, or will
+/// expire soon, each soonest first
+#[derive(Serialize, Deserialize)]
+pub struct ExpiringLocation {
+    pub storage_location: String,
+    pub expired: Vec<ChemicalInventory>,
+    pub expiring: Vec<ChemicalInventory>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ExpiringLocationList {
+    pub locations: Vec<ExpiringLocation>,
+}
+
+/// Some of an inventory entry that was used up, and how much of the entry
+/// was left afterwards
+///
+/// `remaining` is in the unit of the inventory entry, which may differ from
+/// the unit the usage was recorded in. Updates to the amount of an entry are
+/// logged as usage too, with a negative quantity when they added to it.
+#[derive(Queryable, Serialize, Deserialize)]
+pub struct ChemicalUsage {
+    pub id: u64,
+    pub inventory_id: u64,
+    pub user_id: u64,
+    pub quantity: f64,
+    pub unit: Unit,
+    pub purpose: String,
+    pub remaining: f64,
+    pub used: NaiveDateTime,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct NewChemicalUsage {
+    pub quantity: f64,
+    pub unit: Unit,
+    pub purpose: String,
+}
+
+#[derive(Insertable)]
+#[table_name = "chemical_usage"]
+pub struct NewRawChemicalUsage {
+    pub inventory_id: u64,
+    pub user_id: u64,
+    pub quantity: f64,
+    pub unit: Unit,
+    pub purpose: String,
+    pub remaining: f64,
+    pub used: NaiveDateTime,
+}
+
+impl NewChemicalUsage {
+    pub fn validate(&self) -> Result<(), Error> {
+        validate_quantity(self.quantity)
+    }
+
+    pub fn amount(&self) -> Amount {
+        Amount {
+            quantity: self.quantity,
+            unit: self.unit,
+        }
+    }
+}
+
+/// The usage of an inventory entry, newest first
+#[derive(Serialize, Deserialize)]
+pub struct ChemicalUsageList {
+    pub usages: Vec<ChemicalUsage>,
 }
 
 pub enum ChemicalInventoryRequest {
-    SearchInventory(SearchChemicalInventory),
+    SearchInventory(Box<SearchChemicalInventory>),
     GetInventory(u64),
     CreateInventory(NewChemicalInventory),
     UpdateInventory(u64, PartialChemicalInventory),
     DeleteInventory(u64),
+    RestoreInventory(u64),
+    GetUsage(u64),
+    RecordUsage(u64, NewChemicalUsage),
+    GetExpiring(u32, Search<String>),
 }
 
 impl ChemicalInventoryRequest {
@@ -216,6 +659,11 @@ impl ChemicalInventoryRequest {
                 let mut chemical_id_search = Search::NoSearch;
                 let mut storage_location_search = Search::NoSearch;
                 let mut amount_search = Search::NoSearch;
+                let mut received_search = NullableSearch::NoSearch;
+                let mut opened_search = NullableSearch::NoSearch;
+                let mut expires_search = NullableSearch::NoSearch;
+                let mut include_deleted = false;
+                let mut page = Page::default();
 
                 for (field, query) in url_queries {
                     match field.as_ref() as &str {
@@ -227,18 +675,56 @@ impl ChemicalInventoryRequest {
                             Search::from_query(query.as_ref())?,
                         "storage_location" => storage_location_search
                             = Search::from_query(query.as_ref())?,
-                        "amount" => amount_search = Search::from_query(query.as_ref())?,
+                        "amount" => {
+                            amount_search = Search::from_query(query.as_ref())?;
+                            if !Amount::all_compatible(amount_search.terms()) {
+                                return Err(Error::new(ErrorKind::Url));
+                            }
+                        },
+                        "received" => received_search =
+                            NullableSearch::from_query(query.as_ref())?,
+                        "opened" => opened_search =
+                            NullableSearch::from_query(query.as_ref())?,
+                        "expires" => expires_search =
+                            NullableSearch::from_query(query.as_ref())?,
+                        "include_deleted" => include_deleted = query.parse()?,
+                        "limit" | "offset" | "sort" =>
+                            page.add_query(field.as_ref(), query.as_ref())?,
                         _ => return Err(Error::new(ErrorKind::Url)),
                     }
                 }
 
-                Ok(ChemicalInventoryRequest::SearchInventory(SearchChemicalInventory {
+                Ok(ChemicalInventoryRequest::SearchInventory(Box::new(SearchChemicalInventory {
                     purchaser_id: purchaser_id_search,
                     custodian_id: custodian_id_search,
                     chemical_id: chemical_id_search,
                     storage_location: storage_location_search,
                     amount: amount_search,
-                }))
+                    received: received_search,
+                    opened: opened_search,
+                    expires: expires_search,
+                    include_deleted,
+                    page,
+                })))
+            },
+
+            (GET) (/expiring) => {
+                let mut days = DEFAULT_EXPIRING_DAYS;
+                let mut storage_location_search = Search::NoSearch;
+
+                for (field, query) in url_queries {
+                    match field.as_ref() as &str {
+                        "days" => days = query.parse()?,
+                        "storage_location" => storage_location_search
+                            = Search::from_query(query.as_ref())?,
+                        _ => return Err(Error::new(ErrorKind::Url)),
+                    }
+                }
+
+                Ok(ChemicalInventoryRequest::GetExpiring(
+                        days,
+                        storage_location_search
+                ))
             },
 
             (GET) (/{permission_id: u64}) => {
@@ -250,6 +736,7 @@ impl ChemicalInventoryRequest {
                     .ok_or(Error::new(ErrorKind::Body))?;
                 let new_chemical_inventory: NewChemicalInventory =
                     serde_json::from_reader(request_body)?;
+                new_chemical_inventory.validate()?;
                 Ok(ChemicalInventoryRequest::CreateInventory(new_chemical_inventory))
             },
 
@@ -258,6 +745,7 @@ impl ChemicalInventoryRequest {
                     .ok_or(Error::new(ErrorKind::Body))?;
                 let update_chemical_inventory: PartialChemicalInventory =
                     serde_json::from_reader(request_body)?;
+                update_chemical_inventory.validate()?;
 
                 Ok(ChemicalInventoryRequest::UpdateInventory(
                         id,
@@ -269,6 +757,24 @@ impl ChemicalInventoryRequest {
                 Ok(ChemicalInventoryRequest::DeleteInventory(id))
             },
 
+            (POST) (/{id: u64}/restore) => {
+                Ok(ChemicalInventoryRequest::RestoreInventory(id))
+            },
+
+            (GET) (/{id: u64}/usage) => {
+                Ok(ChemicalInventoryRequest::GetUsage(id))
+            },
+
+            (POST) (/{id: u64}/usage) => {
+                let request_body = request.data()
+                    .ok_or(Error::new(ErrorKind::Body))?;
+                let new_chemical_usage: NewChemicalUsage =
+                    serde_json::from_reader(request_body)?;
+                new_chemical_usage.validate()?;
+
+                Ok(ChemicalInventoryRequest::RecordUsage(id, new_chemical_usage))
+            },
+
             _ => {
                 warn!("Could not create a chemical inventory request");
                 Err(Error::new(ErrorKind::NotFound))
@@ -280,6 +786,9 @@ impl ChemicalInventoryRequest {
 pub enum ChemicalInventoryResponse {
     OneInventoryEntry(ChemicalInventory),
     ManyInventoryEntries(ChemicalInventoryList),
+    OneUsage(ChemicalUsage),
+    ManyUsages(ChemicalUsageList),
+    ManyExpiring(ExpiringLocationList),
     NoResponse,
 }
 
@@ -292,9 +801,629 @@ impl ChemicalInventoryResponse {
             ChemicalInventoryResponse::ManyInventoryEntries(entries) => {
                 rouille::Response::json(&entries)
             }
+            ChemicalInventoryResponse::OneUsage(usage) => {
+                rouille::Response::json(&usage)
+            }
+            ChemicalInventoryResponse::ManyUsages(usages) => {
+                rouille::Response::json(&usages)
+            }
+            ChemicalInventoryResponse::ManyExpiring(locations) => {
+                rouille::Response::json(&locations)
+            }
             ChemicalInventoryResponse::NoResponse => {
                 rouille::Response::empty_204()
             }
         }
     }
 }
+
+/// A unit that chemicals are measured in
+///
+/// Units can only be converted to others that measure the same thing, so
+/// volumes can not be converted to masses.
+#[derive(
+    AsExpression,
+    FromSqlRow,
+    Serialize,
+    Deserialize,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+)]
+#[sql_type = "Varchar"]
+pub enum Unit {
+    #[serde(rename = "mL")]
+    Milliliters,
+    #[serde(rename = "L")]
+    Liters,
+    #[serde(rename = "gal")]
+    Gallons,
+    #[serde(rename = "g")]
+    Grams,
+    #[serde(rename = "kg")]
+    Kilograms,
+    #[serde(rename = "lb")]
+    Pounds,
+}
+
+pub const UNITS: [Unit; 6] = [
+    Unit::Milliliters,
+    Unit::Liters,
+    Unit::Gallons,
+    Unit::Grams,
+    Unit::Kilograms,
+    Unit::Pounds,
+];
+
+impl Unit {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Unit::Milliliters => "mL",
+            Unit::Liters => "L",
+            Unit::Gallons => "gal",
+            Unit::Grams => "g",
+            Unit::Kilograms => "kg",
+            Unit::Pounds => "lb",
+        }
+    }
+
+    pub fn is_volume(self) -> bool {
+        match self {
+            Unit::Milliliters | Unit::Liters | Unit::Gallons => true,
+            Unit::Grams | Unit::Kilograms | Unit::Pounds => false,
+        }
+    }
+
+    /// Whether amounts in this unit can be converted to `other`
+    pub fn is_compatible(self, other: Unit) -> bool {
+        self.is_volume() == other.is_volume()
+    }
+
+    /// How many milliliters or grams one of this unit is
+    fn base_size(self) -> f64 {
+        match self {
+            Unit::Milliliters => 1.0,
+            Unit::Liters => 1000.0,
+            Unit::Gallons => 3785.411784,
+            Unit::Grams => 1.0,
+            Unit::Kilograms => 1000.0,
+            Unit::Pounds => 453.59237,
+        }
+    }
+}
+
+impl std::fmt::Display for Unit {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.symbol())
+    }
+}
+
+impl std::str::FromStr for Unit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Unit, String> {
+        match s.trim().to_lowercase().as_ref() {
+            "ml" | "milliliter" | "milliliters" => Ok(Unit::Milliliters),
+            "l" | "liter" | "liters" | "litre" | "litres" => Ok(Unit::Liters),
+            "gal" | "gallon" | "gallons" => Ok(Unit::Gallons),
+            "g" | "gram" | "grams" => Ok(Unit::Grams),
+            "kg" | "kilogram" | "kilograms" => Ok(Unit::Kilograms),
+            "lb" | "lbs" | "pound" | "pounds" => Ok(Unit::Pounds),
+            _ => Err(format!("Unknown unit: {}", s)),
+        }
+    }
+}
+
+impl ToSql<Varchar, Mysql> for Unit {
+    fn to_sql<W: Write>(
+        &self,
+        out: &mut Output<W, Mysql>,
+    ) -> serialize::Result {
+        <str as ToSql<Varchar, Mysql>>::to_sql(self.symbol(), out)
+    }
+}
+
+impl FromSql<Varchar, Mysql> for Unit {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Unit> {
+        let symbol = <String as FromSql<Varchar, Mysql>>::from_sql(bytes)?;
+        Ok(symbol.parse()?)
+    }
+}
+
+/// A quantity of a chemical in some unit, such as `500 mL`
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Amount {
+    pub quantity: f64,
+    pub unit: Unit,
+}
+
+impl Amount {
+    /// The same amount in another unit, if it can be converted
+    pub fn convert(self, unit: Unit) -> Option<Amount> {
+        if self.unit.is_compatible(unit) {
+            Some(Amount {
+                quantity: self.quantity * self.unit.base_size()
+                    / unit.base_size(),
+                unit,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Whether every amount can be converted to the same units
+    pub fn all_compatible(amounts: Vec<&Amount>) -> bool {
+        amounts
+            .windows(2)
+            .all(|pair| pair[0].unit.is_compatible(pair[1].unit))
+    }
+}
+
+impl std::fmt::Display for Amount {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} {}", self.quantity, self.unit)
+    }
+}
+
+impl std::str::FromStr for Amount {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Amount, String> {
+        let s = s.trim();
+        let split = s.find(|c: char| c.is_alphabetic()).unwrap_or(s.len());
+
+        let quantity = s[..split]
+            .trim()
+            .parse()
+            .map_err(|_| format!("Invalid quantity: {}", s))?;
+        let unit = s[split..].parse()?;
+
+        Ok(Amount { quantity, unit })
+    }
+}
+
+/// The word on a hazardous chemical's label saying how severe its hazards are
+#[derive(
+    AsExpression,
+    FromSqlRow,
+    Serialize,
+    Deserialize,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+)]
+#[sql_type = "Varchar"]
+pub enum SignalWord {
+    Danger,
+    Warning,
+}
+
+impl SignalWord {
+    pub fn word(self) -> &'static str {
+        match self {
+            SignalWord::Danger => "Danger",
+            SignalWord::Warning => "Warning",
+        }
+    }
+}
+
+impl std::fmt::Display for SignalWord {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.word())
+    }
+}
+
+impl std::str::FromStr for SignalWord {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<SignalWord, String> {
+        match s.trim().to_lowercase().as_ref() {
+            "danger" => Ok(SignalWord::Danger),
+            "warning" => Ok(SignalWord::Warning),
+            _ => Err(format!("Unknown signal word: {}", s)),
+        }
+    }
+}
+
+impl ToSql<Varchar, Mysql> for SignalWord {
+    fn to_sql<W: Write>(
+        &self,
+        out: &mut Output<W, Mysql>,
+    ) -> serialize::Result {
+        <str as ToSql<Varchar, Mysql>>::to_sql(self.word(), out)
+    }
+}
+
+impl FromSql<Varchar, Mysql> for SignalWord {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<SignalWord> {
+        let word = <String as FromSql<Varchar, Mysql>>::from_sql(bytes)?;
+        Ok(word.parse()?)
+    }
+}
+
+/// A GHS hazard pictogram, named after the hazard it warns of
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Pictogram {
+    #[serde(rename = "GHS01")]
+    Explosive,
+    #[serde(rename = "GHS02")]
+    Flammable,
+    #[serde(rename = "GHS03")]
+    Oxidizer,
+    #[serde(rename = "GHS04")]
+    CompressedGas,
+    #[serde(rename = "GHS05")]
+    Corrosive,
+    #[serde(rename = "GHS06")]
+    Toxic,
+    #[serde(rename = "GHS07")]
+    Irritant,
+    #[serde(rename = "GHS08")]
+    HealthHazard,
+    #[serde(rename = "GHS09")]
+    Environment,
+}
+
+pub const PICTOGRAMS: [Pictogram; 9] = [
+    Pictogram::Explosive,
+    Pictogram::Flammable,
+    Pictogram::Oxidizer,
+    Pictogram::CompressedGas,
+    Pictogram::Corrosive,
+    Pictogram::Toxic,
+    Pictogram::Irritant,
+    Pictogram::HealthHazard,
+    Pictogram::Environment,
+];
+
+impl Pictogram {
+    pub fn code(self) -> &'static str {
+        match self {
+            Pictogram::Explosive => "GHS01",
+            Pictogram::Flammable => "GHS02",
+            Pictogram::Oxidizer => "GHS03",
+            Pictogram::CompressedGas => "GHS04",
+            Pictogram::Corrosive => "GHS05",
+            Pictogram::Toxic => "GHS06",
+            Pictogram::Irritant => "GHS07",
+            Pictogram::HealthHazard => "GHS08",
+            Pictogram::Environment => "GHS09",
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Pictogram::Explosive => "explosive",
+            Pictogram::Flammable => "flammable",
+            Pictogram::Oxidizer => "oxidizer",
+            Pictogram::CompressedGas => "compressed_gas",
+            Pictogram::Corrosive => "corrosive",
+            Pictogram::Toxic => "toxic",
+            Pictogram::Irritant => "irritant",
+            Pictogram::HealthHazard => "health_hazard",
+            Pictogram::Environment => "environment",
+        }
+    }
+}
+
+impl std::fmt::Display for Pictogram {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.code())
+    }
+}
+
+impl std::str::FromStr for Pictogram {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Pictogram, String> {
+        let s = s.trim();
+
+        PICTOGRAMS
+            .iter()
+            .find(|pictogram| {
+                pictogram.code().eq_ignore_ascii_case(s)
+                    || pictogram.name().eq_ignore_ascii_case(s)
+            })
+            .copied()
+            .ok_or_else(|| format!("Unknown pictogram: {}", s))
+    }
+}
+
+/// The pictograms on a chemical's label, stored as a comma separated list of
+/// their codes
+#[derive(
+    AsExpression,
+    FromSqlRow,
+    Serialize,
+    Deserialize,
+    Debug,
+    Clone,
+    Default,
+    PartialEq,
+)]
+#[sql_type = "Varchar"]
+#[serde(transparent)]
+pub struct Pictograms(pub Vec<Pictogram>);
+
+impl Pictograms {
+    /// Drop every pictogram listed before, keeping the first of each
+    fn dedup(&mut self) {
+        let mut seen = Vec::new();
+        self.0.retain(|pictogram| {
+            if seen.contains(pictogram) {
+                false
+            } else {
+                seen.push(*pictogram);
+                true
+            }
+        });
+    }
+}
+
+impl ToSql<Varchar, Mysql> for Pictograms {
+    fn to_sql<W: Write>(
+        &self,
+        out: &mut Output<W, Mysql>,
+    ) -> serialize::Result {
+        let codes: Vec<&str> =
+            self.0.iter().map(|pictogram| pictogram.code()).collect();
+        <str as ToSql<Varchar, Mysql>>::to_sql(&codes.join(","), out)
+    }
+}
+
+impl FromSql<Varchar, Mysql> for Pictograms {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Pictograms> {
+        let codes = <String as FromSql<Varchar, Mysql>>::from_sql(bytes)?;
+        let pictograms = codes
+            .split(',')
+            .filter(|code| !code.is_empty())
+            .map(str::parse)
+            .collect::<Result<_, _>>()?;
+        Ok(Pictograms(pictograms))
+    }
+}
+
+/// The prefixes of GHS hazard statement codes, including the EU's own
+const HAZARD_PREFIXES: &[&str] = &["H", "EUH"];
+const PRECAUTIONARY_PREFIXES: &[&str] = &["P"];
+
+/// GHS hazard or precautionary statements, stored as a comma separated list
+/// of their codes
+///
+/// Statements that are printed together are joined with `+`, such as
+/// `P303+P361+P353`.
+#[derive(
+    AsExpression,
+    FromSqlRow,
+    Serialize,
+    Deserialize,
+    Debug,
+    Clone,
+    Default,
+    PartialEq,
+)]
+#[sql_type = "Varchar"]
+#[serde(transparent)]
+pub struct Statements(pub Vec<String>);
+
+impl Statements {
+    /// Check that every statement is made of codes starting with one of
+    /// `prefixes`, followed by three digits
+    fn validate(&self, prefixes: &[&str]) -> Result<(), Error> {
+        let is_code = |code: &str| {
+            prefixes
+                .iter()
+                .any(|prefix| match code.strip_prefix(prefix) {
+                    Some(number) => {
+                        number.len() == 3
+                            && number.chars().all(|c| c.is_ascii_digit())
+                    }
+                    None => false,
+                })
+        };
+
+        if self
+            .0
+            .iter()
+            .all(|statement| statement.split('+').all(is_code))
+        {
+            Ok(())
+        } else {
+            Err(Error::new(ErrorKind::InvalidStatement))
+        }
+    }
+}
+
+impl ToSql<Varchar, Mysql> for Statements {
+    fn to_sql<W: Write>(
+        &self,
+        out: &mut Output<W, Mysql>,
+    ) -> serialize::Result {
+        <str as ToSql<Varchar, Mysql>>::to_sql(&self.0.join(","), out)
+    }
+}
+
+impl FromSql<Varchar, Mysql> for Statements {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Statements> {
+        let codes = <String as FromSql<Varchar, Mysql>>::from_sql(bytes)?;
+        Ok(Statements(
+            codes
+                .split(',')
+                .filter(|code| !code.is_empty())
+                .map(str::to_owned)
+                .collect(),
+        ))
+    }
+}
+
+#[test]
+fn parse_amount_works() {
+    assert_eq!(
+        "500 mL".parse(),
+        Ok(Amount {
+            quantity: 500.0,
+            unit: Unit::Milliliters,
+        })
+    );
+    assert_eq!(
+        " 2.5kg ".parse(),
+        Ok(Amount {
+            quantity: 2.5,
+            unit: Unit::Kilograms,
+        })
+    );
+    assert!("500".parse::<Amount>().is_err());
+    assert!("some mL".parse::<Amount>().is_err());
+    assert!("500 cups".parse::<Amount>().is_err());
+}
+
+#[test]
+fn convert_amount_works() {
+    let amount = Amount {
+        quantity: 2.0,
+        unit: Unit::Liters,
+    };
+
+    assert_eq!(
+        amount.convert(Unit::Milliliters),
+        Some(Amount {
+            quantity: 2000.0,
+            unit: Unit::Milliliters,
+        })
+    );
+    assert_eq!(amount.convert(Unit::Grams), None);
+
+    let pounds = Amount {
+        quantity: 1.0,
+        unit: Unit::Kilograms,
+    }
+    .convert(Unit::Pounds)
+    .unwrap();
+    assert!((pounds.quantity - 2.20462).abs() < 0.0001);
+}
+
+#[test]
+fn validate_quantity_works() {
+    assert!(validate_quantity(0.0).is_ok());
+    assert!(validate_quantity(12.5).is_ok());
+    assert!(validate_quantity(-1.0).is_err());
+    assert!(validate_quantity(f64::NAN).is_err());
+    assert!(validate_quantity(f64::INFINITY).is_err());
+}
+
+#[test]
+fn validate_minimum_works() {
+    assert!(validate_minimum(Some(1.0), Some(Unit::Liters)).is_ok());
+    assert!(validate_minimum(None, None).is_ok());
+    assert!(validate_minimum(Some(-1.0), Some(Unit::Liters)).is_err());
+    assert!(validate_minimum(Some(1.0), None).is_err());
+    assert!(validate_minimum(None, Some(Unit::Liters)).is_err());
+}
+
+#[test]
+fn partial_chemical_clears_minimum_with_null() {
+    let mut partial: PartialChemical = serde_json::from_str(
+        r#"{"minimum_quantity": null, "minimum_unit": null}"#,
+    )
+    .unwrap();
+
+    assert_eq!(partial.minimum_quantity, Some(None));
+    assert_eq!(partial.minimum_unit, Some(None));
+    assert!(partial.validate().is_ok());
+
+    let mut partial: PartialChemical =
+        serde_json::from_str(r#"{"minimum_quantity": null}"#).unwrap();
+
+    assert!(partial.validate().is_err());
+}
+
+#[test]
+fn partial_chemical_clears_hazards_with_null() {
+    let mut partial: PartialChemical =
+        serde_json::from_str(r#"{"cas_number": null, "signal_word": null}"#)
+            .unwrap();
+
+    assert_eq!(partial.cas_number, Some(None));
+    assert_eq!(partial.signal_word, Some(None));
+    assert!(partial.validate().is_ok());
+}
+
+#[test]
+fn validate_dates_works() {
+    let date = |day| NaiveDate::from_ymd_opt(2019, 9, day);
+
+    assert!(validate_dates(date(1), date(1)).is_ok());
+    assert!(validate_dates(date(1), date(2)).is_ok());
+    assert!(validate_dates(None, date(2)).is_ok());
+    assert!(validate_dates(date(2), None).is_ok());
+    assert!(validate_dates(date(2), date(1)).is_err());
+}
+
+#[test]
+fn partial_chemical_inventory_clears_dates_with_null() {
+    let partial: PartialChemicalInventory =
+        serde_json::from_str(r#"{"received": null, "expires": "2019-09-23"}"#)
+            .unwrap();
+
+    assert_eq!(partial.received, Some(None));
+    assert_eq!(partial.opened, None);
+    assert_eq!(partial.expires, Some(NaiveDate::from_ymd_opt(2019, 9, 23)));
+}
+
+#[test]
+fn validate_cas_number_works() {
+    assert!(validate_cas_number("7732-18-5").is_ok());
+    assert!(validate_cas_number("64-17-5").is_ok());
+    assert!(validate_cas_number("7647-01-0").is_ok());
+    assert!(validate_cas_number("7732-18-4").is_err());
+    assert!(validate_cas_number("7732185").is_err());
+    assert!(validate_cas_number("7-18-5").is_err());
+    assert!(validate_cas_number("7732-1a-5").is_err());
+    assert!(validate_cas_number("").is_err());
+}
+
+#[test]
+fn validate_statements_works() {
+    let statements =
+        |codes: &[&str]| Statements(codes.iter().map(|&c| c.into()).collect());
+
+    assert!(statements(&["H225", "H319", "EUH066"])
+        .validate(HAZARD_PREFIXES)
+        .is_ok());
+    assert!(statements(&["P210", "P303+P361+P353"])
+        .validate(PRECAUTIONARY_PREFIXES)
+        .is_ok());
+    assert!(statements(&[]).validate(HAZARD_PREFIXES).is_ok());
+    assert!(statements(&["P210"]).validate(HAZARD_PREFIXES).is_err());
+    assert!(statements(&["H22"]).validate(HAZARD_PREFIXES).is_err());
+    assert!(statements(&["H225+"]).validate(HAZARD_PREFIXES).is_err());
+    assert!(statements(&["Highly flammable"])
+        .validate(HAZARD_PREFIXES)
+        .is_err());
+}
+
+#[test]
+fn pictograms_dedup_works() {
+    let mut pictograms = Pictograms(vec![
+        Pictogram::Flammable,
+        Pictogram::Corrosive,
+        Pictogram::Flammable,
+    ]);
+
+    pictograms.dedup();
+
+    assert_eq!(
+        pictograms,
+        Pictograms(vec![Pictogram::Flammable, Pictogram::Corrosive])
+    );
+}
+
+#[test]
+fn parse_pictogram_works() {
+    assert_eq!("GHS02".parse(), Ok(Pictogram::Flammable));
+    assert_eq!(" flammable ".parse(), Ok(Pictogram::Flammable));
+    assert_eq!("Health_Hazard".parse(), Ok(Pictogram::HealthHazard));
+    assert!("GHS10".parse::<Pictogram>().is_err());
+}
@@ -1,29 +1,53 @@
+use std::collections::HashMap;
+
 use diesel;
 use diesel::mysql::types::Unsigned;
+use diesel::mysql::Mysql;
 use diesel::mysql::MysqlConnection;
 use diesel::query_builder::AsQuery;
 use diesel::sql_types;
+use diesel::BoolExpressionMethods;
+use diesel::Connection;
 use diesel::ExpressionMethods;
+use diesel::JoinOnDsl;
 use diesel::QueryDsl;
 use diesel::RunQueryDsl;
-use diesel::TextExpressionMethods;
+
+use chrono::offset::Local;
+use chrono::Duration;
+use chrono::NaiveDate;
+use chrono::NaiveDateTime;
 
 use crate::errors::{Error, ErrorKind};
 
-use crate::search::Search;
+use crate::access::models::{AccessLevel, AccessScope, SearchAccess};
+use crate::access::requests::{check_to_run, check_to_search};
+
+use crate::audit::requests::record_change;
 
-use crate::access::requests::check_to_run;
+use crate::search::{Condition, Search};
 
 use super::models::{
-    Chemical, ChemicalInventory, ChemicalInventoryList,
+    Amount, Chemical, ChemicalInventory, ChemicalInventoryList,
     ChemicalInventoryRequest, ChemicalInventoryResponse, ChemicalList,
-    ChemicalRequest, ChemicalResponse, NewChemical, NewChemicalInventory,
-    PartialChemical, PartialChemicalInventory, SearchChemical,
-    SearchChemicalInventory,
+    ChemicalRequest, ChemicalResponse, ChemicalUsage, ChemicalUsageList,
+    ExpiringLocation, ExpiringLocationList, LowStockChemical,
+    LowStockChemicalList, LowStockEntry, NewChemical, NewChemicalInventory,
+    NewChemicalUsage, NewRawChemicalUsage, PartialChemical,
+    PartialChemicalInventory, SearchChemical, SearchChemicalInventory, UNITS,
 };
 
 use super::schema::chemical as chemical_schema;
 use super::schema::chemical_inventory as chemical_inventory_schema;
+use super::schema::chemical_usage as chemical_usage_schema;
+
+use crate::users::schema::users as users_schema;
+
+/// How far below zero the quantity left of an entry can be rounded up to zero
+const QUANTITY_TOLERANCE: f64 = 1e-9;
+
+/// The purpose logged for updates that change the amount of an entry
+const ADJUSTMENT_PURPOSE: &str = "Adjusted by hand";
 
 pub fn handle_chemical(
     request: ChemicalRequest,
@@ -35,9 +59,10 @@ pub fn handle_chemical(
             match check_to_run(
                 requested_user,
                 "GetChemical",
+                None,
                 database_connection,
             ) {
-                Ok(()) => search_chemical(chemical, database_connection)
+                Ok(()) => search_chemical(*chemical, database_connection)
                     .map(|c| ChemicalResponse::ManyChemical(c)),
                 Err(e) => Err(e),
             }
@@ -46,10 +71,11 @@ pub fn handle_chemical(
             match check_to_run(
                 requested_user,
                 "GetChemical",
+                None,
                 database_connection,
             ) {
                 Ok(()) => get_chemical(id, database_connection)
-                    .map(|c| ChemicalResponse::OneChemical(c)),
+                    .map(|c| ChemicalResponse::OneChemical(Box::new(c))),
                 Err(e) => Err(e),
             }
         }
@@ -57,10 +83,25 @@ pub fn handle_chemical(
             match check_to_run(
                 requested_user,
                 "CreateChemical",
+                None,
                 database_connection,
             ) {
-                Ok(()) => create_chemical(chemical, database_connection)
-                    .map(|c| ChemicalResponse::OneChemical(c)),
+                Ok(()) => database_connection
+                    .transaction::<_, Error, _>(|| {
+                        let created =
+                            create_chemical(chemical, database_connection)?;
+                        record_change(
+                            requested_user,
+                            "CreateChemical",
+                            "chemical",
+                            created.id,
+                            None,
+                            Some(&created),
+                            database_connection,
+                        )?;
+                        Ok(created)
+                    })
+                    .map(|c| ChemicalResponse::OneChemical(Box::new(c))),
                 Err(e) => Err(e),
             }
         }
@@ -68,9 +109,24 @@ pub fn handle_chemical(
             match check_to_run(
                 requested_user,
                 "UpdateChemical",
+                None,
                 database_connection,
             ) {
-                Ok(()) => update_chemical(id, chemical, database_connection)
+                Ok(()) => database_connection
+                    .transaction::<_, Error, _>(|| {
+                        let before = get_chemical(id, database_connection)?;
+                        update_chemical(id, chemical, database_connection)?;
+                        let after = get_chemical(id, database_connection)?;
+                        record_change(
+                            requested_user,
+                            "UpdateChemical",
+                            "chemical",
+                            id,
+                            Some(&before),
+                            Some(&after),
+                            database_connection,
+                        )
+                    })
                     .map(|_| ChemicalResponse::NoResponse),
                 Err(e) => Err(e),
             }
@@ -79,13 +135,62 @@ pub fn handle_chemical(
             match check_to_run(
                 requested_user,
                 "DeleteChemical",
+                None,
                 database_connection,
             ) {
-                Ok(()) => delete_chemical(id, database_connection)
+                Ok(()) => database_connection
+                    .transaction::<_, Error, _>(|| {
+                        let before = get_chemical(id, database_connection)?;
+                        delete_chemical(id, database_connection)?;
+                        let after = get_chemical(id, database_connection)?;
+                        record_change(
+                            requested_user,
+                            "DeleteChemical",
+                            "chemical",
+                            id,
+                            Some(&before),
+                            Some(&after),
+                            database_connection,
+                        )
+                    })
                     .map(|_| ChemicalResponse::NoResponse),
                 Err(e) => Err(e),
             }
         }
+        ChemicalRequest::RestoreChemical(id) => {
+            check_to_run(
+                requested_user,
+                "RestoreChemical",
+                None,
+                database_connection,
+            )?;
+            database_connection
+                .transaction::<_, Error, _>(|| {
+                    let before = get_chemical(id, database_connection)?;
+                    restore_chemical(id, database_connection)?;
+                    let after = get_chemical(id, database_connection)?;
+                    record_change(
+                        requested_user,
+                        "RestoreChemical",
+                        "chemical",
+                        id,
+                        Some(&before),
+                        Some(&after),
+                        database_connection,
+                    )
+                })
+                .map(|_| ChemicalResponse::NoResponse)
+        }
+        ChemicalRequest::GetLowStock => {
+            check_to_run(
+                requested_user,
+                "GetLowStockChemicals",
+                None,
+                database_connection,
+            )?;
+            get_low_stock_chemicals(database_connection)
+                .map(|c| ChemicalResponse::ManyLowStock(c))
+        }
     }
 }
 
@@ -93,84 +198,124 @@ pub(crate) fn search_chemical(
     chemical_search: SearchChemical,
     database_connection: &MysqlConnection,
 ) -> Result<ChemicalList, Error> {
-    let mut chemical_query = chemical_schema::table.as_query().into_boxed();
-
-    match chemical_search.name {
-        Search::Partial(s) => {
-            chemical_query = chemical_query
-                .filter(chemical_schema::name.like(format!("%%{}%", s)))
-        }
+    let total = chemical_query(&chemical_search)
+        .count()
+        .get_result::<i64>(database_connection)? as u64;
 
-        Search::Exact(s) => {
-            chemical_query = chemical_query.filter(chemical_schema::name.eq(s))
-        }
+    let mut chemical_query = chemical_query(&chemical_search);
 
-        Search::NoSearch => {}
+    if let Some(sort) = &chemical_search.page.sort {
+        chemical_query = match sort.field.as_ref() {
+            "id" => sort.order.order_by(chemical_query, chemical_schema::id),
+            "name" => {
+                sort.order.order_by(chemical_query, chemical_schema::name)
+            }
+            "purpose" => sort
+                .order
+                .order_by(chemical_query, chemical_schema::purpose),
+            "company_name" => sort
+                .order
+                .order_by(chemical_query, chemical_schema::company_name),
+            "ingredients" => sort
+                .order
+                .order_by(chemical_query, chemical_schema::ingredients),
+            "manual_link" => sort
+                .order
+                .order_by(chemical_query, chemical_schema::manual_link),
+            "cas_number" => sort
+                .order
+                .order_by(chemical_query, chemical_schema::cas_number),
+            "signal_word" => sort
+                .order
+                .order_by(chemical_query, chemical_schema::signal_word),
+            _ => return Err(Error::new(ErrorKind::Url)),
+        };
     }
 
-    match chemical_search.purpose {
-        Search::Partial(s) => {
-            chemical_query = chemical_query
-                .filter(chemical_schema::purpose.like(format!("%%{}%", s)))
-        }
+    let found_chemicals = chemical_search
+        .page
+        .paginate(chemical_query.then_order_by(chemical_schema::id.asc()))
+        .load::<Chemical>(database_connection)?;
+    let chemical_list = ChemicalList {
+        chemicals: found_chemicals,
+        total,
+        next: chemical_search.page.next(total),
+    };
 
-        Search::Exact(s) => {
-            chemical_query =
-                chemical_query.filter(chemical_schema::purpose.eq(s))
-        }
+    Ok(chemical_list)
+}
 
-        Search::NoSearch => {}
-    }
+/// Build a query for the chemicals matching a search, in no particular order
+fn chemical_query(
+    chemical_search: &SearchChemical,
+) -> chemical_schema::BoxedQuery<'_, Mysql> {
+    let mut chemical_query = chemical_schema::table.as_query().into_boxed();
 
-    match chemical_search.company_name {
-        Search::Partial(s) => {
-            chemical_query = chemical_query
-                .filter(chemical_schema::company_name.like(format!("%%{}%", s)))
-        }
+    chemical_query = chemical_search
+        .name
+        .filter_text(chemical_query, chemical_schema::name);
 
-        Search::Exact(s) => {
-            chemical_query =
-                chemical_query.filter(chemical_schema::company_name.eq(s))
-        }
+    chemical_query = chemical_search
+        .purpose
+        .filter_text(chemical_query, chemical_schema::purpose);
 
-        Search::NoSearch => {}
-    }
+    chemical_query = chemical_search
+        .company_name
+        .filter_text(chemical_query, chemical_schema::company_name);
 
-    match chemical_search.ingredients {
-        Search::Partial(s) => {
-            chemical_query = chemical_query
-                .filter(chemical_schema::ingredients.like(format!("%%{}%", s)))
-        }
+    chemical_query = chemical_search
+        .ingredients
+        .filter_text(chemical_query, chemical_schema::ingredients);
 
-        Search::Exact(s) => {
-            chemical_query =
-                chemical_query.filter(chemical_schema::ingredients.eq(s))
-        }
+    chemical_query = chemical_search
+        .manual_link
+        .filter_text(chemical_query, chemical_schema::manual_link);
+
+    chemical_query = chemical_search
+        .cas_number
+        .filter_text(chemical_query, chemical_schema::cas_number);
 
-        Search::NoSearch => {}
+    chemical_query = chemical_search
+        .signal_word
+        .filter(chemical_query, chemical_schema::signal_word);
+
+    // Pictograms are stored as a list of codes, so an exact search for one
+    // matches every chemical that has it
+    if let Some(pictograms_search) = chemical_search
+        .pictograms
+        .try_map(&|pictogram| Some(pictogram.code().to_owned()))
+    {
+        chemical_query = pictograms_search
+            .filter_list(chemical_query, chemical_schema::pictograms);
     }
 
-    match chemical_search.manual_link {
-        Search::Partial(s) => {
-            chemical_query = chemical_query
-                .filter(chemical_schema::manual_link.like(format!("%{}%", s)))
-        }
+    chemical_query = chemical_search
+        .hazard_statements
+        .filter_list(chemical_query, chemical_schema::hazard_statements);
 
-        Search::Exact(s) => {
-            chemical_query =
-                chemical_query.filter(chemical_schema::manual_link.eq(s))
-        }
+    chemical_query = chemical_search
+        .precautionary_statements
+        .filter_list(chemical_query, chemical_schema::precautionary_statements);
 
-        Search::NoSearch => {}
+    if let Some(condition) = chemical_search
+        .storage_location
+        .text_condition(chemical_inventory_schema::storage_location)
+    {
+        let stocked_chemical_ids = chemical_inventory_schema::table
+            .select(chemical_inventory_schema::chemical_id)
+            .filter(chemical_inventory_schema::deleted_at.is_null())
+            .filter(condition);
+
+        chemical_query = chemical_query
+            .filter(chemical_schema::id.eq_any(stocked_chemical_ids));
     }
 
-    let found_chemicals =
-        chemical_query.load::<Chemical>(database_connection)?;
-    let chemical_list = ChemicalList {
-        chemicals: found_chemicals,
-    };
+    if !chemical_search.include_deleted {
+        chemical_query =
+            chemical_query.filter(chemical_schema::deleted_at.is_null());
+    }
 
-    Ok(chemical_list)
+    chemical_query
 }
 
 pub(crate) fn get_chemical(
@@ -220,16 +365,128 @@ pub(crate) fn update_chemical(
     Ok(())
 }
 
+/// Mark a chemical as deleted, hiding it from searches until it is restored
 pub(crate) fn delete_chemical(
     id: u64,
     database_connection: &MysqlConnection,
 ) -> Result<(), Error> {
-    diesel::delete(chemical_schema::table.filter(chemical_schema::id.eq(id)))
+    diesel::update(chemical_schema::table)
+        .filter(chemical_schema::id.eq(id))
+        .set(chemical_schema::deleted_at.eq(Some(Local::now().naive_local())))
+        .execute(database_connection)?;
+
+    Ok(())
+}
+
+pub(crate) fn restore_chemical(
+    id: u64,
+    database_connection: &MysqlConnection,
+) -> Result<(), Error> {
+    diesel::update(chemical_schema::table)
+        .filter(chemical_schema::id.eq(id))
+        .set(chemical_schema::deleted_at.eq(None::<NaiveDateTime>))
         .execute(database_connection)?;
 
     Ok(())
 }
 
+/// Get the chemicals with less in stock than their minimum, along with where
+/// their stock is kept and who is responsible for it
+///
+/// Deleted chemicals and inventory entries are left out.
+pub(crate) fn get_low_stock_chemicals(
+    database_connection: &MysqlConnection,
+) -> Result<LowStockChemicalList, Error> {
+    let chemicals = chemical_schema::table
+        .filter(chemical_schema::minimum_quantity.is_not_null())
+        .filter(chemical_schema::minimum_unit.is_not_null())
+        .filter(chemical_schema::deleted_at.is_null())
+        .order(chemical_schema::name.asc())
+        .load::<Chemical>(database_connection)?;
+
+    let chemical_ids: Vec<u64> =
+        chemicals.iter().map(|chemical| chemical.id).collect();
+
+    let entries = chemical_inventory_schema::table
+        .inner_join(
+            users_schema::table
+                .on(users_schema::id
+                    .eq(chemical_inventory_schema::custodian_id)),
+        )
+        .filter(chemical_inventory_schema::chemical_id.eq_any(&chemical_ids))
+        .filter(chemical_inventory_schema::deleted_at.is_null())
+        .select((
+            chemical_inventory_schema::all_columns,
+            (
+                users_schema::first_name,
+                users_schema::last_name,
+                users_schema::email,
+            ),
+        ))
+        .order(chemical_inventory_schema::id.asc())
+        .load::<(ChemicalInventory, (String, String, String))>(
+            database_connection,
+        )?;
+
+    let mut chemical_entries: HashMap<u64, Vec<LowStockEntry>> = HashMap::new();
+
+    for (entry, (first_name, last_name, email)) in entries {
+        chemical_entries.entry(entry.chemical_id).or_default().push(
+            LowStockEntry {
+                inventory_id: entry.id,
+                amount: entry.amount(),
+                unparsed_amount: entry.unparsed_amount,
+                storage_location: entry.storage_location,
+                custodian_id: entry.custodian_id,
+                custodian_first_name: first_name,
+                custodian_last_name: last_name,
+                custodian_email: email,
+            },
+        );
+    }
+
+    let chemicals = chemicals
+        .into_iter()
+        .filter_map(|chemical| {
+            let entries =
+                chemical_entries.remove(&chemical.id).unwrap_or_default();
+            low_stock(chemical, entries)
+        })
+        .collect();
+
+    Ok(LowStockChemicalList { chemicals })
+}
+
+/// The chemical as a low stock chemical, if its entries add up to less than
+/// its minimum
+fn low_stock(
+    chemical: Chemical,
+    entries: Vec<LowStockEntry>,
+) -> Option<LowStockChemical> {
+    let minimum = chemical.minimum()?;
+
+    let total = Amount {
+        quantity: entries
+            .iter()
+            .filter(|entry| entry.unparsed_amount.is_none())
+            .filter_map(|entry| entry.amount.convert(minimum.unit))
+            .map(|amount| amount.quantity)
+            .sum(),
+        unit: minimum.unit,
+    };
+
+    if total.quantity < minimum.quantity {
+        Some(LowStockChemical {
+            chemical,
+            minimum,
+            total,
+            entries,
+        })
+    } else {
+        None
+    }
+}
+
 pub fn handle_chemical_inventory(
     request: ChemicalInventoryRequest,
     requested_user: Option<u64>,
@@ -237,162 +494,531 @@ pub fn handle_chemical_inventory(
 ) -> Result<ChemicalInventoryResponse, Error> {
     match request {
         ChemicalInventoryRequest::SearchInventory(inventory) => {
-            match check_to_run(
+            let search_access = check_to_search(
                 requested_user,
                 "GetChemicalInventory",
                 database_connection,
-            ) {
-                Ok(()) => {
-                    search_chemical_inventory(inventory, database_connection)
-                        .map(|c| {
-                            ChemicalInventoryResponse::ManyInventoryEntries(c)
-                        })
-                }
-                Err(e) => Err(e),
-            }
+            )?;
+            search_chemical_inventory(
+                *inventory,
+                &search_access,
+                database_connection,
+            )
+            .map(|c| ChemicalInventoryResponse::ManyInventoryEntries(c))
         }
         ChemicalInventoryRequest::GetInventory(id) => {
-            match check_to_run(
+            let inventory = get_permitted_chemical_inventory(
+                id,
                 requested_user,
                 "GetChemicalInventory",
                 database_connection,
-            ) {
-                Ok(()) => get_chemical_inventory(id, database_connection)
-                    .map(|c| ChemicalInventoryResponse::OneInventoryEntry(c)),
-                Err(e) => Err(e),
-            }
+            )?;
+            Ok(ChemicalInventoryResponse::OneInventoryEntry(inventory))
         }
         ChemicalInventoryRequest::CreateInventory(inventory) => {
             match check_to_run(
                 requested_user,
                 "CreateChemicalInventory",
+                Some(&inventory.resource()),
                 database_connection,
             ) {
-                Ok(()) => {
-                    create_chemical_inventory(inventory, database_connection)
-                        .map(|c| {
-                            ChemicalInventoryResponse::OneInventoryEntry(c)
-                        })
-                }
+                Ok(()) => database_connection
+                    .transaction::<_, Error, _>(|| {
+                        let created = create_chemical_inventory(
+                            inventory,
+                            database_connection,
+                        )?;
+                        record_change(
+                            requested_user,
+                            "CreateChemicalInventory",
+                            "chemical_inventory",
+                            created.id,
+                            None,
+                            Some(&created),
+                            database_connection,
+                        )?;
+                        Ok(created)
+                    })
+                    .map(|c| ChemicalInventoryResponse::OneInventoryEntry(c)),
                 Err(e) => Err(e),
             }
         }
         ChemicalInventoryRequest::UpdateInventory(id, inventory) => {
-            match check_to_run(
+            let existing = get_permitted_chemical_inventory(
+                id,
                 requested_user,
                 "UpdateChemicalInventory",
                 database_connection,
-            ) {
-                Ok(()) => update_chemical_inventory(
-                    id,
-                    inventory,
-                    database_connection,
-                )
-                .map(|_| ChemicalInventoryResponse::NoResponse),
-                Err(e) => Err(e),
-            }
+            )?;
+            // Moving an entry out of a user's scope needs access to where it
+            // ends up, too
+            check_to_run(
+                requested_user,
+                "UpdateChemicalInventory",
+                Some(&inventory.resource(&existing)),
+                database_connection,
+            )?;
+            let user_id = requested_user
+                .ok_or_else(|| Error::new(ErrorKind::AccessDenied))?;
+            database_connection
+                .transaction::<_, Error, _>(|| {
+                    let before =
+                        lock_chemical_inventory(id, database_connection)?;
+                    inventory.validate_dates(&before)?;
+                    update_chemical_inventory(
+                        id,
+                        inventory,
+                        database_connection,
+                    )?;
+                    let after =
+                        get_chemical_inventory(id, database_connection)?;
+                    // Changes to the amount go in the usage log, so that
+                    // every change to it can be traced
+                    if let Some(quantity) = adjustment(&before, &after) {
+                        record_adjustment(
+                            &after,
+                            user_id,
+                            quantity,
+                            database_connection,
+                        )?;
+                    }
+                    record_change(
+                        requested_user,
+                        "UpdateChemicalInventory",
+                        "chemical_inventory",
+                        id,
+                        Some(&before),
+                        Some(&after),
+                        database_connection,
+                    )
+                })
+                .map(|_| ChemicalInventoryResponse::NoResponse)
         }
         ChemicalInventoryRequest::DeleteInventory(id) => {
-            match check_to_run(
+            let existing = get_permitted_chemical_inventory(
+                id,
                 requested_user,
                 "DeleteChemicalInventory",
                 database_connection,
-            ) {
-                Ok(()) => delete_chemical_inventory(id, database_connection)
-                    .map(|_| ChemicalInventoryResponse::NoResponse),
-                Err(e) => Err(e),
-            }
+            )?;
+            database_connection
+                .transaction::<_, Error, _>(|| {
+                    delete_chemical_inventory(id, database_connection)?;
+                    let after =
+                        get_chemical_inventory(id, database_connection)?;
+                    record_change(
+                        requested_user,
+                        "DeleteChemicalInventory",
+                        "chemical_inventory",
+                        id,
+                        Some(&existing),
+                        Some(&after),
+                        database_connection,
+                    )
+                })
+                .map(|_| ChemicalInventoryResponse::NoResponse)
+        }
+        ChemicalInventoryRequest::RestoreInventory(id) => {
+            let existing = get_permitted_chemical_inventory(
+                id,
+                requested_user,
+                "RestoreChemicalInventory",
+                database_connection,
+            )?;
+            database_connection
+                .transaction::<_, Error, _>(|| {
+                    restore_chemical_inventory(id, database_connection)?;
+                    let after =
+                        get_chemical_inventory(id, database_connection)?;
+                    record_change(
+                        requested_user,
+                        "RestoreChemicalInventory",
+                        "chemical_inventory",
+                        id,
+                        Some(&existing),
+                        Some(&after),
+                        database_connection,
+                    )
+                })
+                .map(|_| ChemicalInventoryResponse::NoResponse)
+        }
+        ChemicalInventoryRequest::GetUsage(id) => {
+            get_permitted_chemical_inventory(
+                id,
+                requested_user,
+                "GetChemicalUsage",
+                database_connection,
+            )?;
+            get_chemical_usage(id, database_connection)
+                .map(|u| ChemicalInventoryResponse::ManyUsages(u))
+        }
+        ChemicalInventoryRequest::RecordUsage(id, usage) => {
+            get_permitted_chemical_inventory(
+                id,
+                requested_user,
+                "RecordChemicalUsage",
+                database_connection,
+            )?;
+            let user_id = requested_user
+                .ok_or_else(|| Error::new(ErrorKind::AccessDenied))?;
+            database_connection
+                .transaction::<_, Error, _>(|| {
+                    let before =
+                        lock_chemical_inventory(id, database_connection)?;
+                    let recorded = record_chemical_usage(
+                        &before,
+                        user_id,
+                        usage,
+                        database_connection,
+                    )?;
+                    let after =
+                        get_chemical_inventory(id, database_connection)?;
+                    record_change(
+                        requested_user,
+                        "RecordChemicalUsage",
+                        "chemical_inventory",
+                        id,
+                        Some(&before),
+                        Some(&after),
+                        database_connection,
+                    )?;
+                    Ok(recorded)
+                })
+                .map(|u| ChemicalInventoryResponse::OneUsage(u))
+        }
+        ChemicalInventoryRequest::GetExpiring(days, storage_location) => {
+            let search_access = check_to_search(
+                requested_user,
+                "GetChemicalInventory",
+                database_connection,
+            )?;
+            get_expiring_chemical_inventory(
+                days,
+                &storage_location,
+                &search_access,
+                database_connection,
+            )
+            .map(|l| ChemicalInventoryResponse::ManyExpiring(l))
         }
     }
 }
 
+/// Get an inventory entry that a user may use a permission on
+///
+/// Users without any grant of the permission are denied before the entry is
+/// looked up, and entries outside a scoped grant are not found, so neither
+/// can tell which entries exist.
+fn get_permitted_chemical_inventory(
+    id: u64,
+    requested_user: Option<u64>,
+    permission_name: &str,
+    database_connection: &MysqlConnection,
+) -> Result<ChemicalInventory, Error> {
+    check_to_search(requested_user, permission_name, database_connection)?;
+
+    let inventory = get_chemical_inventory(id, database_connection)?;
+
+    match check_to_run(
+        requested_user,
+        permission_name,
+        Some(&inventory.resource()),
+        database_connection,
+    ) {
+        Ok(()) => Ok(inventory),
+        Err(e) => match e.kind() {
+            ErrorKind::AccessDenied => Err(Error::new(ErrorKind::NotFound)),
+            _ => Err(e),
+        },
+    }
+}
+
 pub(crate) fn search_chemical_inventory(
     chemical_inventory_search: SearchChemicalInventory,
+    search_access: &SearchAccess,
     database_connection: &MysqlConnection,
 ) -> Result<ChemicalInventoryList, Error> {
+    let total =
+        chemical_inventory_query(&chemical_inventory_search, search_access)
+            .count()
+            .get_result::<i64>(database_connection)? as u64;
+
     let mut chemical_inventory_query =
-        chemical_inventory_schema::table.as_query().into_boxed();
+        chemical_inventory_query(&chemical_inventory_search, search_access);
 
-    match chemical_inventory_search.purchaser_id {
-        Search::Partial(s) => {
-            chemical_inventory_query = chemical_inventory_query
-                .filter(chemical_inventory_schema::purchaser_id.eq(s))
-        }
+    if let Some(sort) = &chemical_inventory_search.page.sort {
+        chemical_inventory_query = match sort.field.as_ref() {
+            "id" => sort.order.order_by(
+                chemical_inventory_query,
+                chemical_inventory_schema::id,
+            ),
+            "purchaser_id" => sort.order.order_by(
+                chemical_inventory_query,
+                chemical_inventory_schema::purchaser_id,
+            ),
+            "custodian_id" => sort.order.order_by(
+                chemical_inventory_query,
+                chemical_inventory_schema::custodian_id,
+            ),
+            "chemical_id" => sort.order.order_by(
+                chemical_inventory_query,
+                chemical_inventory_schema::chemical_id,
+            ),
+            "storage_location" => sort.order.order_by(
+                chemical_inventory_query,
+                chemical_inventory_schema::storage_location,
+            ),
+            "quantity" => sort.order.order_by(
+                chemical_inventory_query,
+                chemical_inventory_schema::quantity,
+            ),
+            "unit" => sort.order.order_by(
+                chemical_inventory_query,
+                chemical_inventory_schema::unit,
+            ),
+            "received" => sort.order.order_by(
+                chemical_inventory_query,
+                chemical_inventory_schema::received,
+            ),
+            "opened" => sort.order.order_by(
+                chemical_inventory_query,
+                chemical_inventory_schema::opened,
+            ),
+            "expires" => sort.order.order_by(
+                chemical_inventory_query,
+                chemical_inventory_schema::expires,
+            ),
+            _ => return Err(Error::new(ErrorKind::Url)),
+        };
+    }
 
-        Search::Exact(s) => {
-            chemical_inventory_query = chemical_inventory_query
-                .filter(chemical_inventory_schema::purchaser_id.eq(s))
-        }
+    let found_entries = chemical_inventory_search
+        .page
+        .paginate(
+            chemical_inventory_query
+                .then_order_by(chemical_inventory_schema::id.asc()),
+        )
+        .load::<ChemicalInventory>(database_connection)?;
+    let inventory_list = ChemicalInventoryList {
+        entries: found_entries,
+        total,
+        next: chemical_inventory_search.page.next(total),
+    };
+
+    Ok(inventory_list)
+}
 
-        Search::NoSearch => {}
+/// Build a query for the inventory entries matching a search that the
+/// searching user may see, in no particular order
+fn chemical_inventory_query<'a>(
+    chemical_inventory_search: &'a SearchChemicalInventory,
+    search_access: &SearchAccess,
+) -> chemical_inventory_schema::BoxedQuery<'a, Mysql> {
+    let mut chemical_inventory_query =
+        chemical_inventory_schema::table.as_query().into_boxed();
+
+    if let Some(condition) = access_condition(search_access) {
+        chemical_inventory_query = chemical_inventory_query.filter(condition);
     }
 
-    match chemical_inventory_search.custodian_id {
-        Search::Partial(s) => {
-            chemical_inventory_query = chemical_inventory_query
-                .filter(chemical_inventory_schema::custodian_id.eq(s))
-        }
+    chemical_inventory_query = chemical_inventory_search.purchaser_id.filter(
+        chemical_inventory_query,
+        chemical_inventory_schema::purchaser_id,
+    );
 
-        Search::Exact(s) => {
-            chemical_inventory_query = chemical_inventory_query
-                .filter(chemical_inventory_schema::custodian_id.eq(s))
-        }
+    chemical_inventory_query = chemical_inventory_search.custodian_id.filter(
+        chemical_inventory_query,
+        chemical_inventory_schema::custodian_id,
+    );
+
+    chemical_inventory_query = chemical_inventory_search.chemical_id.filter(
+        chemical_inventory_query,
+        chemical_inventory_schema::chemical_id,
+    );
+
+    chemical_inventory_query =
+        chemical_inventory_search.storage_location.filter_text(
+            chemical_inventory_query,
+            chemical_inventory_schema::storage_location,
+        );
 
-        Search::NoSearch => {}
+    if let Some(condition) = amount_condition(&chemical_inventory_search.amount)
+    {
+        chemical_inventory_query = chemical_inventory_query.filter(condition);
     }
 
-    match chemical_inventory_search.chemical_id {
-        Search::Partial(s) => {
-            chemical_inventory_query = chemical_inventory_query
-                .filter(chemical_inventory_schema::chemical_id.eq(s))
-        }
+    chemical_inventory_query = chemical_inventory_search.received.filter(
+        chemical_inventory_query,
+        chemical_inventory_schema::received,
+    );
 
-        Search::Exact(s) => {
-            chemical_inventory_query = chemical_inventory_query
-                .filter(chemical_inventory_schema::chemical_id.eq(s))
-        }
+    chemical_inventory_query = chemical_inventory_search
+        .opened
+        .filter(chemical_inventory_query, chemical_inventory_schema::opened);
+
+    chemical_inventory_query = chemical_inventory_search
+        .expires
+        .filter(chemical_inventory_query, chemical_inventory_schema::expires);
 
-        Search::NoSearch => {}
+    if !chemical_inventory_search.include_deleted {
+        chemical_inventory_query = chemical_inventory_query
+            .filter(chemical_inventory_schema::deleted_at.is_null());
     }
 
-    match chemical_inventory_search.storage_location {
-        Search::Partial(s) => {
-            chemical_inventory_query = chemical_inventory_query.filter(
-                chemical_inventory_schema::storage_location
-                    .like(format!("%{}%", s)),
-            )
-        }
+    chemical_inventory_query
+}
 
-        Search::Exact(s) => {
-            chemical_inventory_query = chemical_inventory_query
-                .filter(chemical_inventory_schema::storage_location.eq(s))
-        }
+/// Build the condition for entries a user's grants cover, if they are scoped
+///
+/// Entries are owned by their custodian, and kept in the room of their
+/// storage location.
+fn access_condition<'a>(
+    search_access: &SearchAccess,
+) -> Option<Condition<'a, chemical_inventory_schema::table>> {
+    let scopes = match &search_access.access_level {
+        AccessLevel::Unrestricted => return None,
+        AccessLevel::Scoped(scopes) => scopes,
+    };
 
-        Search::NoSearch => {}
-    }
+    let condition: Option<Condition<_>> = scopes
+        .iter()
+        .map(|scope| match scope {
+            AccessScope::Owned => Box::new(
+                chemical_inventory_schema::custodian_id
+                    .eq(search_access.user_id),
+            ) as Condition<_>,
+            AccessScope::Room(room) => Box::new(
+                chemical_inventory_schema::storage_location.eq(room.clone()),
+            ),
+            AccessScope::Department(department) => {
+                Box::new(chemical_inventory_schema::custodian_id.eq_any(
+                    users_schema::table.select(users_schema::id).filter(
+                        users_schema::department.eq(department.clone()),
+                    ),
+                ))
+            }
+        })
+        .fold(None, |conditions, condition| match conditions {
+            Some(conditions) => Some(Box::new(conditions.or(condition))),
+            None => Some(condition),
+        });
 
-    match chemical_inventory_search.amount {
-        Search::Partial(s) => {
-            chemical_inventory_query = chemical_inventory_query.filter(
-                chemical_inventory_schema::amount.like(format!("%{}%", s)),
-            )
-        }
+    // Scoped access without any scopes covers nothing
+    Some(condition.unwrap_or_else(|| {
+        Box::new(diesel::dsl::sql::<sql_types::Bool>("FALSE"))
+    }))
+}
 
-        Search::Exact(s) => {
-            chemical_inventory_query = chemical_inventory_query
-                .filter(chemical_inventory_schema::amount.eq(s))
-        }
+/// Build the condition for entries matching an amount search, if searching
+///
+/// Entries are compared in their own unit, so the searched amounts are
+/// converted to each unit they can be. Entries with an unparsed amount have
+/// no real quantity, so they never match.
+fn amount_condition<'a>(
+    amount_search: &Search<Amount>,
+) -> Option<Condition<'a, chemical_inventory_schema::table>> {
+    let unit_conditions = UNITS
+        .iter()
+        .filter_map(|&unit| {
+            let quantity_search = amount_search
+                .try_map(&|amount| amount.convert(unit).map(|a| a.quantity))?;
+            let quantity_condition = quantity_search
+                .condition(chemical_inventory_schema::quantity)?;
+
+            Some(Box::new(
+                chemical_inventory_schema::unit
+                    .eq(unit)
+                    .and(quantity_condition),
+            ) as Condition<_>)
+        })
+        .fold(None, |conditions: Option<Condition<_>>, condition| {
+            match conditions {
+                Some(conditions) => Some(Box::new(conditions.or(condition))),
+                None => Some(condition),
+            }
+        })?;
+
+    Some(Box::new(
+        chemical_inventory_schema::unparsed_amount
+            .is_null()
+            .and(unit_conditions),
+    ))
+}
+
+/// Get the inventory entries that have expired, or will within `days`, by
+/// storage location
+///
+/// Deleted entries and entries without an expiration date are left out.
+pub(crate) fn get_expiring_chemical_inventory(
+    days: u32,
+    storage_location_search: &Search<String>,
+    search_access: &SearchAccess,
+    database_connection: &MysqlConnection,
+) -> Result<ExpiringLocationList, Error> {
+    let today = Local::now().date_naive();
+    let cutoff = today
+        .checked_add_signed(Duration::days(i64::from(days)))
+        .ok_or_else(|| Error::new(ErrorKind::Url))?;
+
+    let mut expiring_query = chemical_inventory_schema::table
+        .filter(chemical_inventory_schema::expires.le(cutoff))
+        .filter(chemical_inventory_schema::deleted_at.is_null())
+        .into_boxed::<Mysql>();
 
-        Search::NoSearch => {}
+    if let Some(condition) = access_condition(search_access) {
+        expiring_query = expiring_query.filter(condition);
     }
 
-    let found_entries = chemical_inventory_query
+    expiring_query = storage_location_search.filter_text(
+        expiring_query,
+        chemical_inventory_schema::storage_location,
+    );
+
+    let entries = expiring_query
+        .order((
+            chemical_inventory_schema::storage_location.asc(),
+            chemical_inventory_schema::expires.asc(),
+            chemical_inventory_schema::id.asc(),
+        ))
         .load::<ChemicalInventory>(database_connection)?;
-    let inventory_list = ChemicalInventoryList {
-        entries: found_entries,
-    };
 
-    Ok(inventory_list)
+    Ok(ExpiringLocationList {
+        locations: expiring_locations(entries, today),
+    })
+}
+
+/// Group entries sorted by storage location, splitting those that expired by
+/// `today` from those that have not yet
+fn expiring_locations(
+    entries: Vec<ChemicalInventory>,
+    today: NaiveDate,
+) -> Vec<ExpiringLocation> {
+    let mut locations: Vec<ExpiringLocation> = Vec::new();
+
+    for entry in entries {
+        let location = match locations.last_mut() {
+            Some(location)
+                if location.storage_location == entry.storage_location =>
+            {
+                location
+            }
+            _ => {
+                locations.push(ExpiringLocation {
+                    storage_location: entry.storage_location.clone(),
+                    expired: Vec::new(),
+                    expiring: Vec::new(),
+                });
+                locations.last_mut().unwrap()
+            }
+        };
+
+        if entry.expires.is_some_and(|expires| expires <= today) {
+            location.expired.push(entry);
+        } else {
+            location.expiring.push(entry);
+        }
+    }
+
+    locations
 }
 
 pub(crate) fn get_chemical_inventory(
@@ -409,6 +1035,24 @@ pub(crate) fn get_chemical_inventory(
     }
 }
 
+/// Get an inventory entry, locking it until the end of the transaction
+///
+/// This keeps usage recorded at the same time from being lost.
+fn lock_chemical_inventory(
+    id: u64,
+    database_connection: &MysqlConnection,
+) -> Result<ChemicalInventory, Error> {
+    let mut found_inventory = chemical_inventory_schema::table
+        .filter(chemical_inventory_schema::id.eq(id))
+        .for_update()
+        .load::<ChemicalInventory>(database_connection)?;
+
+    match found_inventory.pop() {
+        Some(entry) => Ok(entry),
+        None => Err(Error::new(ErrorKind::NotFound)),
+    }
+}
+
 pub(crate) fn create_chemical_inventory(
     inventory: NewChemicalInventory,
     database_connection: &MysqlConnection,
@@ -430,27 +1074,659 @@ pub(crate) fn create_chemical_inventory(
     }
 }
 
+/// Update an inventory entry
+///
+/// Giving an entry a quantity or unit fixes its amount, so any unparsed
+/// amount it had is cleared.
 pub(crate) fn update_chemical_inventory(
     id: u64,
     inventory: PartialChemicalInventory,
     database_connection: &MysqlConnection,
 ) -> Result<(), Error> {
+    let fixes_amount = inventory.quantity.is_some() || inventory.unit.is_some();
+
     diesel::update(chemical_inventory_schema::table)
         .filter(chemical_inventory_schema::id.eq(id))
         .set(&inventory)
         .execute(database_connection)?;
+
+    if fixes_amount {
+        diesel::update(chemical_inventory_schema::table)
+            .filter(chemical_inventory_schema::id.eq(id))
+            .set(chemical_inventory_schema::unparsed_amount.eq(None::<String>))
+            .execute(database_connection)?;
+    }
+
+    Ok(())
+}
+
+/// How much of an inventory entry an update took out, in the entry's new
+/// unit, or `None` if the update left its amount alone
+///
+/// An amount that was never parsed, or that can not be converted to the new
+/// unit, counts as nothing, so the whole new quantity shows as added.
+fn adjustment(
+    before: &ChemicalInventory,
+    after: &ChemicalInventory,
+) -> Option<f64> {
+    if before.amount() == after.amount()
+        && before.unparsed_amount == after.unparsed_amount
+    {
+        return None;
+    }
+
+    let quantity_before = match before.unparsed_amount {
+        Some(_) => 0.0,
+        None => before
+            .amount()
+            .convert(after.unit)
+            .map_or(0.0, |amount| amount.quantity),
+    };
+
+    Some(quantity_before - after.quantity)
+}
+
+/// Log an update to the amount of an inventory entry as usage of it, which
+/// is negative when the update added to the entry
+fn record_adjustment(
+    inventory: &ChemicalInventory,
+    user_id: u64,
+    quantity: f64,
+    database_connection: &MysqlConnection,
+) -> Result<(), Error> {
+    let new_raw_chemical_usage = NewRawChemicalUsage {
+        inventory_id: inventory.id,
+        user_id,
+        quantity,
+        unit: inventory.unit,
+        purpose: ADJUSTMENT_PURPOSE.to_owned(),
+        remaining: inventory.quantity,
+        used: Local::now().naive_local(),
+    };
+
+    diesel::insert_into(chemical_usage_schema::table)
+        .values(new_raw_chemical_usage)
+        .execute(database_connection)?;
+
     Ok(())
 }
 
+/// Mark an inventory entry as deleted, hiding it from searches until it is
+/// restored
 pub(crate) fn delete_chemical_inventory(
     id: u64,
     database_connection: &MysqlConnection,
 ) -> Result<(), Error> {
-    diesel::delete(
-        chemical_inventory_schema::table
-            .filter(chemical_inventory_schema::id.eq(id)),
-    )
-    .execute(database_connection)?;
+    diesel::update(chemical_inventory_schema::table)
+        .filter(chemical_inventory_schema::id.eq(id))
+        .set(
+            chemical_inventory_schema::deleted_at
+                .eq(Some(Local::now().naive_local())),
+        )
+        .execute(database_connection)?;
+
+    Ok(())
+}
+
+pub(crate) fn restore_chemical_inventory(
+    id: u64,
+    database_connection: &MysqlConnection,
+) -> Result<(), Error> {
+    diesel::update(chemical_inventory_schema::table)
+        .filter(chemical_inventory_schema::id.eq(id))
+        .set(chemical_inventory_schema::deleted_at.eq(None::<NaiveDateTime>))
+        .execute(database_connection)?;
 
     Ok(())
 }
+
+/// Get the usage of an inventory entry, newest first
+pub(crate) fn get_chemical_usage(
+    inventory_id: u64,
+    database_connection: &MysqlConnection,
+) -> Result<ChemicalUsageList, Error> {
+    let usages = chemical_usage_schema::table
+        .filter(chemical_usage_schema::inventory_id.eq(inventory_id))
+        .order((
+            chemical_usage_schema::used.desc(),
+            chemical_usage_schema::id.desc(),
+        ))
+        .load::<ChemicalUsage>(database_connection)?;
+
+    Ok(ChemicalUsageList { usages })
+}
+
+/// Record some of an inventory entry being used, taking it out of the
+/// entry's quantity
+///
+/// Deleted entries, and entries whose amount was never parsed, can not be
+/// used. Entries that were not opened yet are marked as opened today.
+pub(crate) fn record_chemical_usage(
+    inventory: &ChemicalInventory,
+    user_id: u64,
+    usage: NewChemicalUsage,
+    database_connection: &MysqlConnection,
+) -> Result<ChemicalUsage, Error> {
+    if inventory.deleted_at.is_some() {
+        return Err(Error::new(ErrorKind::NotFound));
+    }
+
+    if inventory.unparsed_amount.is_some() {
+        return Err(Error::new(ErrorKind::UnparsedAmount));
+    }
+
+    let remaining = remaining_after(inventory, &usage)?;
+
+    diesel::update(chemical_inventory_schema::table)
+        .filter(chemical_inventory_schema::id.eq(inventory.id))
+        .set(chemical_inventory_schema::quantity.eq(remaining))
+        .execute(database_connection)?;
+
+    if inventory.opened.is_none() {
+        diesel::update(chemical_inventory_schema::table)
+            .filter(chemical_inventory_schema::id.eq(inventory.id))
+            .set(
+                chemical_inventory_schema::opened
+                    .eq(Some(Local::now().date_naive())),
+            )
+            .execute(database_connection)?;
+    }
+
+    let new_raw_chemical_usage = NewRawChemicalUsage {
+        inventory_id: inventory.id,
+        user_id,
+        quantity: usage.quantity,
+        unit: usage.unit,
+        purpose: usage.purpose,
+        remaining,
+        used: Local::now().naive_local(),
+    };
+
+    diesel::insert_into(chemical_usage_schema::table)
+        .values(new_raw_chemical_usage)
+        .execute(database_connection)?;
+
+    no_arg_sql_function!(last_insert_id, Unsigned<sql_types::Bigint>);
+
+    let mut inserted_usages = chemical_usage_schema::table
+        .filter(chemical_usage_schema::id.eq(last_insert_id))
+        .load::<ChemicalUsage>(database_connection)?;
+
+    if let Some(inserted_usage) = inserted_usages.pop() {
+        Ok(inserted_usage)
+    } else {
+        Err(Error::new(ErrorKind::Database))
+    }
+}
+
+/// How much of an inventory entry is left after some of it is used, in the
+/// entry's unit
+fn remaining_after(
+    inventory: &ChemicalInventory,
+    usage: &NewChemicalUsage,
+) -> Result<f64, Error> {
+    let used = usage
+        .amount()
+        .convert(inventory.unit)
+        .ok_or_else(|| Error::new(ErrorKind::IncompatibleUnits))?;
+
+    let remaining = inventory.quantity - used.quantity;
+
+    // Using up all of an entry in another unit can leave a rounding error
+    if remaining < -QUANTITY_TOLERANCE {
+        Err(Error::new(ErrorKind::InsufficientQuantity))
+    } else {
+        Ok(remaining.max(0.0))
+    }
+}
+
+#[test]
+fn chemical_inventory_query_partially_searches_ids() {
+    use crate::search::{NullableSearch, Page, Search};
+
+    let chemical_inventory_search = SearchChemicalInventory {
+        purchaser_id: Search::from_query("prefix,12").unwrap(),
+        custodian_id: Search::from_query("suffix,034").unwrap(),
+        chemical_id: Search::from_query("partial,5").unwrap(),
+        storage_location: Search::NoSearch,
+        amount: Search::NoSearch,
+        received: NullableSearch::NoSearch,
+        opened: NullableSearch::NoSearch,
+        expires: NullableSearch::NoSearch,
+        include_deleted: true,
+        page: Page::default(),
+    };
+
+    let search_access = SearchAccess {
+        user_id: 1,
+        access_level: AccessLevel::Unrestricted,
+    };
+
+    let query =
+        chemical_inventory_query(&chemical_inventory_search, &search_access)
+            .select(chemical_inventory_schema::id);
+
+    assert_eq!(
+        diesel::debug_query::<Mysql, _>(&query).to_string(),
+        "SELECT `chemical_inventory`.`id` FROM `chemical_inventory` \
+         WHERE CAST(`chemical_inventory`.`purchaser_id` AS CHAR) LIKE ? \
+         AND CAST(`chemical_inventory`.`custodian_id` AS CHAR) LIKE ? \
+         AND CAST(`chemical_inventory`.`chemical_id` AS CHAR) LIKE ? \
+         -- binds: [\"12%\", \"%034\", \"%5%\"]"
+    );
+}
+
+#[test]
+fn amount_condition_converts_amounts() {
+    let amount_search = Search::from_query("gt,1 L").unwrap();
+    let query = chemical_inventory_schema::table
+        .select(chemical_inventory_schema::id)
+        .filter(amount_condition(&amount_search).unwrap());
+
+    assert_eq!(
+        diesel::debug_query::<Mysql, _>(&query).to_string(),
+        "SELECT `chemical_inventory`.`id` FROM `chemical_inventory` \
+         WHERE `chemical_inventory`.`unparsed_amount` IS NULL \
+         AND ((`chemical_inventory`.`unit` = ? \
+         AND `chemical_inventory`.`quantity` > ? \
+         OR `chemical_inventory`.`unit` = ? \
+         AND `chemical_inventory`.`quantity` > ?) \
+         OR `chemical_inventory`.`unit` = ? \
+         AND `chemical_inventory`.`quantity` > ?) \
+         -- binds: [Milliliters, 1000.0, Liters, 1.0, \
+         Gallons, 0.26417205235814845]"
+    );
+}
+
+#[test]
+fn remaining_after_converts_usage() {
+    use super::models::Unit;
+
+    let inventory = ChemicalInventory {
+        id: 1,
+        purchaser_id: 1,
+        custodian_id: 1,
+        chemical_id: 1,
+        storage_location: "Lab 101".to_owned(),
+        quantity: 1.0,
+        unit: Unit::Liters,
+        unparsed_amount: None,
+        received: None,
+        opened: None,
+        expires: None,
+        deleted_at: None,
+    };
+
+    let usage = |quantity, unit| NewChemicalUsage {
+        quantity,
+        unit,
+        purpose: "Titration".to_owned(),
+    };
+
+    assert_eq!(
+        remaining_after(&inventory, &usage(250.0, Unit::Milliliters)).unwrap(),
+        0.75
+    );
+    assert_eq!(
+        remaining_after(&inventory, &usage(1000.0, Unit::Milliliters)).unwrap(),
+        0.0
+    );
+    assert!(remaining_after(&inventory, &usage(1.0, Unit::Gallons)).is_err());
+    assert!(remaining_after(&inventory, &usage(1.0, Unit::Grams)).is_err());
+}
+
+#[test]
+fn adjustment_converts_amounts() {
+    use super::models::Unit;
+
+    let inventory = |quantity, unit, unparsed_amount| ChemicalInventory {
+        id: 1,
+        purchaser_id: 1,
+        custodian_id: 1,
+        chemical_id: 1,
+        storage_location: "Lab 101".to_owned(),
+        quantity,
+        unit,
+        unparsed_amount,
+        received: None,
+        opened: None,
+        expires: None,
+        deleted_at: None,
+    };
+
+    let one_liter = inventory(1.0, Unit::Liters, None);
+
+    assert_eq!(adjustment(&one_liter, &one_liter), None);
+    assert_eq!(
+        adjustment(&one_liter, &inventory(250.0, Unit::Milliliters, None)),
+        Some(750.0)
+    );
+    assert_eq!(
+        adjustment(&one_liter, &inventory(3.0, Unit::Liters, None)),
+        Some(-2.0)
+    );
+    assert_eq!(
+        adjustment(&one_liter, &inventory(5.0, Unit::Grams, None)),
+        Some(-5.0)
+    );
+    assert_eq!(
+        adjustment(
+            &inventory(0.0, Unit::Milliliters, Some("2 bottles".to_owned())),
+            &inventory(2.0, Unit::Liters, None),
+        ),
+        Some(-2.0)
+    );
+}
+
+#[test]
+fn low_stock_sums_converted_entries() {
+    use super::models::{Pictogram, Pictograms, SignalWord, Statements, Unit};
+
+    let chemical = Chemical {
+        id: 1,
+        name: "Ethanol".to_owned(),
+        purpose: "Cleaning".to_owned(),
+        company_name: "Company".to_owned(),
+        ingredients: "Ethanol".to_owned(),
+        manual_link: "".to_owned(),
+        cas_number: Some("64-17-5".to_owned()),
+        signal_word: Some(SignalWord::Danger),
+        pictograms: Pictograms(vec![Pictogram::Flammable]),
+        hazard_statements: Statements(vec!["H225".to_owned()]),
+        precautionary_statements: Statements(vec!["P210".to_owned()]),
+        minimum_quantity: Some(2.0),
+        minimum_unit: Some(Unit::Liters),
+        deleted_at: None,
+    };
+
+    let entry = |quantity, unit| LowStockEntry {
+        inventory_id: 1,
+        storage_location: "Lab 101".to_owned(),
+        amount: Amount { quantity, unit },
+        unparsed_amount: None,
+        custodian_id: 1,
+        custodian_first_name: "Lab".to_owned(),
+        custodian_last_name: "Manager".to_owned(),
+        custodian_email: "manager@rowan.edu".to_owned(),
+    };
+
+    let low = low_stock(
+        chemical,
+        vec![
+            entry(1.0, Unit::Liters),
+            entry(500.0, Unit::Milliliters),
+            entry(10.0, Unit::Grams),
+            LowStockEntry {
+                unparsed_amount: Some("2 bottles".to_owned()),
+                ..entry(0.0, Unit::Milliliters)
+            },
+        ],
+    )
+    .unwrap();
+
+    assert_eq!(
+        low.total,
+        Amount {
+            quantity: 1.5,
+            unit: Unit::Liters,
+        }
+    );
+    assert_eq!(low.entries.len(), 4);
+
+    assert!(low_stock(
+        low.chemical,
+        vec![entry(1.0, Unit::Liters), entry(1.0, Unit::Liters)],
+    )
+    .is_none());
+}
+
+#[test]
+fn chemical_inventory_query_searches_date_ranges() {
+    use crate::search::{NullableSearch, Page, Search};
+
+    let chemical_inventory_search = SearchChemicalInventory {
+        purchaser_id: Search::NoSearch,
+        custodian_id: Search::NoSearch,
+        chemical_id: Search::NoSearch,
+        storage_location: Search::NoSearch,
+        amount: Search::NoSearch,
+        received: NullableSearch::from_query("between,2019-01-01,2019-06-30")
+            .unwrap(),
+        opened: NullableSearch::None,
+        expires: NullableSearch::from_query("lt,2019-09-23").unwrap(),
+        include_deleted: true,
+        page: Page::default(),
+    };
+
+    let search_access = SearchAccess {
+        user_id: 1,
+        access_level: AccessLevel::Unrestricted,
+    };
+
+    let query =
+        chemical_inventory_query(&chemical_inventory_search, &search_access)
+            .select(chemical_inventory_schema::id);
+
+    assert_eq!(
+        diesel::debug_query::<Mysql, _>(&query).to_string(),
+        "SELECT `chemical_inventory`.`id` FROM `chemical_inventory` \
+         WHERE `chemical_inventory`.`received` BETWEEN ? AND ? \
+         AND `chemical_inventory`.`opened` IS NULL \
+         AND `chemical_inventory`.`expires` < ? \
+         -- binds: [2019-01-01, 2019-06-30, 2019-09-23]"
+    );
+}
+
+#[test]
+fn chemical_inventory_query_filters_scoped_access() {
+    use crate::search::{NullableSearch, Page, Search};
+
+    let chemical_inventory_search = SearchChemicalInventory {
+        purchaser_id: Search::NoSearch,
+        custodian_id: Search::NoSearch,
+        chemical_id: Search::NoSearch,
+        storage_location: Search::NoSearch,
+        amount: Search::NoSearch,
+        received: NullableSearch::NoSearch,
+        opened: NullableSearch::NoSearch,
+        expires: NullableSearch::NoSearch,
+        include_deleted: true,
+        page: Page::default(),
+    };
+    let search_access = SearchAccess {
+        user_id: 7,
+        access_level: AccessLevel::Scoped(vec![
+            AccessScope::Owned,
+            AccessScope::Room("B-101".to_owned()),
+            AccessScope::Department("Chemistry".to_owned()),
+        ]),
+    };
+
+    let query =
+        chemical_inventory_query(&chemical_inventory_search, &search_access)
+            .select(chemical_inventory_schema::id);
+
+    assert_eq!(
+        diesel::debug_query::<Mysql, _>(&query).to_string(),
+        "SELECT `chemical_inventory`.`id` FROM `chemical_inventory` \
+         WHERE ((`chemical_inventory`.`custodian_id` = ? \
+         OR `chemical_inventory`.`storage_location` = ?) \
+         OR `chemical_inventory`.`custodian_id` IN \
+         (SELECT `users`.`id` FROM `users` WHERE `users`.`department` = ?)) \
+         -- binds: [7, \"B-101\", \"Chemistry\"]"
+    );
+}
+
+#[test]
+fn expiring_locations_groups_entries() {
+    use super::models::Unit;
+
+    let date = |day| NaiveDate::from_ymd_opt(2019, 9, day);
+    let entry = |id, storage_location: &str, expires| ChemicalInventory {
+        id,
+        purchaser_id: 1,
+        custodian_id: 1,
+        chemical_id: 1,
+        storage_location: storage_location.to_owned(),
+        quantity: 1.0,
+        unit: Unit::Liters,
+        unparsed_amount: None,
+        received: None,
+        opened: None,
+        expires,
+        deleted_at: None,
+    };
+
+    let locations = expiring_locations(
+        vec![
+            entry(1, "Lab 101", date(20)),
+            entry(2, "Lab 101", date(23)),
+            entry(3, "Lab 101", date(30)),
+            entry(4, "Lab 102", date(25)),
+        ],
+        date(23).unwrap(),
+    );
+
+    let ids = |entries: &Vec<ChemicalInventory>| {
+        entries.iter().map(|entry| entry.id).collect::<Vec<_>>()
+    };
+
+    assert_eq!(locations.len(), 2);
+    assert_eq!(locations[0].storage_location, "Lab 101");
+    assert_eq!(ids(&locations[0].expired), vec![1, 2]);
+    assert_eq!(ids(&locations[0].expiring), vec![3]);
+    assert_eq!(locations[1].storage_location, "Lab 102");
+    assert_eq!(ids(&locations[1].expired), Vec::<u64>::new());
+    assert_eq!(ids(&locations[1].expiring), vec![4]);
+}
+
+#[test]
+fn chemical_query_searches_hazards() {
+    use super::models::{Pictogram, SignalWord};
+    use crate::search::{NullableSearch, Page, Search};
+
+    let chemical_search = SearchChemical {
+        name: Search::NoSearch,
+        purpose: Search::NoSearch,
+        company_name: Search::NoSearch,
+        ingredients: Search::NoSearch,
+        manual_link: Search::NoSearch,
+        cas_number: NullableSearch::Some,
+        signal_word: NullableSearch::Matches(Search::Exact(SignalWord::Danger)),
+        pictograms: Search::Exact(Pictogram::Flammable),
+        hazard_statements: Search::Prefix("H3".to_owned()),
+        precautionary_statements: Search::NoSearch,
+        storage_location: Search::Prefix("Science Hall".to_owned()),
+        include_deleted: true,
+        page: Page::default(),
+    };
+
+    let query = chemical_query(&chemical_search).select(chemical_schema::id);
+
+    assert_eq!(
+        diesel::debug_query::<Mysql, _>(&query).to_string(),
+        "SELECT `chemical`.`id` FROM `chemical` \
+         WHERE `chemical`.`cas_number` IS NOT NULL \
+         AND `chemical`.`signal_word` = ? \
+         AND find_in_set(?, `chemical`.`pictograms`) > ? \
+         AND (`chemical`.`hazard_statements` LIKE ? \
+         OR `chemical`.`hazard_statements` LIKE ?) \
+         AND `chemical`.`id` IN (SELECT `chemical_inventory`.`chemical_id` \
+         FROM `chemical_inventory` \
+         WHERE `chemical_inventory`.`deleted_at` IS NULL \
+         AND `chemical_inventory`.`storage_location` LIKE ?) \
+         -- binds: [Danger, \"GHS02\", 0, \"H3%\", \"%,H3%\", \
+         \"Science Hall%\"]"
+    );
+}
+
+#[test]
+#[ignore]
+fn delete_chemical_hides_chemical_until_restored() {
+    use super::models::{Pictograms, Statements};
+    use crate::search::{NullableSearch, Page, Search};
+
+    let database_connection = crate::testing::database_connection();
+
+    database_connection.test_transaction::<_, Error, _>(|| {
+        let new_chemical = NewChemical {
+            name: "Transaction Test".to_owned(),
+            purpose: "Testing".to_owned(),
+            company_name: "Rowan".to_owned(),
+            ingredients: "None".to_owned(),
+            manual_link: "https://rowan.edu".to_owned(),
+            cas_number: None,
+            signal_word: None,
+            pictograms: Pictograms::default(),
+            hazard_statements: Statements::default(),
+            precautionary_statements: Statements::default(),
+            minimum_quantity: None,
+            minimum_unit: None,
+        };
+
+        let chemical = create_chemical(new_chemical, &database_connection)?;
+
+        let search = |include_deleted| SearchChemical {
+            name: Search::Exact("Transaction Test".to_owned()),
+            purpose: Search::NoSearch,
+            company_name: Search::NoSearch,
+            ingredients: Search::NoSearch,
+            manual_link: Search::NoSearch,
+            cas_number: NullableSearch::NoSearch,
+            signal_word: NullableSearch::NoSearch,
+            pictograms: Search::NoSearch,
+            hazard_statements: Search::NoSearch,
+            precautionary_statements: Search::NoSearch,
+            storage_location: Search::NoSearch,
+            include_deleted,
+            page: Page::default(),
+        };
+
+        delete_chemical(chemical.id, &database_connection)?;
+
+        assert_eq!(
+            search_chemical(search(false), &database_connection)?.total,
+            0
+        );
+        assert_eq!(
+            search_chemical(search(true), &database_connection)?.total,
+            1
+        );
+
+        restore_chemical(chemical.id, &database_connection)?;
+
+        let found_chemicals =
+            search_chemical(search(false), &database_connection)?;
+
+        assert_eq!(found_chemicals.total, 1);
+        assert!(found_chemicals.chemicals[0].deleted_at.is_none());
+
+        Ok(())
+    });
+}
+
+#[test]
+#[ignore]
+fn get_expiring_chemical_inventory_rejects_distant_cutoffs() {
+    use crate::search::Search;
+
+    let database_connection = crate::testing::database_connection();
+
+    let search_access = SearchAccess {
+        user_id: 1,
+        access_level: AccessLevel::Unrestricted,
+    };
+
+    match get_expiring_chemical_inventory(
+        u32::MAX,
+        &Search::NoSearch,
+        &search_access,
+        &database_connection,
+    ) {
+        Err(ref e) => match e.kind() {
+            ErrorKind::Url => (),
+            _ => panic!("Distant cutoff was not refused: {}", e),
+        },
+        Ok(_) => panic!("Distant cutoff was accepted"),
+    }
+}
@@ -8,6 +8,14 @@ table! {
         company_name -> Varchar,
         ingredients -> Varchar,
         manual_link -> Varchar,
+        cas_number -> Nullable<Varchar>,
+        signal_word -> Nullable<Varchar>,
+        pictograms -> Varchar,
+        hazard_statements -> Varchar,
+        precautionary_statements -> Varchar,
+        minimum_quantity -> Nullable<Double>,
+        minimum_unit -> Nullable<Varchar>,
+        deleted_at -> Nullable<Timestamp>,
     }
 }
 
@@ -18,7 +26,26 @@ table! {
         custodian_id -> Unsigned<Bigint>,
         chemical_id -> Unsigned<Bigint>,
         storage_location -> Varchar,
-        amount -> Varchar,
+        quantity -> Double,
+        unit -> Varchar,
+        unparsed_amount -> Nullable<Varchar>,
+        received -> Nullable<Date>,
+        opened -> Nullable<Date>,
+        expires -> Nullable<Date>,
+        deleted_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    chemical_usage (id) {
+        id -> Unsigned<Bigint>,
+        inventory_id -> Unsigned<Bigint>,
+        user_id -> Unsigned<Bigint>,
+        quantity -> Double,
+        unit -> Varchar,
+        purpose -> Varchar,
+        remaining -> Double,
+        used -> Timestamp,
     }
 }
 
@@ -26,5 +53,12 @@ table! {
 //joinable!(chemical_inventory -> users (purchaser_id));
 //joinable!(chemical_inventory -> users (custodian_id));
 joinable!(chemical_inventory -> chemical (chemical_id));
+joinable!(chemical_usage -> chemical_inventory (inventory_id));
+joinable!(chemical_usage -> users (user_id));
 
-allow_tables_to_appear_in_same_query!(chemical, chemical_inventory, users,);
+allow_tables_to_appear_in_same_query!(
+    chemical,
+    chemical_inventory,
+    chemical_usage,
+    users,
+);
@@ -0,0 +1,25 @@
+use chrono::offset::Local;
+use chrono::offset::TimeZone;
+use chrono::DateTime;
+use chrono::NaiveDateTime;
+
+use log::error;
+
+use crate::errors::Error;
+use crate::errors::ErrorKind;
+
+/// Read a timestamp stored by the database as local time
+pub(crate) fn to_local(
+    datetime: NaiveDateTime,
+) -> Result<DateTime<Local>, Error> {
+    match Local.from_local_datetime(&datetime).earliest() {
+        Some(datetime) => Ok(datetime),
+        None => {
+            error!(
+                "Could not create a datetime from the database! {:?}",
+                datetime
+            );
+            Err(Error::new(ErrorKind::Database))
+        }
+    }
+}
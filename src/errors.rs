@@ -21,6 +21,24 @@ pub enum ErrorKind {
     OpenedTestTwice,
     OpeningClosedForTest,
     SubmissionsClosedForTest,
+    SubmittedTestNotOpened,
+    SubmittedQuestionNotServed,
+    SubmittedQuestionTwice,
+    DeletedServedQuestion,
+    SubmittedAfterTimeLimit,
+    InvalidSchedule,
+    RetakeLimitReached,
+    RetakeCooldown,
+    MissingCertification,
+    InvalidValidMonths,
+    InvalidQuantity,
+    InvalidMinimum,
+    IncompatibleUnits,
+    InsufficientQuantity,
+    UnparsedAmount,
+    InvalidDates,
+    InvalidCasNumber,
+    InvalidStatement,
     Unimplemented,
 }
 
@@ -58,6 +76,60 @@ impl std::fmt::Display for Error {
             ErrorKind::SubmissionsClosedForTest => {
                 write!(f, "The test session is closed for submissions")
             }
+            ErrorKind::SubmittedTestNotOpened => {
+                write!(f, "Submitted a test that was not opened")
+            }
+            ErrorKind::SubmittedQuestionNotServed => {
+                write!(f, "Submitted an answer to a question that was not served")
+            }
+            ErrorKind::SubmittedQuestionTwice => {
+                write!(f, "Submitted more than one answer to a question")
+            }
+            ErrorKind::DeletedServedQuestion => {
+                write!(f, "Can not delete a question that has been served")
+            }
+            ErrorKind::SubmittedAfterTimeLimit => {
+                write!(f, "Submitted the test after its time limit")
+            }
+            ErrorKind::InvalidSchedule => {
+                write!(f, "A test session window must start before it ends")
+            }
+            ErrorKind::RetakeLimitReached => {
+                write!(f, "No attempts are left for the test")
+            }
+            ErrorKind::RetakeCooldown => {
+                write!(f, "Too soon to retake the test")
+            }
+            ErrorKind::MissingCertification => {
+                write!(f, "Missing a certification required for access")
+            }
+            ErrorKind::InvalidValidMonths => {
+                write!(f, "Certifications can be valid for at most 1200 months")
+            }
+            ErrorKind::InvalidQuantity => {
+                write!(f, "Quantities must be a number that is not negative")
+            }
+            ErrorKind::InvalidMinimum => {
+                write!(f, "A minimum needs both a quantity and a unit")
+            }
+            ErrorKind::IncompatibleUnits => {
+                write!(f, "The units can not be converted between each other")
+            }
+            ErrorKind::InsufficientQuantity => {
+                write!(f, "Not enough of the chemical is left")
+            }
+            ErrorKind::UnparsedAmount => {
+                write!(f, "The amount of the chemical must be fixed first")
+            }
+            ErrorKind::InvalidDates => {
+                write!(f, "A container can not be opened before it is received")
+            }
+            ErrorKind::InvalidCasNumber => {
+                write!(f, "CAS numbers must look like 7732-18-5, with a correct check digit")
+            }
+            ErrorKind::InvalidStatement => {
+                write!(f, "Hazard and precautionary statements must be GHS codes, such as H225 or P210")
+            }
         }
     }
 }
@@ -193,6 +265,61 @@ impl From<Error> for rouille::Response {
             ErrorKind::SubmissionsClosedForTest => {
                 rouille::Response::text(e.to_string()).with_status_code(409)
             }
+            ErrorKind::SubmittedTestNotOpened => {
+                rouille::Response::text(e.to_string()).with_status_code(409)
+            }
+            ErrorKind::SubmittedQuestionNotServed => {
+                rouille::Response::text(e.to_string()).with_status_code(400)
+            }
+            ErrorKind::SubmittedQuestionTwice => {
+                rouille::Response::text(e.to_string()).with_status_code(400)
+            }
+            ErrorKind::DeletedServedQuestion => {
+                rouille::Response::text(e.to_string()).with_status_code(409)
+            }
+            ErrorKind::SubmittedAfterTimeLimit => {
+                rouille::Response::text(e.to_string()).with_status_code(409)
+            }
+            ErrorKind::InvalidSchedule => {
+                rouille::Response::text(e.to_string()).with_status_code(400)
+            }
+            ErrorKind::RetakeLimitReached => {
+                rouille::Response::text(e.to_string()).with_status_code(409)
+            }
+            ErrorKind::RetakeCooldown => {
+                rouille::Response::text(e.to_string()).with_status_code(409)
+            }
+            ErrorKind::MissingCertification => {
+                rouille::Response::text(e.to_string_with_source())
+                    .with_status_code(403)
+            }
+            ErrorKind::InvalidValidMonths => {
+                rouille::Response::text(e.to_string()).with_status_code(400)
+            }
+            ErrorKind::InvalidQuantity => {
+                rouille::Response::text(e.to_string()).with_status_code(400)
+            }
+            ErrorKind::InvalidMinimum => {
+                rouille::Response::text(e.to_string()).with_status_code(400)
+            }
+            ErrorKind::IncompatibleUnits => {
+                rouille::Response::text(e.to_string()).with_status_code(400)
+            }
+            ErrorKind::InsufficientQuantity => {
+                rouille::Response::text(e.to_string()).with_status_code(409)
+            }
+            ErrorKind::UnparsedAmount => {
+                rouille::Response::text(e.to_string()).with_status_code(409)
+            }
+            ErrorKind::InvalidDates => {
+                rouille::Response::text(e.to_string()).with_status_code(400)
+            }
+            ErrorKind::InvalidCasNumber => {
+                rouille::Response::text(e.to_string()).with_status_code(400)
+            }
+            ErrorKind::InvalidStatement => {
+                rouille::Response::text(e.to_string()).with_status_code(400)
+            }
             ErrorKind::Unimplemented => {
                 rouille::Response::text(e.to_string()).with_status_code(501)
             }
@@ -6,8 +6,15 @@ extern crate diesel_migrations;
 extern crate google_signin;
 
 pub mod access;
+pub mod audit;
+pub mod certifications;
 pub mod chemicals;
+mod dates;
 pub mod errors;
+mod nullable;
 pub mod search;
+pub mod sessions;
 pub mod tests;
+#[cfg(test)]
+mod testing;
 pub mod users;
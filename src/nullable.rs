@@ -0,0 +1,34 @@
+use serde::Deserialize;
+use serde::Deserializer;
+
+/// Read an update field that can be set back to `NULL`
+///
+/// serde reads a `null` the same as a missing field, so a plain
+/// `Option<Option<_>>` can never clear a column. Use this with
+/// `#[serde(default, deserialize_with = "deserialize_nullable")]` instead: a
+/// missing field stays `None`, leaving the column alone, and `null` becomes
+/// `Some(None)`, clearing it.
+pub(crate) fn deserialize_nullable<'de, T, D>(
+    deserializer: D,
+) -> Result<Option<Option<T>>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+#[test]
+fn deserialize_nullable_tells_null_from_missing() {
+    #[derive(Deserialize)]
+    struct Update {
+        #[serde(default, deserialize_with = "deserialize_nullable")]
+        value: Option<Option<u32>>,
+    }
+
+    let read = |json: &str| serde_json::from_str::<Update>(json).unwrap().value;
+
+    assert_eq!(read(r#"{}"#), None);
+    assert_eq!(read(r#"{"value": null}"#), Some(None));
+    assert_eq!(read(r#"{"value": 3}"#), Some(Some(3)));
+}
@@ -1,17 +1,41 @@
+use diesel::dsl::sql;
+use diesel::dsl::AsExprOf;
+use diesel::expression::array_comparison::{AsInExpression, MaybeEmpty};
+use diesel::expression::{
+    AppearsOnTable, AsExpression, BoxableExpression, Expression, NonAggregate,
+    SelectableExpression,
+};
+use diesel::helper_types::{Asc, Desc};
+use diesel::mysql::Mysql;
+use diesel::query_builder::{AstPass, QueryFragment};
+use diesel::query_dsl::methods::{FilterDsl, LimitDsl, OffsetDsl, OrderDsl};
+use diesel::sql_types::{Bool, Integer, SingleValue, Text};
+use diesel::BoolExpressionMethods;
+use diesel::ExpressionMethods;
+use diesel::QueryResult;
+use diesel::TextExpressionMethods;
+
+/// Why a search query could not be read
+///
+/// Each error has the position, in bytes, of the bad part of the query.
 #[derive(Debug, PartialEq)]
 pub enum SearchParseError {
-    Kind(String),
-    Term(String),
+    Kind { position: usize, kind: String },
+    Term { position: usize, term: String },
+    Order { position: usize, order: String },
 }
 
 impl std::fmt::Display for SearchParseError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
-            SearchParseError::Kind(s) => {
-                write!(f, "Invalid search kind: {}", s)
+            SearchParseError::Kind { position, kind } => {
+                write!(f, "Invalid search kind at {}: {}", position, kind)
+            }
+            SearchParseError::Term { position, term } => {
+                write!(f, "Invalid search term at {}: {}", position, term)
             }
-            SearchParseError::Term(s) => {
-                write!(f, "Invalid search term: {}", s)
+            SearchParseError::Order { position, order } => {
+                write!(f, "Invalid sort order at {}: {}", position, order)
             }
         }
     }
@@ -19,8 +43,16 @@ impl std::fmt::Display for SearchParseError {
 
 impl std::error::Error for SearchParseError {}
 
+/// Kinds of search that ignore case when started with an `i`, like `ipartial`
+const TEXT_KINDS: [&str; 6] =
+    ["partial", "exact", "prefix", "suffix", "not", "in"];
+
 /// Search for a field that cannot be null
 ///
+/// Searches are read from queries like `partial,hello`, `in,a|b|c` or
+/// `between,1,10`. Text searches ignore case when their kind starts with an
+/// `i`, like `iexact,hello`.
+///
 /// Use a `NullableSearch<T>` when a field could be null instead of `Search<Option<T>>`
 #[derive(Debug, PartialEq)]
 pub enum Search<T> {
@@ -30,40 +62,585 @@ pub enum Search<T> {
     /// Field fully matches
     Exact(T),
 
+    /// Field starts with
+    Prefix(T),
+
+    /// Field ends with
+    Suffix(T),
+
+    /// Field does not fully match
+    Not(T),
+
+    /// Field fully matches any of these
+    In(Vec<T>),
+
+    /// Field is greater than
+    GreaterThan(T),
+
+    /// Field is less than
+    LessThan(T),
+
+    /// Field is between these, inclusive
+    Between(T, T),
+
+    /// Field matches the search, ignoring case
+    IgnoreCase(Box<Search<T>>),
+
     /// Do not search by this field
     NoSearch,
 }
 
 impl<T: std::str::FromStr> Search<T> {
     pub fn from_query(query: &str) -> Result<Search<T>, SearchParseError> {
-        let mut query_iter = query.split(',');
-
-        let kind = query_iter.next().map(|s| s.trim());
-        let term = query_iter.next().map(|s| s.trim());
-
-        match (kind, term) {
-            (Some("partial"), Some(s)) => s
-                .trim()
-                .parse()
-                .map(|p| Search::Partial(p))
-                .map_err(|_| SearchParseError::Term(s.to_owned())),
-            (Some("exact"), Some(s)) => s
-                .trim()
-                .parse()
-                .map(|p| Search::Exact(p))
-                .map_err(|_| SearchParseError::Term(s.to_owned())),
-            (Some("partial"), None) => {
-                Err(SearchParseError::Term("".to_owned()))
+        let ((kind_position, full_kind), terms) = split_kind(query);
+
+        let (ignore_case, kind) = match full_kind.strip_prefix('i') {
+            Some(kind) if TEXT_KINDS.contains(&kind) => (true, kind),
+            _ => (false, full_kind),
+        };
+
+        let search = match kind {
+            "partial" => Search::Partial(parse_term(required(terms, query)?)?),
+            "exact" => Search::Exact(parse_term(required(terms, query)?)?),
+            "prefix" => Search::Prefix(parse_term(required(terms, query)?)?),
+            "suffix" => Search::Suffix(parse_term(required(terms, query)?)?),
+            "not" => Search::Not(parse_term(required(terms, query)?)?),
+            "gt" => Search::GreaterThan(parse_term(required(terms, query)?)?),
+            "lt" => Search::LessThan(parse_term(required(terms, query)?)?),
+            "in" => Search::In(
+                split_terms(required(terms, query)?, '|')
+                    .into_iter()
+                    .map(parse_term)
+                    .collect::<Result<_, _>>()?,
+            ),
+            "between" => {
+                match split_terms(required(terms, query)?, ',').as_slice() {
+                    [lower, upper] => Search::Between(
+                        parse_term(*lower)?,
+                        parse_term(*upper)?,
+                    ),
+                    [_] => return Err(missing_term(query)),
+                    [_, _, (position, term), ..] => {
+                        return Err(SearchParseError::Term {
+                            position: *position,
+                            term: (*term).to_owned(),
+                        })
+                    }
+                    [] => return Err(missing_term(query)),
+                }
+            }
+            _ => {
+                return Err(SearchParseError::Kind {
+                    position: kind_position,
+                    kind: full_kind.to_owned(),
+                })
+            }
+        };
+
+        if ignore_case {
+            Ok(Search::IgnoreCase(Box::new(search)))
+        } else {
+            Ok(search)
+        }
+    }
+
+    /// Read a search for a field holding a list, which has no order to
+    /// compare by
+    pub fn from_list_query(query: &str) -> Result<Search<T>, SearchParseError> {
+        let ((kind_position, kind), _) = split_kind(query);
+
+        match kind {
+            "gt" | "lt" | "between" => Err(SearchParseError::Kind {
+                position: kind_position,
+                kind: kind.to_owned(),
+            }),
+            _ => Search::from_query(query),
+        }
+    }
+}
+
+impl<T> Search<T> {
+    /// The terms being searched for
+    pub fn terms(&self) -> Vec<&T> {
+        match self {
+            Search::Partial(t)
+            | Search::Exact(t)
+            | Search::Prefix(t)
+            | Search::Suffix(t)
+            | Search::Not(t)
+            | Search::GreaterThan(t)
+            | Search::LessThan(t) => vec![t],
+            Search::In(ts) => ts.iter().collect(),
+            Search::Between(lower, upper) => vec![lower, upper],
+            Search::IgnoreCase(search) => search.terms(),
+            Search::NoSearch => Vec::new(),
+        }
+    }
+
+    /// The same search with each term converted, if they all can be
+    pub fn try_map<U>(
+        &self,
+        f: &dyn Fn(&T) -> Option<U>,
+    ) -> Option<Search<U>> {
+        Some(match self {
+            Search::Partial(t) => Search::Partial(f(t)?),
+            Search::Exact(t) => Search::Exact(f(t)?),
+            Search::Prefix(t) => Search::Prefix(f(t)?),
+            Search::Suffix(t) => Search::Suffix(f(t)?),
+            Search::Not(t) => Search::Not(f(t)?),
+            Search::In(ts) => {
+                Search::In(ts.iter().map(f).collect::<Option<_>>()?)
+            }
+            Search::GreaterThan(t) => Search::GreaterThan(f(t)?),
+            Search::LessThan(t) => Search::LessThan(f(t)?),
+            Search::Between(lower, upper) => {
+                Search::Between(f(lower)?, f(upper)?)
+            }
+            Search::IgnoreCase(search) => {
+                Search::IgnoreCase(Box::new(search.try_map(f)?))
+            }
+            Search::NoSearch => Search::NoSearch,
+        })
+    }
+}
+
+impl<T: Clone + std::fmt::Display> Search<T> {
+    /// Filter a query to the rows where `column` matches this search
+    ///
+    /// Columns that are not text, like ids, are cast to text for partial,
+    /// prefix and suffix searches, so `partial,1234` finds banner ids with
+    /// `1234` anywhere in them. Search whole number columns with `Digits`
+    /// terms, so that these searches keep any leading zeros.
+    pub fn filter<'a, Q, QS, C, ST>(&self, query: Q, column: C) -> Q
+    where
+        Q: FilterDsl<Condition<'a, QS>, Output = Q>,
+        C: Operand<'a, QS> + Expression<SqlType = ST> + Copy,
+        ST: SingleValue,
+        T: AsExpression<ST>,
+        AsExprOf<T, ST>: Operand<'a, QS>,
+        Vec<T>: AsInExpression<ST>,
+        <Vec<T> as AsInExpression<ST>>::InExpression: InValues<'a, QS>,
+    {
+        match self.condition(column) {
+            Some(condition) => query.filter(condition),
+            None => query,
+        }
+    }
+
+    /// Build the condition for `column` matching this search, if searching
+    pub fn condition<'a, QS, C, ST>(
+        &self,
+        column: C,
+    ) -> Option<Condition<'a, QS>>
+    where
+        C: Operand<'a, QS> + Expression<SqlType = ST> + Copy,
+        ST: SingleValue,
+        T: AsExpression<ST>,
+        AsExprOf<T, ST>: Operand<'a, QS>,
+        Vec<T>: AsInExpression<ST>,
+        <Vec<T> as AsInExpression<ST>>::InExpression: InValues<'a, QS>,
+    {
+        match self {
+            Search::Partial(t) => {
+                Some(Box::new(CastToText(column).like(format!("%{}%", t))))
+            }
+            Search::Prefix(t) => {
+                Some(Box::new(CastToText(column).like(format!("{}%", t))))
+            }
+            Search::Suffix(t) => {
+                Some(Box::new(CastToText(column).like(format!("%{}", t))))
+            }
+            Search::IgnoreCase(search) => search.condition(column),
+            search => search.comparison(column),
+        }
+    }
+
+    /// Build the condition for the searches that compare whole values
+    fn comparison<'a, QS, C, ST>(&self, column: C) -> Option<Condition<'a, QS>>
+    where
+        C: Operand<'a, QS> + Expression<SqlType = ST> + Copy,
+        ST: SingleValue,
+        T: AsExpression<ST>,
+        AsExprOf<T, ST>: Operand<'a, QS>,
+        Vec<T>: AsInExpression<ST>,
+        <Vec<T> as AsInExpression<ST>>::InExpression: InValues<'a, QS>,
+    {
+        match self {
+            Search::Exact(t) => Some(Box::new(column.eq(t.clone()))),
+            Search::Not(t) => Some(Box::new(column.ne(t.clone()))),
+            Search::In(ts) => Some(Box::new(column.eq_any(ts.clone()))),
+            Search::GreaterThan(t) => Some(Box::new(column.gt(t.clone()))),
+            Search::LessThan(t) => Some(Box::new(column.lt(t.clone()))),
+            Search::Between(lower, upper) => {
+                Some(Box::new(column.between(lower.clone(), upper.clone())))
+            }
+            Search::Partial(_)
+            | Search::Prefix(_)
+            | Search::Suffix(_)
+            | Search::IgnoreCase(_)
+            | Search::NoSearch => None,
+        }
+    }
+}
+
+impl Search<String> {
+    /// Filter a query to the rows where the text `column` matches this search
+    pub fn filter_text<'a, Q, QS, C, ST>(&self, query: Q, column: C) -> Q
+    where
+        Q: FilterDsl<Condition<'a, QS>, Output = Q>,
+        C: Operand<'a, QS> + TextExpressionMethods<SqlType = ST> + Copy,
+        Lower<C>: TextExpressionMethods<SqlType = ST>,
+        ST: SingleValue,
+        String: AsExpression<ST>,
+        AsExprOf<String, ST>: Operand<'a, QS>,
+        Vec<String>: AsInExpression<ST>,
+        <Vec<String> as AsInExpression<ST>>::InExpression: InValues<'a, QS>,
+    {
+        match self.text_condition(column) {
+            Some(condition) => query.filter(condition),
+            None => query,
+        }
+    }
+
+    /// Build the condition for the text `column` matching this search, if
+    /// searching
+    pub fn text_condition<'a, QS, C, ST>(
+        &self,
+        column: C,
+    ) -> Option<Condition<'a, QS>>
+    where
+        C: Operand<'a, QS> + TextExpressionMethods<SqlType = ST> + Copy,
+        Lower<C>: TextExpressionMethods<SqlType = ST>,
+        ST: SingleValue,
+        String: AsExpression<ST>,
+        AsExprOf<String, ST>: Operand<'a, QS>,
+        Vec<String>: AsInExpression<ST>,
+        <Vec<String> as AsInExpression<ST>>::InExpression: InValues<'a, QS>,
+    {
+        match self {
+            Search::IgnoreCase(search) => {
+                search.to_lowercase().text_match(Lower(column))
+            }
+            search => search.text_match(column),
+        }
+    }
+
+    fn text_match<'a, QS, C, ST>(&self, column: C) -> Option<Condition<'a, QS>>
+    where
+        C: Operand<'a, QS> + TextExpressionMethods<SqlType = ST> + Copy,
+        ST: SingleValue,
+        String: AsExpression<ST>,
+        AsExprOf<String, ST>: Operand<'a, QS>,
+        Vec<String>: AsInExpression<ST>,
+        <Vec<String> as AsInExpression<ST>>::InExpression: InValues<'a, QS>,
+    {
+        match self {
+            Search::Partial(s) => {
+                Some(Box::new(column.like(format!("%{}%", s))))
+            }
+            Search::Prefix(s) => Some(Box::new(column.like(format!("{}%", s)))),
+            Search::Suffix(s) => Some(Box::new(column.like(format!("%{}", s)))),
+            Search::IgnoreCase(search) => search.text_match(column),
+            search => search.comparison(column),
+        }
+    }
+
+    /// Filter a query to the rows where the comma separated list in the text
+    /// `column` matches this search
+    pub fn filter_list<'a, Q, QS, C>(&self, query: Q, column: C) -> Q
+    where
+        Q: FilterDsl<Condition<'a, QS>, Output = Q>,
+        C: Operand<'a, QS> + Expression<SqlType = Text> + Copy,
+        QS: 'a,
+    {
+        match self.list_condition(column) {
+            Some(condition) => query.filter(condition),
+            None => query,
+        }
+    }
+
+    /// Build the condition for the comma separated list in the text `column`
+    /// matching this search, if searching
+    ///
+    /// Exact, not and in searches compare whole members of the list, so
+    /// `exact,H225` matches `H225,H319` but not `H2250`. Prefix and suffix
+    /// searches match the start or end of any member.
+    pub fn list_condition<'a, QS, C>(
+        &self,
+        column: C,
+    ) -> Option<Condition<'a, QS>>
+    where
+        C: Operand<'a, QS> + Expression<SqlType = Text> + Copy,
+        QS: 'a,
+    {
+        match self {
+            Search::IgnoreCase(search) => {
+                search.to_lowercase().list_match(Lower(column))
+            }
+            search => search.list_match(column),
+        }
+    }
+
+    fn list_match<'a, QS, C>(&self, column: C) -> Option<Condition<'a, QS>>
+    where
+        C: Operand<'a, QS> + Expression<SqlType = Text> + Copy,
+        QS: 'a,
+    {
+        let has_member = |s: &String| -> Condition<'a, QS> {
+            Box::new(find_in_set(s.clone(), column).gt(0))
+        };
+
+        match self {
+            Search::Partial(s) => {
+                Some(Box::new(column.like(format!("%{}%", s))))
+            }
+            Search::Prefix(s) => Some(Box::new(
+                column
+                    .like(format!("{}%", s))
+                    .or(column.like(format!("%,{}%", s))),
+            )),
+            Search::Suffix(s) => Some(Box::new(
+                column
+                    .like(format!("%{}", s))
+                    .or(column.like(format!("%{},%", s))),
+            )),
+            Search::Exact(s) => Some(has_member(s)),
+            Search::Not(s) => {
+                Some(Box::new(find_in_set(s.clone(), column).eq(0)))
+            }
+            Search::In(ss) => Some(
+                ss.iter()
+                    .map(has_member)
+                    .fold(
+                        None,
+                        |conditions: Option<Condition<_>>, condition| {
+                            match conditions {
+                                Some(conditions) => {
+                                    Some(Box::new(conditions.or(condition)))
+                                }
+                                None => Some(condition),
+                            }
+                        },
+                    )
+                    .unwrap_or_else(|| Box::new(sql::<Bool>("FALSE"))),
+            ),
+            Search::IgnoreCase(search) => search.list_match(column),
+            search => search.comparison(column),
+        }
+    }
+
+    fn to_lowercase(&self) -> Search<String> {
+        match self {
+            Search::Partial(s) => Search::Partial(s.to_lowercase()),
+            Search::Exact(s) => Search::Exact(s.to_lowercase()),
+            Search::Prefix(s) => Search::Prefix(s.to_lowercase()),
+            Search::Suffix(s) => Search::Suffix(s.to_lowercase()),
+            Search::Not(s) => Search::Not(s.to_lowercase()),
+            Search::In(ss) => {
+                Search::In(ss.iter().map(|s| s.to_lowercase()).collect())
             }
-            (Some("exact"), None) => Err(SearchParseError::Term("".to_owned())),
-            (Some(k), Some(_)) => Err(SearchParseError::Kind(k.to_owned())),
-            (Some(k), None) => Err(SearchParseError::Kind(k.to_owned())),
-            (None, Some(_)) => Err(SearchParseError::Kind("".to_owned())),
-            (None, None) => Err(SearchParseError::Kind("".to_owned())),
+            Search::GreaterThan(s) => Search::GreaterThan(s.to_lowercase()),
+            Search::LessThan(s) => Search::LessThan(s.to_lowercase()),
+            Search::Between(lower, upper) => {
+                Search::Between(lower.to_lowercase(), upper.to_lowercase())
+            }
+            Search::IgnoreCase(search) => search.to_lowercase(),
+            Search::NoSearch => Search::NoSearch,
         }
     }
 }
 
+/// A condition on the columns of `QS` that a boxed query can be filtered by
+pub type Condition<'a, QS> =
+    Box<dyn BoxableExpression<QS, Mysql, SqlType = Bool> + 'a>;
+
+/// An expression that can be part of a `Condition` on the columns of `QS`
+pub trait Operand<'a, QS>:
+    SelectableExpression<QS> + NonAggregate + QueryFragment<Mysql> + 'a
+{
+}
+
+impl<'a, QS, E> Operand<'a, QS> for E where
+    E: SelectableExpression<QS> + NonAggregate + QueryFragment<Mysql> + 'a
+{
+}
+
+/// The values a `Condition` can check a column is in
+pub trait InValues<'a, QS>:
+    SelectableExpression<QS> + QueryFragment<Mysql> + MaybeEmpty + 'a
+{
+}
+
+impl<'a, QS, E> InValues<'a, QS> for E where
+    E: SelectableExpression<QS> + QueryFragment<Mysql> + MaybeEmpty + 'a
+{
+}
+
+sql_function! {
+    /// `FIND_IN_SET(member, list)`, the position of `member` in the comma
+    /// separated `list`, or 0 if it is not in it
+    fn find_in_set(member: Text, list: Text) -> Integer;
+}
+
+/// `LOWER(expression)`, for searching text without regard to case
+#[derive(Debug, Clone, Copy, QueryId)]
+pub struct Lower<E>(E);
+
+impl<E: Expression> Expression for Lower<E> {
+    type SqlType = E::SqlType;
+}
+
+impl<E: NonAggregate> NonAggregate for Lower<E> {}
+
+impl<E: AppearsOnTable<QS>, QS> AppearsOnTable<QS> for Lower<E> {}
+
+impl<E: SelectableExpression<QS>, QS> SelectableExpression<QS> for Lower<E> {}
+
+impl<E: QueryFragment<Mysql>> QueryFragment<Mysql> for Lower<E> {
+    fn walk_ast(&self, mut out: AstPass<Mysql>) -> QueryResult<()> {
+        out.push_sql("LOWER(");
+        self.0.walk_ast(out.reborrow())?;
+        out.push_sql(")");
+        Ok(())
+    }
+}
+
+/// `CAST(expression AS CHAR)`, for partially searching columns that are not
+/// text
+#[derive(Debug, Clone, Copy, QueryId)]
+pub struct CastToText<E>(E);
+
+impl<E: Expression> Expression for CastToText<E> {
+    type SqlType = Text;
+}
+
+impl<E: NonAggregate> NonAggregate for CastToText<E> {}
+
+impl<E: AppearsOnTable<QS>, QS> AppearsOnTable<QS> for CastToText<E> {}
+
+impl<E: SelectableExpression<QS>, QS> SelectableExpression<QS>
+    for CastToText<E>
+{
+}
+
+impl<E: QueryFragment<Mysql>> QueryFragment<Mysql> for CastToText<E> {
+    fn walk_ast(&self, mut out: AstPass<Mysql>) -> QueryResult<()> {
+        out.push_sql("CAST(");
+        self.0.walk_ast(out.reborrow())?;
+        out.push_sql(" AS CHAR)");
+        Ok(())
+    }
+}
+
+/// A whole number search term, along with the digits it was written with
+///
+/// Comparisons use the number, while partial, prefix and suffix searches use
+/// the digits, so `suffix,0042` does not also find numbers ending in `1242`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Digits<T> {
+    pub value: T,
+    digits: String,
+}
+
+impl<T: std::str::FromStr> std::str::FromStr for Digits<T> {
+    type Err = ();
+
+    fn from_str(term: &str) -> Result<Digits<T>, ()> {
+        if term.is_empty() || !term.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(());
+        }
+
+        Ok(Digits {
+            value: term.parse().map_err(|_| ())?,
+            digits: term.to_owned(),
+        })
+    }
+}
+
+impl<T> std::fmt::Display for Digits<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.digits)
+    }
+}
+
+impl<T: AsExpression<ST>, ST> AsExpression<ST> for Digits<T> {
+    type Expression = T::Expression;
+
+    fn as_expression(self) -> T::Expression {
+        self.value.as_expression()
+    }
+}
+
+/// Trim part of a query, along with the position it starts at
+fn trim_part(part: &str, position: usize) -> (usize, &str) {
+    let trimmed = part.trim_start();
+    (position + part.len() - trimmed.len(), trimmed.trim_end())
+}
+
+/// Split a query into its kind and its terms, if it has any
+fn split_kind(query: &str) -> ((usize, &str), Option<(usize, &str)>) {
+    match query.find(',') {
+        Some(i) => (
+            trim_part(&query[..i], 0),
+            Some(trim_part(&query[i + 1..], i + 1)),
+        ),
+        None => (trim_part(query, 0), None),
+    }
+}
+
+/// Split the terms of a query, keeping track of where each one starts
+fn split_terms(
+    (position, terms): (usize, &str),
+    separator: char,
+) -> Vec<(usize, &str)> {
+    let mut start = position;
+
+    terms
+        .split(separator)
+        .map(|term| {
+            let part = trim_part(term, start);
+            start += term.len() + separator.len_utf8();
+            part
+        })
+        .collect()
+}
+
+fn required<'q>(
+    terms: Option<(usize, &'q str)>,
+    query: &str,
+) -> Result<(usize, &'q str), SearchParseError> {
+    terms.ok_or_else(|| missing_term(query))
+}
+
+fn missing_term(query: &str) -> SearchParseError {
+    SearchParseError::Term {
+        position: query.len(),
+        term: "".to_owned(),
+    }
+}
+
+fn parse_term<T: std::str::FromStr>(
+    (position, term): (usize, &str),
+) -> Result<T, SearchParseError> {
+    term.parse().map_err(|_| SearchParseError::Term {
+        position,
+        term: term.to_owned(),
+    })
+}
+
+/// Parse a limit or offset, which MySQL reads as a signed `BIGINT`
+fn parse_page_term(
+    (position, term): (usize, &str),
+) -> Result<u64, SearchParseError> {
+    match parse_term::<u64>((position, term))? {
+        value if value <= i64::MAX as u64 => Ok(value),
+        _ => Err(SearchParseError::Term {
+            position,
+            term: term.to_owned(),
+        }),
+    }
+}
+
 #[test]
 fn parse_search_partial_search_works() {
     let s = Search::from_query(" partial , hello ");
@@ -76,40 +653,186 @@ fn parse_search_exact_search_works() {
     assert_eq!(s, Ok(Search::Exact("hello".to_owned())));
 }
 
+#[test]
+fn parse_search_prefix_suffix_and_not_work() {
+    let s = Search::from_query("prefix,hel");
+    assert_eq!(s, Ok(Search::Prefix("hel".to_owned())));
+
+    let s = Search::from_query("suffix,llo");
+    assert_eq!(s, Ok(Search::Suffix("llo".to_owned())));
+
+    let s = Search::from_query("not,hello");
+    assert_eq!(s, Ok(Search::Not("hello".to_owned())));
+}
+
+#[test]
+fn parse_search_terms_can_have_commas() {
+    let s = Search::from_query("exact,Smith, John");
+    assert_eq!(s, Ok(Search::Exact("Smith, John".to_owned())));
+}
+
+#[test]
+fn parse_search_in_works() {
+    let s: Result<Search<u64>, _> = Search::from_query("in, 1 | 2|3");
+    assert_eq!(s, Ok(Search::In(vec![1, 2, 3])));
+}
+
+#[test]
+fn parse_search_ranges_work() {
+    let s: Result<Search<u64>, _> = Search::from_query("gt,5");
+    assert_eq!(s, Ok(Search::GreaterThan(5)));
+
+    let s: Result<Search<u64>, _> = Search::from_query("lt,5");
+    assert_eq!(s, Ok(Search::LessThan(5)));
+
+    let s: Result<Search<u64>, _> = Search::from_query("between, 5, 10");
+    assert_eq!(s, Ok(Search::Between(5, 10)));
+}
+
+#[test]
+fn parse_search_ignore_case_works() {
+    let s = Search::from_query("ipartial,Hello");
+    assert_eq!(
+        s,
+        Ok(Search::IgnoreCase(Box::new(Search::Partial(
+            "Hello".to_owned()
+        ))))
+    );
+
+    let s = Search::from_query("iin,a|B");
+    assert_eq!(
+        s,
+        Ok(Search::IgnoreCase(Box::new(Search::In(vec![
+            "a".to_owned(),
+            "B".to_owned()
+        ]))))
+    );
+}
+
+#[test]
+fn parse_search_ignore_case_only_for_text_kinds() {
+    let s: Result<Search<u64>, _> = Search::from_query("igt,5");
+    assert_eq!(
+        s,
+        Err(SearchParseError::Kind {
+            position: 0,
+            kind: "igt".to_owned()
+        })
+    );
+}
+
+#[test]
+fn parse_search_invalid_term_has_position() {
+    let s: Result<Search<u64>, _> = Search::from_query("in,1|two|3");
+    assert_eq!(
+        s,
+        Err(SearchParseError::Term {
+            position: 5,
+            term: "two".to_owned()
+        })
+    );
+
+    let s: Result<Search<u64>, _> = Search::from_query("exact, one");
+    assert_eq!(
+        s,
+        Err(SearchParseError::Term {
+            position: 7,
+            term: "one".to_owned()
+        })
+    );
+}
+
+#[test]
+fn parse_search_between_needs_two_terms() {
+    let s: Result<Search<u64>, _> = Search::from_query("between,5");
+    assert_eq!(
+        s,
+        Err(SearchParseError::Term {
+            position: 9,
+            term: "".to_owned()
+        })
+    );
+
+    let s: Result<Search<u64>, _> = Search::from_query("between,1,2,3");
+    assert_eq!(
+        s,
+        Err(SearchParseError::Term {
+            position: 12,
+            term: "3".to_owned()
+        })
+    );
+}
+
 #[test]
 fn parse_search_invalid_kind_with_term_fails() {
     let s: Result<Search<String>, _> = Search::from_query("hello, bye");
-    assert_eq!(s, Err(SearchParseError::Kind("hello".to_owned())));
+    assert_eq!(
+        s,
+        Err(SearchParseError::Kind {
+            position: 0,
+            kind: "hello".to_owned()
+        })
+    );
 }
 
 #[test]
 fn parse_search_no_kind_with_term_fails() {
     let s: Result<Search<String>, _> = Search::from_query(", bye");
-    assert_eq!(s, Err(SearchParseError::Kind("".to_owned())));
+    assert_eq!(
+        s,
+        Err(SearchParseError::Kind {
+            position: 0,
+            kind: "".to_owned()
+        })
+    );
 }
 
 #[test]
 fn parse_search_partial_with_no_term_fails() {
     let s: Result<Search<String>, _> = Search::from_query(" partial");
-    assert_eq!(s, Err(SearchParseError::Term("".to_owned())));
+    assert_eq!(
+        s,
+        Err(SearchParseError::Term {
+            position: 8,
+            term: "".to_owned()
+        })
+    );
 }
 
 #[test]
 fn parse_search_exact_with_no_term_fails() {
     let s: Result<Search<String>, _> = Search::from_query(" exact");
-    assert_eq!(s, Err(SearchParseError::Term("".to_owned())));
+    assert_eq!(
+        s,
+        Err(SearchParseError::Term {
+            position: 6,
+            term: "".to_owned()
+        })
+    );
 }
 
 #[test]
 fn parse_search_invalid_with_no_term_fails() {
     let s: Result<Search<String>, _> = Search::from_query("hello");
-    assert_eq!(s, Err(SearchParseError::Kind("hello".to_owned())));
+    assert_eq!(
+        s,
+        Err(SearchParseError::Kind {
+            position: 0,
+            kind: "hello".to_owned()
+        })
+    );
 }
 
 #[test]
 fn parse_search_empty_string_fails() {
     let s: Result<Search<String>, _> = Search::from_query("");
-    assert_eq!(s, Err(SearchParseError::Kind("".to_owned())));
+    assert_eq!(
+        s,
+        Err(SearchParseError::Kind {
+            position: 0,
+            kind: "".to_owned()
+        })
+    );
 }
 
 /// Search fo a field that can be null
@@ -130,11 +853,8 @@ fn parse_search_empty_string_fails() {
 ///
 #[derive(Debug, PartialEq)]
 pub enum NullableSearch<T> {
-    /// Field is not null and partially matches
-    Partial(T),
-
-    /// Field is not null and exactly matches
-    Exact(T),
+    /// Field is not null and matches the search
+    Matches(Search<T>),
 
     /// Field is not null
     /// (`Some` matches Rust terminology better than `NonNull` or similar)
@@ -152,43 +872,70 @@ impl<T: std::str::FromStr> NullableSearch<T> {
     pub fn from_query(
         query: &str,
     ) -> Result<NullableSearch<T>, SearchParseError> {
-        let mut query_iter = query.split(',');
-
-        let kind = query_iter.next().map(|s| s.trim());
-        let term = query_iter.next().map(|s| s.trim());
-
-        match (kind, term) {
-            (Some("partial"), Some(s)) => s
-                .trim()
-                .parse()
-                .map(|p| NullableSearch::Partial(p))
-                .map_err(|_| SearchParseError::Term(s.to_owned())),
-            (Some("exact"), Some(s)) => s
-                .trim()
-                .parse()
-                .map(|p| NullableSearch::Exact(p))
-                .map_err(|_| SearchParseError::Term(s.to_owned())),
-
-            (Some("some"), None) => Ok(NullableSearch::Some),
-            (Some("none"), None) => Ok(NullableSearch::None),
-
-            (Some("partial"), None) => {
-                Err(SearchParseError::Term("".to_owned()))
+        let ((_, kind), terms) = split_kind(query);
+
+        match (kind, terms) {
+            ("some", None) => Ok(NullableSearch::Some),
+            ("none", None) => Ok(NullableSearch::None),
+
+            ("some", Some((position, term)))
+            | ("none", Some((position, term))) => Err(SearchParseError::Term {
+                position,
+                term: term.to_owned(),
+            }),
+
+            _ => Search::from_query(query).map(NullableSearch::Matches),
+        }
+    }
+}
+
+impl<T: Clone + std::fmt::Display> NullableSearch<T> {
+    /// Filter a query to the rows where the nullable `column` matches this
+    /// search
+    pub fn filter<'a, Q, QS, C, ST>(&self, query: Q, column: C) -> Q
+    where
+        Q: FilterDsl<Condition<'a, QS>, Output = Q>,
+        C: Operand<'a, QS> + Expression<SqlType = ST> + Copy,
+        ST: SingleValue,
+        T: AsExpression<ST>,
+        AsExprOf<T, ST>: Operand<'a, QS>,
+        Vec<T>: AsInExpression<ST>,
+        <Vec<T> as AsInExpression<ST>>::InExpression: InValues<'a, QS>,
+    {
+        match self {
+            NullableSearch::Matches(search) => search.filter(query, column),
+            NullableSearch::Some => {
+                query.filter(Box::new(column.is_not_null()))
             }
-            (Some("exact"), None) => Err(SearchParseError::Term("".to_owned())),
+            NullableSearch::None => query.filter(Box::new(column.is_null())),
+            NullableSearch::NoSearch => query,
+        }
+    }
+}
 
-            (Some("some"), Some(s)) => {
-                Err(SearchParseError::Term(s.to_owned()))
+impl NullableSearch<String> {
+    /// Filter a query to the rows where the nullable text `column` matches
+    /// this search
+    pub fn filter_text<'a, Q, QS, C, ST>(&self, query: Q, column: C) -> Q
+    where
+        Q: FilterDsl<Condition<'a, QS>, Output = Q>,
+        C: Operand<'a, QS> + TextExpressionMethods<SqlType = ST> + Copy,
+        Lower<C>: TextExpressionMethods<SqlType = ST>,
+        ST: SingleValue,
+        String: AsExpression<ST>,
+        AsExprOf<String, ST>: Operand<'a, QS>,
+        Vec<String>: AsInExpression<ST>,
+        <Vec<String> as AsInExpression<ST>>::InExpression: InValues<'a, QS>,
+    {
+        match self {
+            NullableSearch::Matches(search) => {
+                search.filter_text(query, column)
             }
-            (Some("none"), Some(s)) => {
-                Err(SearchParseError::Term(s.to_owned()))
+            NullableSearch::Some => {
+                query.filter(Box::new(column.is_not_null()))
             }
-
-            (Some(k), Some(_)) => Err(SearchParseError::Kind(k.to_owned())),
-            (Some(k), None) => Err(SearchParseError::Kind(k.to_owned())),
-
-            (None, Some(_)) => Err(SearchParseError::Kind("".to_owned())),
-            (None, None) => Err(SearchParseError::Kind("".to_owned())),
+            NullableSearch::None => query.filter(Box::new(column.is_null())),
+            NullableSearch::NoSearch => query,
         }
     }
 }
@@ -196,13 +943,32 @@ impl<T: std::str::FromStr> NullableSearch<T> {
 #[test]
 fn parse_nullable_search_partial_search_works() {
     let s = NullableSearch::from_query(" partial , hello ");
-    assert_eq!(s, Ok(NullableSearch::Partial("hello".to_owned())));
+    assert_eq!(
+        s,
+        Ok(NullableSearch::Matches(Search::Partial("hello".to_owned())))
+    );
 }
 
 #[test]
 fn parse_nullable_search_exact_search_works() {
     let s = NullableSearch::from_query(" exact, hello ");
-    assert_eq!(s, Ok(NullableSearch::Exact("hello".to_owned())));
+    assert_eq!(
+        s,
+        Ok(NullableSearch::Matches(Search::Exact("hello".to_owned())))
+    );
+}
+
+#[test]
+fn parse_nullable_search_other_kinds_work() {
+    let s: Result<NullableSearch<String>, _> =
+        NullableSearch::from_query("in,a|b");
+    assert_eq!(
+        s,
+        Ok(NullableSearch::Matches(Search::In(vec![
+            "a".to_owned(),
+            "b".to_owned()
+        ])))
+    );
 }
 
 #[test]
@@ -223,53 +989,483 @@ fn parse_nullable_search_none_works() {
 fn parse_nullable_search_some_with_term_fails() {
     let s: Result<NullableSearch<String>, _> =
         NullableSearch::from_query(" some, hello");
-    assert_eq!(s, Err(SearchParseError::Term("hello".to_owned())));
+    assert_eq!(
+        s,
+        Err(SearchParseError::Term {
+            position: 7,
+            term: "hello".to_owned()
+        })
+    );
 }
 
 #[test]
 fn parse_nullable_search_none_with_term_fails() {
     let s: Result<NullableSearch<String>, _> =
         NullableSearch::from_query(" none, hello");
-    assert_eq!(s, Err(SearchParseError::Term("hello".to_owned())));
+    assert_eq!(
+        s,
+        Err(SearchParseError::Term {
+            position: 7,
+            term: "hello".to_owned()
+        })
+    );
 }
 
 #[test]
 fn parse_nullable_search_invalid_kind_with_term_fails() {
     let s: Result<NullableSearch<String>, _> =
         NullableSearch::from_query("hello, bye");
-    assert_eq!(s, Err(SearchParseError::Kind("hello".to_owned())));
+    assert_eq!(
+        s,
+        Err(SearchParseError::Kind {
+            position: 0,
+            kind: "hello".to_owned()
+        })
+    );
 }
 
 #[test]
 fn parse_nullable_search_no_kind_with_term_fails() {
     let s: Result<NullableSearch<String>, _> =
         NullableSearch::from_query(", bye");
-    assert_eq!(s, Err(SearchParseError::Kind("".to_owned())));
+    assert_eq!(
+        s,
+        Err(SearchParseError::Kind {
+            position: 0,
+            kind: "".to_owned()
+        })
+    );
 }
 
 #[test]
 fn parse_nullable_search_partial_with_no_term_fails() {
     let s: Result<NullableSearch<String>, _> =
         NullableSearch::from_query(" partial");
-    assert_eq!(s, Err(SearchParseError::Term("".to_owned())));
+    assert_eq!(
+        s,
+        Err(SearchParseError::Term {
+            position: 8,
+            term: "".to_owned()
+        })
+    );
 }
 
 #[test]
 fn parse_nullable_search_exact_with_no_term_fails() {
     let s: Result<NullableSearch<String>, _> =
         NullableSearch::from_query(" exact");
-    assert_eq!(s, Err(SearchParseError::Term("".to_owned())));
+    assert_eq!(
+        s,
+        Err(SearchParseError::Term {
+            position: 6,
+            term: "".to_owned()
+        })
+    );
 }
 
 #[test]
 fn parse_nullable_search_invalid_with_no_term_fails() {
     let s: Result<NullableSearch<String>, _> =
         NullableSearch::from_query("hello");
-    assert_eq!(s, Err(SearchParseError::Kind("hello".to_owned())));
+    assert_eq!(
+        s,
+        Err(SearchParseError::Kind {
+            position: 0,
+            kind: "hello".to_owned()
+        })
+    );
 }
 
 #[test]
 fn parse_nullable_search_empty_string_fails() {
     let s: Result<NullableSearch<String>, _> = NullableSearch::from_query("");
-    assert_eq!(s, Err(SearchParseError::Kind("".to_owned())));
+    assert_eq!(
+        s,
+        Err(SearchParseError::Kind {
+            position: 0,
+            kind: "".to_owned()
+        })
+    );
+}
+
+/// Which way to sort search results
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SortOrder {
+    Ascending,
+    Descending,
+}
+
+impl SortOrder {
+    /// Order a query by a column, replacing any order it already had
+    pub fn order_by<Q, E>(self, query: Q, column: E) -> Q
+    where
+        E: ExpressionMethods,
+        Q: OrderDsl<Asc<E>, Output = Q> + OrderDsl<Desc<E>, Output = Q>,
+    {
+        match self {
+            SortOrder::Ascending => OrderDsl::order(query, column.asc()),
+            SortOrder::Descending => OrderDsl::order(query, column.desc()),
+        }
+    }
+}
+
+/// Sort search results by a field, such as `?sort=last_name,desc`
+///
+/// Results are sorted in ascending order when no order is given. Each search
+/// decides which fields it can be sorted by.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Sort {
+    pub field: String,
+    pub order: SortOrder,
+}
+
+impl Sort {
+    pub fn from_query(query: &str) -> Result<Sort, SearchParseError> {
+        let ((field_position, field), order) = split_kind(query);
+
+        let order = match order {
+            Some((_, "asc")) | None => SortOrder::Ascending,
+            Some((_, "desc")) => SortOrder::Descending,
+            Some((position, order)) => {
+                return Err(SearchParseError::Order {
+                    position,
+                    order: order.to_owned(),
+                })
+            }
+        };
+
+        if field.is_empty() {
+            return Err(SearchParseError::Term {
+                position: field_position,
+                term: "".to_owned(),
+            });
+        }
+
+        Ok(Sort {
+            field: field.to_owned(),
+            order,
+        })
+    }
+}
+
+/// How many results a page holds when no limit was given, for searches that
+/// look up each result on the page by id or that can grow too large to return
+/// at once
+pub const DEFAULT_LIMIT: u64 = 100;
+
+/// Which page of search results to return, from the `limit`, `offset` and
+/// `sort` queries
+///
+/// Without a limit, every result past the offset is returned.
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct Page {
+    pub limit: Option<u64>,
+    pub offset: u64,
+    pub sort: Option<Sort>,
+}
+
+impl Page {
+    /// Read one of the `limit`, `offset` or `sort` queries into the page
+    pub fn add_query(
+        &mut self,
+        field: &str,
+        query: &str,
+    ) -> Result<(), SearchParseError> {
+        match field {
+            "limit" => self.limit = Some(parse_page_term(trim_part(query, 0))?),
+            "offset" => self.offset = parse_page_term(trim_part(query, 0))?,
+            "sort" => self.sort = Some(Sort::from_query(query)?),
+            _ => {
+                return Err(SearchParseError::Kind {
+                    position: 0,
+                    kind: field.to_owned(),
+                })
+            }
+        }
+
+        Ok(())
+    }
+
+    /// This page, holding at most `limit` results unless a limit was given
+    pub fn or_limit(self, limit: u64) -> Page {
+        Page {
+            limit: Some(self.limit.unwrap_or(limit)),
+            ..self
+        }
+    }
+
+    /// Limit a query to the results on this page
+    pub fn paginate<Q>(&self, query: Q) -> Q
+    where
+        Q: LimitDsl<Output = Q> + OffsetDsl<Output = Q>,
+    {
+        // MySQL needs a limit to use an offset
+        let limit = self.limit.unwrap_or(u64::MAX).min(i64::MAX as u64);
+        let offset = self.offset.min(i64::MAX as u64);
+
+        query.limit(limit as i64).offset(offset as i64)
+    }
+
+    /// The offset of the page after this one, if there are results left
+    pub fn next(&self, total: u64) -> Option<u64> {
+        self.limit
+            .and_then(|limit| self.offset.checked_add(limit))
+            .filter(|&next| next < total)
+    }
+}
+
+#[test]
+fn parse_sort_works() {
+    assert_eq!(
+        Sort::from_query(" last_name , desc "),
+        Ok(Sort {
+            field: "last_name".to_owned(),
+            order: SortOrder::Descending,
+        })
+    );
+    assert_eq!(
+        Sort::from_query("last_name"),
+        Ok(Sort {
+            field: "last_name".to_owned(),
+            order: SortOrder::Ascending,
+        })
+    );
+}
+
+#[test]
+fn parse_sort_invalid_order_fails() {
+    assert_eq!(
+        Sort::from_query("last_name,sideways"),
+        Err(SearchParseError::Order {
+            position: 10,
+            order: "sideways".to_owned()
+        })
+    );
+    assert_eq!(
+        Sort::from_query(",desc"),
+        Err(SearchParseError::Term {
+            position: 0,
+            term: "".to_owned()
+        })
+    );
+}
+
+#[test]
+fn page_add_query_works() {
+    let mut page = Page::default();
+
+    page.add_query("limit", "20").unwrap();
+    page.add_query("offset", " 40 ").unwrap();
+    page.add_query("sort", "email,asc").unwrap();
+
+    assert_eq!(
+        page,
+        Page {
+            limit: Some(20),
+            offset: 40,
+            sort: Some(Sort {
+                field: "email".to_owned(),
+                order: SortOrder::Ascending,
+            }),
+        }
+    );
+    assert_eq!(
+        page.add_query("limit", "-1"),
+        Err(SearchParseError::Term {
+            position: 0,
+            term: "-1".to_owned()
+        })
+    );
+    assert_eq!(
+        page.add_query("offset", "9223372036854775808"),
+        Err(SearchParseError::Term {
+            position: 0,
+            term: "9223372036854775808".to_owned()
+        })
+    );
+    assert!(page.add_query("limit", "9223372036854775807").is_ok());
+}
+
+#[test]
+fn page_next_works() {
+    let page = Page {
+        limit: Some(20),
+        offset: 40,
+        sort: None,
+    };
+
+    assert_eq!(page.next(100), Some(60));
+    assert_eq!(page.next(60), None);
+    assert_eq!(Page::default().next(100), None);
+
+    let huge_page = Page {
+        limit: Some(u64::MAX),
+        offset: 40,
+        sort: None,
+    };
+
+    assert_eq!(huge_page.next(u64::MAX), None);
+}
+
+#[test]
+fn page_or_limit_works() {
+    let page = Page {
+        limit: Some(20),
+        offset: 40,
+        sort: None,
+    };
+
+    assert_eq!(page.clone().or_limit(100), page);
+    assert_eq!(Page::default().or_limit(100).limit, Some(100));
+    assert_eq!(Page::default().or_limit(100).next(150), Some(100));
+}
+
+#[test]
+fn search_filter_text_ignore_case_works() {
+    use crate::users::schema::users;
+    use diesel::debug_query;
+    use diesel::QueryDsl;
+
+    let query = Search::from_query("ipartial,Ann").unwrap().filter_text(
+        users::table.select(users::id).into_boxed(),
+        users::first_name,
+    );
+
+    assert_eq!(
+        debug_query::<Mysql, _>(&query).to_string(),
+        "SELECT `users`.`id` FROM `users` \
+         WHERE LOWER(`users`.`first_name`) LIKE ? -- binds: [\"%ann%\"]"
+    );
+}
+
+#[test]
+fn parse_digits_works() {
+    let s: Result<Search<Digits<u32>>, _> = Search::from_query("exact,0042");
+    match s {
+        Ok(Search::Exact(digits)) => assert_eq!(digits.value, 42),
+        _ => panic!("expected an exact search"),
+    }
+
+    let s: Result<Search<Digits<u32>>, _> = Search::from_query("exact,+42");
+    assert!(s.is_err());
+
+    let s: Result<Search<Digits<u32>>, _> = Search::from_query("exact,");
+    assert!(s.is_err());
+}
+
+#[test]
+fn search_filter_digits_keeps_leading_zeros() {
+    use crate::users::schema::users;
+    use diesel::debug_query;
+    use diesel::QueryDsl;
+
+    let query = Search::<Digits<u32>>::from_query("suffix,0042")
+        .unwrap()
+        .filter(
+            users::table.select(users::id).into_boxed(),
+            users::banner_id,
+        );
+
+    assert_eq!(
+        debug_query::<Mysql, _>(&query).to_string(),
+        "SELECT `users`.`id` FROM `users` \
+         WHERE CAST(`users`.`banner_id` AS CHAR) LIKE ? -- binds: [\"%0042\"]"
+    );
+
+    let query = Search::<Digits<u32>>::from_query("in,0042|7")
+        .unwrap()
+        .filter(
+            users::table.select(users::id).into_boxed(),
+            users::banner_id,
+        );
+
+    assert_eq!(
+        debug_query::<Mysql, _>(&query).to_string(),
+        "SELECT `users`.`id` FROM `users` \
+         WHERE `users`.`banner_id` IN (?, ?) -- binds: [42, 7]"
+    );
+}
+
+#[test]
+fn parse_list_search_rejects_ordered_kinds() {
+    let s: Result<Search<String>, _> = Search::from_list_query("in,H225|H319");
+    assert!(s.is_ok());
+
+    let s: Result<Search<String>, _> = Search::from_list_query("gt,H225");
+    assert_eq!(
+        s,
+        Err(SearchParseError::Kind {
+            position: 0,
+            kind: "gt".to_owned()
+        })
+    );
+}
+
+#[test]
+fn search_filter_list_matches_members() {
+    use crate::users::schema::users;
+    use diesel::debug_query;
+    use diesel::QueryDsl;
+
+    let filter = |query| {
+        Search::<String>::from_list_query(query)
+            .unwrap()
+            .filter_list(
+                users::table.select(users::id).into_boxed(),
+                users::email,
+            )
+    };
+
+    assert_eq!(
+        debug_query::<Mysql, _>(&filter("exact,H225")).to_string(),
+        "SELECT `users`.`id` FROM `users` \
+         WHERE find_in_set(?, `users`.`email`) > ? -- binds: [\"H225\", 0]"
+    );
+    assert_eq!(
+        debug_query::<Mysql, _>(&filter("not,H225")).to_string(),
+        "SELECT `users`.`id` FROM `users` \
+         WHERE find_in_set(?, `users`.`email`) = ? -- binds: [\"H225\", 0]"
+    );
+    assert_eq!(
+        debug_query::<Mysql, _>(&filter("iin,H225|H319")).to_string(),
+        "SELECT `users`.`id` FROM `users` \
+         WHERE (find_in_set(?, LOWER(`users`.`email`)) > ? \
+         OR find_in_set(?, LOWER(`users`.`email`)) > ?) \
+         -- binds: [\"h225\", 0, \"h319\", 0]"
+    );
+    assert_eq!(
+        debug_query::<Mysql, _>(&filter("prefix,H3")).to_string(),
+        "SELECT `users`.`id` FROM `users` \
+         WHERE (`users`.`email` LIKE ? OR `users`.`email` LIKE ?) \
+         -- binds: [\"H3%\", \"%,H3%\"]"
+    );
+}
+
+#[test]
+fn search_filter_in_and_between_work() {
+    use crate::users::schema::users;
+    use diesel::debug_query;
+    use diesel::QueryDsl;
+
+    let query = Search::<u32>::from_query("in,1|2").unwrap().filter(
+        users::table.select(users::id).into_boxed(),
+        users::banner_id,
+    );
+
+    assert_eq!(
+        debug_query::<Mysql, _>(&query).to_string(),
+        "SELECT `users`.`id` FROM `users` \
+         WHERE `users`.`banner_id` IN (?, ?) -- binds: [1, 2]"
+    );
+
+    let query = Search::<u32>::from_query("between,1,2").unwrap().filter(
+        users::table.select(users::id).into_boxed(),
+        users::banner_id,
+    );
+
+    assert_eq!(
+        debug_query::<Mysql, _>(&query).to_string(),
+        "SELECT `users`.`id` FROM `users` \
+         WHERE `users`.`banner_id` BETWEEN ? AND ? -- binds: [1, 2]"
+    );
 }
@@ -0,0 +1,209 @@
+use rouille;
+use rouille::router;
+use serde::Deserialize;
+use serde::Serialize;
+
+use url::form_urlencoded;
+
+use chrono::offset::Local;
+use chrono::DateTime;
+use chrono::NaiveDateTime;
+
+use log::warn;
+
+use crate::errors::Error;
+use crate::errors::ErrorKind;
+
+use super::schema::sessions;
+
+/// Name of the cookie that holds the session token
+pub const SESSION_COOKIE: &str = "session";
+
+/// Attributes of the session cookie, keeping it from scripts, plain HTTP and
+/// other sites
+const SESSION_COOKIE_ATTRIBUTES: &str =
+    "Path=/; HttpOnly; Secure; SameSite=Strict";
+
+#[derive(Queryable, Debug)]
+pub struct RawSession {
+    pub id: u64,
+    pub token_hash: String,
+    pub user_id: u64,
+    pub created: NaiveDateTime,
+    pub expires: NaiveDateTime,
+    pub revoked: bool,
+}
+
+#[derive(Insertable, Debug)]
+#[table_name = "sessions"]
+pub struct NewRawSession {
+    pub token_hash: String,
+    pub user_id: u64,
+    pub created: NaiveDateTime,
+    pub expires: NaiveDateTime,
+    pub revoked: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Session {
+    pub id: u64,
+    pub user_id: u64,
+    pub created: DateTime<Local>,
+    pub expires: DateTime<Local>,
+}
+
+/// A newly created session, the only time the token is handed out
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LoginSession {
+    pub token: String,
+    pub session: Session,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SessionList {
+    pub sessions: Vec<Session>,
+}
+
+pub enum SessionRequest {
+    Login(String),
+    Logout(Option<String>),
+    GetSessions(Option<u64>),
+    RevokeSession(u64),
+}
+
+impl SessionRequest {
+    pub fn from_rouille(
+        request: &rouille::Request,
+    ) -> Result<SessionRequest, Error> {
+        let mut url_queries =
+            form_urlencoded::parse(request.raw_query_string().as_bytes());
+
+        router!(request,
+            (GET) (/) => {
+                let user_id = url_queries.find_map(|q| {
+                    if q.0 == "user_id" {
+                        q.1.parse().ok()
+                    } else {
+                        None
+                    }
+                });
+
+                Ok(SessionRequest::GetSessions(user_id))
+            },
+
+            (POST) (/login) => {
+                if let Some(id_token) = request.header("id_token") {
+                    Ok(SessionRequest::Login(id_token.to_string()))
+                } else {
+                    Err(Error::new(ErrorKind::AccessDenied))
+                }
+            },
+
+            (POST) (/logout) => {
+                Ok(SessionRequest::Logout(session_token(request)))
+            },
+
+            (DELETE) (/{id: u64}) => {
+                Ok(SessionRequest::RevokeSession(id))
+            },
+
+            _ => {
+                warn!("Could not create a session request for the given rouille request");
+                Err(Error::new(ErrorKind::NotFound))
+            }
+        )
+    }
+}
+
+pub enum SessionResponse {
+    LoggedIn(LoginSession),
+    LoggedOut,
+    ManySessions(SessionList),
+    NoResponse,
+}
+
+impl SessionResponse {
+    pub fn to_rouille(self) -> rouille::Response {
+        match self {
+            SessionResponse::LoggedIn(login_session) => {
+                let cookie = format!(
+                    "{}={}; {}; Max-Age={}",
+                    SESSION_COOKIE,
+                    login_session.token,
+                    SESSION_COOKIE_ATTRIBUTES,
+                    (login_session.session.expires - Local::now())
+                        .num_seconds()
+                        .max(0)
+                );
+
+                rouille::Response::json(&login_session)
+                    .with_additional_header("Set-Cookie", cookie)
+            }
+            SessionResponse::LoggedOut => rouille::Response::empty_204()
+                .with_additional_header(
+                    "Set-Cookie",
+                    format!(
+                        "{}=; {}; Max-Age=0",
+                        SESSION_COOKIE, SESSION_COOKIE_ATTRIBUTES
+                    ),
+                ),
+            SessionResponse::ManySessions(sessions) => {
+                rouille::Response::json(&sessions)
+            }
+            SessionResponse::NoResponse => rouille::Response::empty_204(),
+        }
+    }
+}
+
+/// Find the session token of a request
+///
+/// The token is taken from an `Authorization: Bearer` header if there is one,
+/// and from the session cookie otherwise.
+pub fn session_token(request: &rouille::Request) -> Option<String> {
+    let bearer = request.header("Authorization").and_then(|authorization| {
+        let mut parts = authorization.splitn(2, ' ');
+        match (parts.next(), parts.next()) {
+            (Some("Bearer"), Some(token)) => Some(token.trim().to_owned()),
+            _ => None,
+        }
+    });
+
+    bearer.or_else(|| {
+        rouille::input::cookies(request)
+            .find(|&(name, _)| name == SESSION_COOKIE)
+            .map(|(_, token)| token.to_owned())
+    })
+}
+
+#[test]
+fn session_token_from_bearer_header_works() {
+    let request = rouille::Request::fake_http(
+        "GET",
+        "/",
+        vec![("Authorization".to_owned(), "Bearer abc123".to_owned())],
+        Vec::new(),
+    );
+    assert_eq!(session_token(&request), Some("abc123".to_owned()));
+}
+
+#[test]
+fn session_token_from_cookie_works() {
+    let request = rouille::Request::fake_http(
+        "GET",
+        "/",
+        vec![("Cookie".to_owned(), "other=1; session=abc123".to_owned())],
+        Vec::new(),
+    );
+    assert_eq!(session_token(&request), Some("abc123".to_owned()));
+}
+
+#[test]
+fn session_token_missing_is_none() {
+    let request = rouille::Request::fake_http(
+        "GET",
+        "/",
+        vec![("Authorization".to_owned(), "Basic abc123".to_owned())],
+        Vec::new(),
+    );
+    assert_eq!(session_token(&request), None);
+}
@@ -0,0 +1,274 @@
+use diesel;
+use diesel::mysql::MysqlConnection;
+use diesel::ExpressionMethods;
+use diesel::QueryDsl;
+use diesel::RunQueryDsl;
+
+use chrono::offset::Local;
+use chrono::Duration;
+
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+
+use ring::digest;
+
+use log::trace;
+
+use crate::dates::to_local;
+
+use crate::errors::Error;
+use crate::errors::ErrorKind;
+
+use crate::access::identity::IdentityVerifier;
+use crate::access::requests::check_to_run;
+use crate::access::requests::validate_token;
+
+use super::models::{
+    LoginSession, NewRawSession, RawSession, Session, SessionList,
+    SessionRequest, SessionResponse,
+};
+
+use super::schema::sessions as sessions_schema;
+
+/// How long a session lasts after logging in
+const SESSION_HOURS: i64 = 12;
+
+/// Number of characters in a session token
+const TOKEN_LENGTH: usize = 64;
+
+pub fn handle_session(
+    request: SessionRequest,
+    requested_user: Option<u64>,
+    identity_verifier: &dyn IdentityVerifier,
+    database_connection: &MysqlConnection,
+) -> Result<SessionResponse, Error> {
+    match request {
+        SessionRequest::Login(id_token) => {
+            login(&id_token, identity_verifier, database_connection)
+                .map(|s| SessionResponse::LoggedIn(s))
+        }
+        SessionRequest::Logout(token) => match token {
+            Some(token) => logout(&token, database_connection)
+                .map(|_| SessionResponse::LoggedOut),
+            None => Err(Error::new(ErrorKind::AccessDenied)),
+        },
+        SessionRequest::GetSessions(user_id) => match (requested_user, user_id)
+        {
+            (Some(requested_user), Some(user_id))
+                if requested_user != user_id =>
+            {
+                check_to_run(
+                    Some(requested_user),
+                    "GetSessions",
+                    None,
+                    database_connection,
+                )?;
+                get_sessions(user_id, database_connection)
+                    .map(|s| SessionResponse::ManySessions(s))
+            }
+            (Some(requested_user), _) => {
+                get_sessions(requested_user, database_connection)
+                    .map(|s| SessionResponse::ManySessions(s))
+            }
+            (None, _) => Err(Error::new(ErrorKind::AccessDenied)),
+        },
+        SessionRequest::RevokeSession(id) => {
+            let requested_user = requested_user
+                .ok_or_else(|| Error::new(ErrorKind::AccessDenied))?;
+
+            // Check the permission before looking up the session, so that
+            // users without it can not tell which sessions exist
+            let may_revoke_any = match check_to_run(
+                Some(requested_user),
+                "RevokeSessions",
+                None,
+                database_connection,
+            ) {
+                Ok(()) => true,
+                Err(e) => match e.kind() {
+                    ErrorKind::AccessDenied
+                    | ErrorKind::MissingCertification => false,
+                    _ => return Err(e),
+                },
+            };
+
+            match get_session(id, database_connection) {
+                Ok(session)
+                    if may_revoke_any || session.user_id == requested_user =>
+                {
+                    revoke_session(id, database_connection)
+                        .map(|_| SessionResponse::NoResponse)
+                }
+                Err(e) if may_revoke_any => Err(e),
+                _ => Err(Error::new(ErrorKind::AccessDenied)),
+            }
+        }
+    }
+}
+
+/// Find the user that a session token belongs to
+///
+/// Fails if the session does not exist, has expired, or has been revoked.
+pub fn validate_session(
+    token: &str,
+    database_connection: &MysqlConnection,
+) -> Result<u64, Error> {
+    let mut found_sessions = sessions_schema::table
+        .filter(sessions_schema::token_hash.eq(hash_token(token)))
+        .filter(sessions_schema::revoked.eq(false))
+        .filter(sessions_schema::expires.gt(Local::now().naive_local()))
+        .load::<RawSession>(database_connection)?;
+
+    match found_sessions.pop() {
+        Some(session) => Ok(session.user_id),
+        None => Err(Error::new(ErrorKind::AccessDenied)),
+    }
+}
+
+pub(crate) fn login(
+    id_token: &str,
+    identity_verifier: &dyn IdentityVerifier,
+    database_connection: &MysqlConnection,
+) -> Result<LoginSession, Error> {
+    let user_id =
+        validate_token(id_token, identity_verifier, database_connection)?;
+
+    let token: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(TOKEN_LENGTH)
+        .collect();
+
+    let now = Local::now().naive_local();
+
+    let token_hash = hash_token(&token);
+
+    let new_raw_session = NewRawSession {
+        token_hash: token_hash.clone(),
+        user_id,
+        created: now,
+        expires: now + Duration::hours(SESSION_HOURS),
+        revoked: false,
+    };
+
+    diesel::insert_into(sessions_schema::table)
+        .values(new_raw_session)
+        .execute(database_connection)?;
+
+    let mut inserted_sessions = sessions_schema::table
+        .filter(sessions_schema::token_hash.eq(&token_hash))
+        .load::<RawSession>(database_connection)?;
+
+    if let Some(inserted_session) = inserted_sessions.pop() {
+        trace!(
+            "Logged in user {} with session {}",
+            user_id,
+            inserted_session.id
+        );
+
+        Ok(LoginSession {
+            token,
+            session: to_session(inserted_session)?,
+        })
+    } else {
+        Err(Error::new(ErrorKind::Database))
+    }
+}
+
+pub(crate) fn logout(
+    token: &str,
+    database_connection: &MysqlConnection,
+) -> Result<(), Error> {
+    diesel::update(
+        sessions_schema::table
+            .filter(sessions_schema::token_hash.eq(hash_token(token))),
+    )
+    .set(sessions_schema::revoked.eq(true))
+    .execute(database_connection)?;
+
+    Ok(())
+}
+
+pub(crate) fn get_session(
+    id: u64,
+    database_connection: &MysqlConnection,
+) -> Result<Session, Error> {
+    let mut found_sessions = sessions_schema::table
+        .filter(sessions_schema::id.eq(id))
+        .load::<RawSession>(database_connection)?;
+
+    match found_sessions.pop() {
+        Some(session) => to_session(session),
+        None => Err(Error::new(ErrorKind::NotFound)),
+    }
+}
+
+/// Get the sessions of a user that are still usable
+pub(crate) fn get_sessions(
+    user_id: u64,
+    database_connection: &MysqlConnection,
+) -> Result<SessionList, Error> {
+    let sessions = sessions_schema::table
+        .filter(sessions_schema::user_id.eq(user_id))
+        .filter(sessions_schema::revoked.eq(false))
+        .filter(sessions_schema::expires.gt(Local::now().naive_local()))
+        .load::<RawSession>(database_connection)?
+        .into_iter()
+        .map(to_session)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(SessionList { sessions })
+}
+
+pub(crate) fn revoke_session(
+    id: u64,
+    database_connection: &MysqlConnection,
+) -> Result<(), Error> {
+    diesel::update(sessions_schema::table.filter(sessions_schema::id.eq(id)))
+        .set(sessions_schema::revoked.eq(true))
+        .execute(database_connection)?;
+
+    Ok(())
+}
+
+/// Revoke every session a user has, logging them out everywhere
+pub(crate) fn revoke_user_sessions(
+    user_id: u64,
+    database_connection: &MysqlConnection,
+) -> Result<(), Error> {
+    diesel::update(
+        sessions_schema::table.filter(sessions_schema::user_id.eq(user_id)),
+    )
+    .set(sessions_schema::revoked.eq(true))
+    .execute(database_connection)?;
+
+    Ok(())
+}
+
+/// Hash a session token, so that the tokens themselves are never stored
+///
+/// Tokens are long and random, so a fast hash without a salt is enough.
+fn hash_token(token: &str) -> String {
+    digest::digest(&digest::SHA256, token.as_bytes())
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+fn to_session(raw_session: RawSession) -> Result<Session, Error> {
+    Ok(Session {
+        id: raw_session.id,
+        user_id: raw_session.user_id,
+        created: to_local(raw_session.created)?,
+        expires: to_local(raw_session.expires)?,
+    })
+}
+
+#[test]
+fn hash_token_works() {
+    assert_eq!(
+        hash_token("abc"),
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+    assert_ne!(hash_token("abc"), hash_token("abd"));
+}
@@ -0,0 +1,15 @@
+use crate::users::schema::users;
+
+table! {
+    sessions (id) {
+        id -> Unsigned<Bigint>,
+        token_hash -> Char,
+        user_id -> Unsigned<Bigint>,
+        created -> Timestamp,
+        expires -> Timestamp,
+        revoked -> Bool,
+    }
+}
+
+joinable!(sessions -> users (user_id));
+allow_tables_to_appear_in_same_query!(sessions, users);
@@ -0,0 +1,32 @@
+use diesel::mysql::MysqlConnection;
+use diesel::Connection;
+
+use crate::users::models::NewUser;
+
+/// Connect to the database at `DATABASE_URL`
+///
+/// Tests that use this are marked `#[ignore]`, since they need a migrated
+/// database to run against. Run them with `cargo test -- --ignored`, and wrap
+/// them in `test_transaction` so nothing is left behind.
+pub(crate) fn database_connection() -> MysqlConnection {
+    dotenv::dotenv().ok();
+
+    let database_url = std::env::var("DATABASE_URL")
+        .expect("DATABASE_URL must be set to run database tests");
+
+    MysqlConnection::establish(&database_url)
+        .expect("Could not connect to the database")
+}
+
+/// A user for database tests to create, with no accesses or roles
+pub(crate) fn new_user() -> NewUser {
+    NewUser {
+        first_name: "Transaction".to_owned(),
+        last_name: "Test".to_owned(),
+        banner_id: 0,
+        email: "transaction_test@rowan.edu".to_owned(),
+        department: None,
+        accesses: Vec::new(),
+        roles: Vec::new(),
+    }
+}
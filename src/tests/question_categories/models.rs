@@ -11,6 +11,7 @@ use crate::errors::ErrorKind;
 
 use crate::tests::questions::models::NewQuestion;
 use crate::tests::questions::models::Question;
+use crate::tests::questions::models::QuestionStatisticsList;
 
 use super::schema::question_categories;
 
@@ -53,6 +54,7 @@ pub struct QuestionCategoryList {
 pub enum QuestionCategoryRequest {
     GetQuestionCategories,
     GetQuestionCategory(u64),
+    GetQuestionCategoryStatistics(u64),
     CreateQuestionCategory(NewQuestionCategory),
     DeleteQuestionCategory(u64),
 }
@@ -70,6 +72,10 @@ impl QuestionCategoryRequest {
                 Ok(QuestionCategoryRequest::GetQuestionCategory(id))
             },
 
+            (GET) (/{id: u64}/statistics) => {
+                Ok(QuestionCategoryRequest::GetQuestionCategoryStatistics(id))
+            },
+
             (POST) (/) => {
                 let request_body = request.data().ok_or(Error::new(ErrorKind::Body))?;
                 let new_question_category: NewQuestionCategory = serde_json::from_reader(request_body)?;
@@ -92,6 +98,7 @@ impl QuestionCategoryRequest {
 pub enum QuestionCategoryResponse {
     OneQuestionCategory(QuestionCategory),
     ManyQuestionCategories(QuestionCategoryList),
+    ManyQuestionStatistics(QuestionStatisticsList),
     NoResponse,
 }
 
@@ -104,6 +111,9 @@ impl QuestionCategoryResponse {
             QuestionCategoryResponse::ManyQuestionCategories(
                 question_categories,
             ) => rouille::Response::json(&question_categories),
+            QuestionCategoryResponse::ManyQuestionStatistics(statistics) => {
+                rouille::Response::json(&statistics)
+            }
             QuestionCategoryResponse::NoResponse => {
                 rouille::Response::empty_204()
             }
@@ -1,5 +1,7 @@
 use diesel;
+use diesel::dsl::exists;
 use diesel::mysql::MysqlConnection;
+use diesel::Connection;
 use diesel::ExpressionMethods;
 use diesel::NullableExpressionMethods;
 use diesel::QueryDsl;
@@ -10,6 +12,8 @@ use crate::errors::ErrorKind;
 
 use crate::access::requests::check_to_run;
 
+use crate::audit::requests::record_change;
+
 use crate::tests::question_categories::models::{
     JoinedQuestionCategory, NewQuestionCategory, NewRawQuestionCategory,
     QuestionCategory, QuestionCategoryList, QuestionCategoryRequest,
@@ -18,9 +22,11 @@ use crate::tests::question_categories::models::{
 
 use crate::tests::questions::models::NewRawQuestion;
 use crate::tests::questions::models::Question;
+use crate::tests::questions::requests::get_questions_statistics;
 
 use crate::tests::question_categories::schema::question_categories as question_categories_schema;
 use crate::tests::questions::schema::questions as questions_schema;
+use crate::tests::test_sessions::schema::test_session_served_questions as test_session_served_questions_schema;
 
 pub fn handle_question_category(
     request: QuestionCategoryRequest,
@@ -32,6 +38,7 @@ pub fn handle_question_category(
             check_to_run(
                 requested_user,
                 "GetQuestionCategories",
+                None,
                 database_connection,
             )?;
             get_question_categories(database_connection)
@@ -41,27 +48,71 @@ pub fn handle_question_category(
             check_to_run(
                 requested_user,
                 "GetQuestionCategories",
+                None,
                 database_connection,
             )?;
             get_question_category(id, database_connection)
                 .map(|u| QuestionCategoryResponse::OneQuestionCategory(u))
         }
+        QuestionCategoryRequest::GetQuestionCategoryStatistics(id) => {
+            check_to_run(
+                requested_user,
+                "GetQuestionCategories",
+                None,
+                database_connection,
+            )?;
+            get_question_category(id, database_connection)?;
+            get_questions_statistics(Some(id), database_connection)
+                .map(|u| QuestionCategoryResponse::ManyQuestionStatistics(u))
+        }
         QuestionCategoryRequest::CreateQuestionCategory(question_category) => {
             check_to_run(
                 requested_user,
                 "CreateQuestionCategories",
+                None,
                 database_connection,
             )?;
-            create_question_category(question_category, database_connection)
+            database_connection
+                .transaction::<_, Error, _>(|| {
+                    let created = create_question_category(
+                        question_category,
+                        database_connection,
+                    )?;
+                    record_change(
+                        requested_user,
+                        "CreateQuestionCategories",
+                        "question_categories",
+                        created.id,
+                        None,
+                        Some(&created),
+                        database_connection,
+                    )?;
+                    Ok(created)
+                })
                 .map(|u| QuestionCategoryResponse::OneQuestionCategory(u))
         }
         QuestionCategoryRequest::DeleteQuestionCategory(id) => {
             check_to_run(
                 requested_user,
                 "DeleteQuestionCategories",
+                None,
                 database_connection,
             )?;
-            delete_question_category(id, database_connection)
+            database_connection
+                .transaction::<_, Error, _>(|| {
+                    let before =
+                        get_question_category(id, database_connection)?;
+                    delete_question_category(id, database_connection)?;
+                    record_change(
+                        requested_user,
+                        "DeleteQuestionCategories",
+                        "question_categories",
+                        id,
+                        Some(&before),
+                        None,
+                        database_connection,
+                    )
+                })
                 .map(|_| QuestionCategoryResponse::NoResponse)
         }
     }
@@ -165,73 +216,92 @@ pub(crate) fn create_question_category(
     question_category: NewQuestionCategory,
     database_connection: &MysqlConnection,
 ) -> Result<QuestionCategory, Error> {
-    let new_raw_question_category = NewRawQuestionCategory {
-        title: question_category.title,
-    };
-
-    diesel::insert_into(question_categories_schema::table)
-        .values(new_raw_question_category)
-        .execute(database_connection)?;
+    database_connection.transaction(|| {
+        let new_raw_question_category = NewRawQuestionCategory {
+            title: question_category.title,
+        };
 
-    let mut raw_inserted_question_categories =
-        question_categories_schema::table
-            .filter(diesel::dsl::sql("id = LAST_INSERT_ID()"))
-            .load::<RawQuestionCategory>(database_connection)?;
-
-    if let Some(raw_inserted_question_category) =
-        raw_inserted_question_categories.pop()
-    {
-        let new_raw_questions: Vec<_> = question_category
-            .questions
-            .into_iter()
-            .map(|question| NewRawQuestion {
-                title: question.title,
-                category_id: raw_inserted_question_category.id,
-                correct_answer: question.correct_answer,
-                incorrect_answer_1: question.incorrect_answer_1,
-                incorrect_answer_2: question.incorrect_answer_2,
-                incorrect_answer_3: question.incorrect_answer_3,
-            })
-            .collect();
-
-        diesel::insert_into(questions_schema::table)
-            .values(new_raw_questions)
+        diesel::insert_into(question_categories_schema::table)
+            .values(new_raw_question_category)
             .execute(database_connection)?;
 
-        let inserted_questions = questions_schema::table
-            .filter(
-                questions_schema::category_id
-                    .eq(raw_inserted_question_category.id),
-            )
-            .load::<Question>(database_connection)?
-            .into_iter()
-            .map(|raw_question| Question {
-                id: raw_question.id,
-                category_id: raw_question.category_id,
-                title: raw_question.title,
-                correct_answer: raw_question.correct_answer,
-                incorrect_answer_1: raw_question.incorrect_answer_1,
-                incorrect_answer_2: raw_question.incorrect_answer_2,
-                incorrect_answer_3: raw_question.incorrect_answer_3,
-            })
-            .collect();
-
-        let inserted_question_category = QuestionCategory {
-            id: raw_inserted_question_category.id,
-            title: raw_inserted_question_category.title,
-            questions: inserted_questions,
-        };
+        let mut raw_inserted_question_categories =
+            question_categories_schema::table
+                .filter(diesel::dsl::sql("id = LAST_INSERT_ID()"))
+                .load::<RawQuestionCategory>(database_connection)?;
 
-        Ok(inserted_question_category)
-    } else {
-        Err(Error::new(ErrorKind::Database))
-    }
+        if let Some(raw_inserted_question_category) =
+            raw_inserted_question_categories.pop()
+        {
+            let new_raw_questions: Vec<_> = question_category
+                .questions
+                .into_iter()
+                .map(|question| NewRawQuestion {
+                    title: question.title,
+                    category_id: raw_inserted_question_category.id,
+                    correct_answer: question.correct_answer,
+                    incorrect_answer_1: question.incorrect_answer_1,
+                    incorrect_answer_2: question.incorrect_answer_2,
+                    incorrect_answer_3: question.incorrect_answer_3,
+                })
+                .collect();
+
+            diesel::insert_into(questions_schema::table)
+                .values(new_raw_questions)
+                .execute(database_connection)?;
+
+            let inserted_questions = questions_schema::table
+                .filter(
+                    questions_schema::category_id
+                        .eq(raw_inserted_question_category.id),
+                )
+                .load::<Question>(database_connection)?
+                .into_iter()
+                .map(|raw_question| Question {
+                    id: raw_question.id,
+                    category_id: raw_question.category_id,
+                    title: raw_question.title,
+                    correct_answer: raw_question.correct_answer,
+                    incorrect_answer_1: raw_question.incorrect_answer_1,
+                    incorrect_answer_2: raw_question.incorrect_answer_2,
+                    incorrect_answer_3: raw_question.incorrect_answer_3,
+                })
+                .collect();
+
+            let inserted_question_category = QuestionCategory {
+                id: raw_inserted_question_category.id,
+                title: raw_inserted_question_category.title,
+                questions: inserted_questions,
+            };
+
+            Ok(inserted_question_category)
+        } else {
+            Err(Error::new(ErrorKind::Database))
+        }
+    })
 }
 
+/// Delete a question category and its questions, unless any of them have
+/// been served
 pub(crate) fn delete_question_category(
     id: u64,
     database_connection: &MysqlConnection,
 ) -> Result<(), Error> {
+    let served = diesel::select(exists(
+        test_session_served_questions_schema::table.filter(
+            test_session_served_questions_schema::question_id.eq_any(
+                questions_schema::table
+                    .select(questions_schema::id)
+                    .filter(questions_schema::category_id.eq(id)),
+            ),
+        ),
+    ))
+    .get_result::<bool>(database_connection)?;
+
+    if served {
+        return Err(Error::new(ErrorKind::DeletedServedQuestion));
+    }
+
     diesel::delete(
         question_categories_schema::table
             .filter(question_categories_schema::id.eq(id)),
@@ -240,3 +310,46 @@ pub(crate) fn delete_question_category(
 
     Ok(())
 }
+
+#[test]
+#[ignore]
+fn create_question_category_with_bad_question_creates_nothing() {
+    use crate::tests::questions::models::NewQuestion;
+
+    let database_connection = crate::testing::database_connection();
+
+    // Questions can only belong to the new category, so there is no bad
+    // foreign key to give them. Make too long titles an error instead of
+    // being cut short, whatever the server's default mode is.
+    diesel::sql_query("SET SESSION sql_mode = 'STRICT_ALL_TABLES'")
+        .execute(&database_connection)
+        .unwrap();
+
+    database_connection.test_transaction::<_, Error, _>(|| {
+        let new_question_category = NewQuestionCategory {
+            title: "Transaction Test".to_owned(),
+            questions: vec![NewQuestion {
+                // Longer than the VARCHAR(255) column, so the insert fails
+                title: "?".repeat(1000),
+                correct_answer: "Yes".to_owned(),
+                incorrect_answer_1: "No".to_owned(),
+                incorrect_answer_2: "Maybe".to_owned(),
+                incorrect_answer_3: "Sometimes".to_owned(),
+            }],
+        };
+
+        assert!(create_question_category(
+            new_question_category,
+            &database_connection
+        )
+        .is_err());
+
+        let found_question_categories = question_categories_schema::table
+            .filter(question_categories_schema::title.eq("Transaction Test"))
+            .load::<RawQuestionCategory>(&database_connection)?;
+
+        assert!(found_question_categories.is_empty());
+
+        Ok(())
+    });
+}
@@ -4,11 +4,15 @@ use serde::Deserialize;
 use serde::Serialize;
 use serde_json;
 
+use url::form_urlencoded;
+
 use log::warn;
 
 use crate::errors::Error;
 use crate::errors::ErrorKind;
 
+use crate::search::Page;
+
 use super::schema::questions;
 
 #[derive(Queryable, Serialize, Deserialize, Clone, Debug)]
@@ -42,9 +46,14 @@ pub struct NewQuestion {
     pub incorrect_answer_3: String,
 }
 
+/// One page of questions, out of `total`
+///
+/// `next` is the offset of the page after this one, if there is one.
 #[derive(Serialize, Deserialize, Debug)]
 pub struct QuestionList {
     pub questions: Vec<Question>,
+    pub total: u64,
+    pub next: Option<u64>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -73,8 +82,44 @@ pub struct ResponseQuestionList {
     pub questions: Vec<ResponseQuestion>,
 }
 
+/// How a question has done across every submitted test it was served in
+///
+/// `too_easy` and `confusing` are only raised once a question has been
+/// answered enough times for the numbers to mean something.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct QuestionStatistics {
+    pub question_id: u64,
+    pub category_id: u64,
+    pub title: String,
+    pub times_served: u64,
+    pub times_correct: u64,
+    pub times_unanswered: u64,
+    pub fraction_correct: Option<f32>,
+    pub incorrect_answer_1_chosen: u64,
+    pub incorrect_answer_2_chosen: u64,
+    pub incorrect_answer_3_chosen: u64,
+    pub too_easy: bool,
+    pub confusing: bool,
+}
+
+/// How many times one answer was given to a question
+#[derive(Queryable, Debug)]
+pub struct ResponseCount {
+    pub question_id: u64,
+    pub answer: Option<String>,
+    pub correct: bool,
+    pub count: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct QuestionStatisticsList {
+    pub questions: Vec<QuestionStatistics>,
+}
+
 pub enum QuestionRequest {
-    GetQuestions,
+    GetQuestions(Page),
+    GetQuestionsStatistics,
+    GetQuestionStatistics(u64),
     CreateQuestion(NewRawQuestion),
     DeleteQuestion(u64),
 }
@@ -83,9 +128,30 @@ impl QuestionRequest {
     pub fn from_rouille(
         request: &rouille::Request,
     ) -> Result<QuestionRequest, Error> {
+        let url_queries =
+            form_urlencoded::parse(request.raw_query_string().as_bytes());
+
         router!(request,
             (GET) (/) => {
-                Ok(QuestionRequest::GetQuestions)
+                let mut page = Page::default();
+
+                for (field, query) in url_queries {
+                    match field.as_ref() {
+                        "limit" | "offset" | "sort" =>
+                            page.add_query(field.as_ref(), query.as_ref())?,
+                        _ => return Err(Error::new(ErrorKind::Url)),
+                    }
+                }
+
+                Ok(QuestionRequest::GetQuestions(page))
+            },
+
+            (GET) (/statistics) => {
+                Ok(QuestionRequest::GetQuestionsStatistics)
+            },
+
+            (GET) (/{id: u64}/statistics) => {
+                Ok(QuestionRequest::GetQuestionStatistics(id))
             },
 
             (POST) (/) => {
@@ -111,6 +177,8 @@ impl QuestionRequest {
 pub enum QuestionResponse {
     OneQuestion(Question),
     ManyQuestions(QuestionList),
+    OneQuestionStatistics(QuestionStatistics),
+    ManyQuestionStatistics(QuestionStatisticsList),
     NoResponse,
 }
 
@@ -123,6 +191,12 @@ impl QuestionResponse {
             QuestionResponse::ManyQuestions(questions) => {
                 rouille::Response::json(&questions)
             }
+            QuestionResponse::OneQuestionStatistics(statistics) => {
+                rouille::Response::json(&statistics)
+            }
+            QuestionResponse::ManyQuestionStatistics(statistics) => {
+                rouille::Response::json(&statistics)
+            }
             QuestionResponse::NoResponse => rouille::Response::empty_204(),
         }
     }
@@ -1,5 +1,10 @@
 use diesel;
+use diesel::dsl::exists;
+use diesel::mysql::Mysql;
 use diesel::mysql::MysqlConnection;
+use diesel::query_dsl::GroupByDsl;
+use diesel::sql_types::BigInt;
+use diesel::Connection;
 use diesel::ExpressionMethods;
 use diesel::QueryDsl;
 use diesel::RunQueryDsl;
@@ -7,53 +12,147 @@ use diesel::RunQueryDsl;
 use crate::errors::Error;
 use crate::errors::ErrorKind;
 
+use crate::search::Page;
+
 use crate::access::requests::check_to_run;
 
+use crate::audit::requests::record_change;
+
 use crate::tests::questions::models::{
     NewRawQuestion, Question, QuestionList, QuestionRequest, QuestionResponse,
+    QuestionStatistics, QuestionStatisticsList, ResponseCount,
 };
 use crate::tests::questions::schema::questions as questions_schema;
 
+use crate::tests::test_sessions::schema::test_responses as test_responses_schema;
+use crate::tests::test_sessions::schema::test_session_served_questions as test_session_served_questions_schema;
+
+/// Questions need this many answers before they can be flagged
+const MIN_RESPONSES_TO_FLAG: u64 = 10;
+
+/// Questions answered correctly at least this often are too easy
+const TOO_EASY_FRACTION_CORRECT: f32 = 0.95;
+
+/// Questions answered correctly less often than this are confusing
+const CONFUSING_FRACTION_CORRECT: f32 = 0.25;
+
 pub fn handle_question(
     request: QuestionRequest,
     requested_user: Option<u64>,
     database_connection: &MysqlConnection,
 ) -> Result<QuestionResponse, Error> {
     match request {
-        QuestionRequest::GetQuestions => {
-            check_to_run(requested_user, "GetQuestions", database_connection)?;
-            get_questions(database_connection)
+        QuestionRequest::GetQuestions(page) => {
+            check_to_run(
+                requested_user,
+                "GetQuestions",
+                None,
+                database_connection,
+            )?;
+            get_questions(page, database_connection)
                 .map(|u| QuestionResponse::ManyQuestions(u))
         }
+        QuestionRequest::GetQuestionsStatistics => {
+            check_to_run(
+                requested_user,
+                "GetQuestions",
+                None,
+                database_connection,
+            )?;
+            get_questions_statistics(None, database_connection)
+                .map(|u| QuestionResponse::ManyQuestionStatistics(u))
+        }
+        QuestionRequest::GetQuestionStatistics(id) => {
+            check_to_run(
+                requested_user,
+                "GetQuestions",
+                None,
+                database_connection,
+            )?;
+            get_question_statistics(id, database_connection)
+                .map(|u| QuestionResponse::OneQuestionStatistics(u))
+        }
         QuestionRequest::CreateQuestion(question) => {
             check_to_run(
                 requested_user,
                 "CreateQuestions",
+                None,
                 database_connection,
             )?;
-            create_question(question, database_connection)
+            database_connection
+                .transaction::<_, Error, _>(|| {
+                    let created =
+                        create_question(question, database_connection)?;
+                    record_change(
+                        requested_user,
+                        "CreateQuestions",
+                        "questions",
+                        created.id,
+                        None,
+                        Some(&created),
+                        database_connection,
+                    )?;
+                    Ok(created)
+                })
                 .map(|u| QuestionResponse::OneQuestion(u))
         }
         QuestionRequest::DeleteQuestion(id) => {
             check_to_run(
                 requested_user,
                 "DeleteQuestions",
+                None,
                 database_connection,
             )?;
-            delete_question(id, database_connection)
+            database_connection
+                .transaction::<_, Error, _>(|| {
+                    let before = get_question(id, database_connection)?;
+                    delete_question(id, database_connection)?;
+                    record_change(
+                        requested_user,
+                        "DeleteQuestions",
+                        "questions",
+                        id,
+                        Some(&before),
+                        None,
+                        database_connection,
+                    )
+                })
                 .map(|_| QuestionResponse::NoResponse)
         }
     }
 }
 
 pub(crate) fn get_questions(
+    page: Page,
     database_connection: &MysqlConnection,
 ) -> Result<QuestionList, Error> {
-    let found_questions =
-        questions_schema::table.load::<Question>(database_connection)?;
+    let total = questions_schema::table
+        .count()
+        .get_result::<i64>(database_connection)? as u64;
+
+    let mut questions_query = questions_schema::table.into_boxed::<Mysql>();
+
+    if let Some(sort) = &page.sort {
+        questions_query = match sort.field.as_ref() {
+            "id" => sort.order.order_by(questions_query, questions_schema::id),
+            "category_id" => sort
+                .order
+                .order_by(questions_query, questions_schema::category_id),
+            "title" => sort
+                .order
+                .order_by(questions_query, questions_schema::title),
+            _ => return Err(Error::new(ErrorKind::Url)),
+        };
+    }
+
+    let found_questions = page
+        .paginate(questions_query.then_order_by(questions_schema::id.asc()))
+        .load::<Question>(database_connection)?;
 
     Ok(QuestionList {
         questions: found_questions,
+        total,
+        next: page.next(total),
     })
 }
 
@@ -76,12 +175,265 @@ pub(crate) fn create_question(
     }
 }
 
+pub(crate) fn get_question(
+    id: u64,
+    database_connection: &MysqlConnection,
+) -> Result<Question, Error> {
+    let mut found_questions = questions_schema::table
+        .filter(questions_schema::id.eq(id))
+        .load::<Question>(database_connection)?;
+
+    match found_questions.pop() {
+        Some(question) => Ok(question),
+        None => Err(Error::new(ErrorKind::NotFound)),
+    }
+}
+
+/// Delete a question, unless it has been served
+///
+/// Served questions are kept so that attempts in progress can still be
+/// graded, and the answer sheets they are on can still be checked.
 pub(crate) fn delete_question(
     id: u64,
     database_connection: &MysqlConnection,
 ) -> Result<(), Error> {
+    let served = diesel::select(exists(
+        test_session_served_questions_schema::table
+            .filter(test_session_served_questions_schema::question_id.eq(id)),
+    ))
+    .get_result::<bool>(database_connection)?;
+
+    if served {
+        return Err(Error::new(ErrorKind::DeletedServedQuestion));
+    }
+
     diesel::delete(questions_schema::table.filter(questions_schema::id.eq(id)))
         .execute(database_connection)?;
 
     Ok(())
 }
+
+/// Get the statistics of every question, or only those in a category
+pub(crate) fn get_questions_statistics(
+    category_id: Option<u64>,
+    database_connection: &MysqlConnection,
+) -> Result<QuestionStatisticsList, Error> {
+    let mut query = questions_schema::table.into_boxed();
+
+    if let Some(category_id) = category_id {
+        query = query.filter(questions_schema::category_id.eq(category_id));
+    }
+
+    let found_questions = query.load::<Question>(database_connection)?;
+
+    let question_ids: Vec<u64> =
+        found_questions.iter().map(|question| question.id).collect();
+
+    let response_counts = count_responses(question_ids, database_connection)?;
+
+    let questions = found_questions
+        .iter()
+        .map(|question| question_statistics(question, &response_counts))
+        .collect();
+
+    Ok(QuestionStatisticsList { questions })
+}
+
+pub(crate) fn get_question_statistics(
+    id: u64,
+    database_connection: &MysqlConnection,
+) -> Result<QuestionStatistics, Error> {
+    let mut found_questions = questions_schema::table
+        .filter(questions_schema::id.eq(id))
+        .load::<Question>(database_connection)?;
+
+    let question = match found_questions.pop() {
+        Some(question) => question,
+        None => return Err(Error::new(ErrorKind::NotFound)),
+    };
+
+    let response_counts = count_responses(vec![id], database_connection)?;
+
+    Ok(question_statistics(&question, &response_counts))
+}
+
+/// Count the responses to the questions, grouped by question and answer
+fn count_responses(
+    question_ids: Vec<u64>,
+    database_connection: &MysqlConnection,
+) -> Result<Vec<ResponseCount>, Error> {
+    Ok(test_responses_schema::table
+        .filter(test_responses_schema::question_id.eq_any(question_ids))
+        .group_by((
+            test_responses_schema::question_id,
+            test_responses_schema::answer,
+            test_responses_schema::correct,
+        ))
+        .select((
+            test_responses_schema::question_id,
+            test_responses_schema::answer,
+            test_responses_schema::correct,
+            diesel::dsl::sql::<BigInt>("COUNT(*)"),
+        ))
+        .load::<ResponseCount>(database_connection)?)
+}
+
+/// Tally the responses to a question
+///
+/// Counts for other questions are skipped, so the counts for many questions
+/// can be passed in at once.
+pub(crate) fn question_statistics(
+    question: &Question,
+    response_counts: &[ResponseCount],
+) -> QuestionStatistics {
+    let response_counts: Vec<&ResponseCount> = response_counts
+        .iter()
+        .filter(|counted| counted.question_id == question.id)
+        .collect();
+
+    let total = |matches: &dyn Fn(&ResponseCount) -> bool| {
+        response_counts
+            .iter()
+            .filter(|counted| matches(counted))
+            .map(|counted| counted.count as u64)
+            .sum::<u64>()
+    };
+
+    let chosen = |answer: &str| {
+        total(&|counted| counted.answer.as_deref() == Some(answer))
+    };
+
+    let times_served = total(&|_| true);
+    let times_correct = total(&|counted| counted.correct);
+    let times_unanswered = total(&|counted| counted.answer.is_none());
+
+    let incorrect_answer_1_chosen = chosen(&question.incorrect_answer_1);
+    let incorrect_answer_2_chosen = chosen(&question.incorrect_answer_2);
+    let incorrect_answer_3_chosen = chosen(&question.incorrect_answer_3);
+
+    let fraction_correct = if times_served > 0 {
+        Some(times_correct as f32 / times_served as f32)
+    } else {
+        None
+    };
+
+    let (too_easy, confusing) = match fraction_correct {
+        Some(fraction_correct) if times_served >= MIN_RESPONSES_TO_FLAG => {
+            let most_chosen_distractor = incorrect_answer_1_chosen
+                .max(incorrect_answer_2_chosen)
+                .max(incorrect_answer_3_chosen);
+
+            (
+                fraction_correct >= TOO_EASY_FRACTION_CORRECT,
+                fraction_correct < CONFUSING_FRACTION_CORRECT
+                    || most_chosen_distractor > times_correct,
+            )
+        }
+        _ => (false, false),
+    };
+
+    QuestionStatistics {
+        question_id: question.id,
+        category_id: question.category_id,
+        title: question.title.clone(),
+        times_served,
+        times_correct,
+        times_unanswered,
+        fraction_correct,
+        incorrect_answer_1_chosen,
+        incorrect_answer_2_chosen,
+        incorrect_answer_3_chosen,
+        too_easy,
+        confusing,
+    }
+}
+
+#[cfg(test)]
+fn statistics_question() -> Question {
+    Question {
+        id: 1,
+        category_id: 1,
+        title: "Question".to_owned(),
+        correct_answer: "right".to_owned(),
+        incorrect_answer_1: "wrong 1".to_owned(),
+        incorrect_answer_2: "wrong 2".to_owned(),
+        incorrect_answer_3: "wrong 3".to_owned(),
+    }
+}
+
+#[cfg(test)]
+fn statistics_responses(answers: &[Option<&str>]) -> Vec<ResponseCount> {
+    let mut response_counts: Vec<ResponseCount> = Vec::new();
+
+    for answer in answers {
+        let answer = answer.map(|a| a.to_owned());
+
+        match response_counts
+            .iter_mut()
+            .find(|counted| counted.answer == answer)
+        {
+            Some(counted) => counted.count += 1,
+            None => response_counts.push(ResponseCount {
+                question_id: 1,
+                correct: answer.as_deref() == Some("right"),
+                answer,
+                count: 1,
+            }),
+        }
+    }
+
+    response_counts
+}
+
+#[test]
+fn question_statistics_counts_answers() {
+    let responses = statistics_responses(&[
+        Some("right"),
+        Some("wrong 2"),
+        Some("wrong 2"),
+        None,
+    ]);
+
+    let statistics = question_statistics(&statistics_question(), &responses);
+
+    assert_eq!(statistics.times_served, 4);
+    assert_eq!(statistics.times_correct, 1);
+    assert_eq!(statistics.times_unanswered, 1);
+    assert_eq!(statistics.fraction_correct, Some(0.25));
+    assert_eq!(statistics.incorrect_answer_1_chosen, 0);
+    assert_eq!(statistics.incorrect_answer_2_chosen, 2);
+    assert!(!statistics.too_easy);
+    assert!(!statistics.confusing);
+}
+
+#[test]
+fn question_statistics_flags_too_easy() {
+    let responses = statistics_responses(&[Some("right"); 10]);
+
+    let statistics = question_statistics(&statistics_question(), &responses);
+
+    assert!(statistics.too_easy);
+    assert!(!statistics.confusing);
+}
+
+#[test]
+fn question_statistics_flags_popular_distractor() {
+    let mut answers = vec![Some("right"); 4];
+    answers.extend(vec![Some("wrong 3"); 6]);
+    let responses = statistics_responses(&answers);
+
+    let statistics = question_statistics(&statistics_question(), &responses);
+
+    assert!(!statistics.too_easy);
+    assert!(statistics.confusing);
+}
+
+#[test]
+fn question_statistics_without_responses_works() {
+    let statistics = question_statistics(&statistics_question(), &[]);
+
+    assert_eq!(statistics.times_served, 0);
+    assert_eq!(statistics.fraction_correct, None);
+    assert!(!statistics.too_easy);
+    assert!(!statistics.confusing);
+}
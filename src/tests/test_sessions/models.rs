@@ -15,10 +15,16 @@ use log::warn;
 use crate::errors::Error;
 use crate::errors::ErrorKind;
 
-use crate::tests::questions::models::AnonymousQuestionList;
+use crate::nullable::deserialize_nullable;
+
+use crate::search::Page;
+
+use crate::tests::questions::models::AnonymousQuestion;
 use crate::tests::questions::models::ResponseQuestionList;
 
+use super::schema::test_responses;
 use super::schema::test_session_registrations;
+use super::schema::test_session_served_questions;
 use super::schema::test_sessions;
 
 #[derive(Queryable, Debug)]
@@ -26,9 +32,16 @@ pub struct RawTestSession {
     pub id: u64,
     pub test_id: u64,
     pub name: String,
-    pub registrations_enabled: bool,
-    pub opening_enabled: bool,
-    pub submissions_enabled: bool,
+    pub registrations_enabled: Option<bool>,
+    pub opening_enabled: Option<bool>,
+    pub submissions_enabled: Option<bool>,
+    pub registrations_start: Option<NaiveDateTime>,
+    pub registrations_end: Option<NaiveDateTime>,
+    pub opening_start: Option<NaiveDateTime>,
+    pub opening_end: Option<NaiveDateTime>,
+    pub submissions_start: Option<NaiveDateTime>,
+    pub submissions_end: Option<NaiveDateTime>,
+    pub time_limit_minutes: Option<u32>,
 }
 
 #[derive(Insertable, Debug)]
@@ -36,12 +49,19 @@ pub struct RawTestSession {
 pub struct NewRawTestSession {
     pub test_id: u64,
     pub name: String,
-    pub registrations_enabled: bool,
-    pub opening_enabled: bool,
-    pub submissions_enabled: bool,
+    pub registrations_enabled: Option<bool>,
+    pub opening_enabled: Option<bool>,
+    pub submissions_enabled: Option<bool>,
+    pub registrations_start: Option<NaiveDateTime>,
+    pub registrations_end: Option<NaiveDateTime>,
+    pub opening_start: Option<NaiveDateTime>,
+    pub opening_end: Option<NaiveDateTime>,
+    pub submissions_start: Option<NaiveDateTime>,
+    pub submissions_end: Option<NaiveDateTime>,
+    pub time_limit_minutes: Option<u32>,
 }
 
-#[derive(Queryable, Debug)]
+#[derive(Queryable, Serialize, Debug)]
 pub struct RawTestSessionRegistration {
     pub id: u64,
     pub test_session_id: u64,
@@ -73,40 +93,179 @@ pub struct PartialRawTestSessionRegistration {
     pub score: Option<Option<f32>>,
 }
 
+/// A question served to a test taker when they opened the test
+///
+/// Each `answer_n` is the index of the answer shown in that position, where
+/// `0` is the correct answer and `1` to `3` are the incorrect answers.
+#[derive(Queryable, Debug)]
+pub struct RawServedQuestion {
+    pub id: u64,
+    pub registration_id: u64,
+    pub question_id: u64,
+    pub position: u32,
+    pub answer_1: u8,
+    pub answer_2: u8,
+    pub answer_3: u8,
+    pub answer_4: u8,
+}
+
+#[derive(Insertable, Debug)]
+#[table_name = "test_session_served_questions"]
+pub struct NewRawServedQuestion {
+    pub registration_id: u64,
+    pub question_id: u64,
+    pub position: u32,
+    pub answer_1: u8,
+    pub answer_2: u8,
+    pub answer_3: u8,
+    pub answer_4: u8,
+}
+
+#[derive(Queryable, Debug)]
+pub struct RawTestResponse {
+    pub id: u64,
+    pub registration_id: u64,
+    pub question_id: u64,
+    pub answer: Option<String>,
+    pub correct: bool,
+}
+
+#[derive(Insertable, Debug)]
+#[table_name = "test_responses"]
+pub struct NewRawTestResponse {
+    pub registration_id: u64,
+    pub question_id: u64,
+    pub answer: Option<String>,
+    pub correct: bool,
+}
+
 #[derive(Queryable, Debug)]
 pub struct JoinedTestSession {
     pub test_session: RawTestSession,
     pub test_session_registration: Option<RawTestSessionRegistration>,
+    pub passing_score: Option<f32>,
 }
 
+/// A sitting of a test
+///
+/// Registering, opening and submitting are each allowed while the current
+/// time is inside their scheduled `_start` to `_end` window. Either end of a
+/// window may be left out, but a stage with no window at all stays closed.
+/// Setting a stage's `_enabled` flag overrides its window, forcing the stage
+/// open when `true` or closed when `false`.
 #[derive(Serialize, Deserialize, Debug)]
 pub struct TestSession {
     pub id: u64,
     pub test_id: u64,
     pub name: String,
     pub registrations: Vec<TestSessionRegistration>,
-    pub registrations_enabled: bool,
-    pub opening_enabled: bool,
-    pub submissions_enabled: bool,
+    pub registrations_enabled: Option<bool>,
+    pub opening_enabled: Option<bool>,
+    pub submissions_enabled: Option<bool>,
+    pub registrations_start: Option<DateTime<Local>>,
+    pub registrations_end: Option<DateTime<Local>>,
+    pub opening_start: Option<DateTime<Local>>,
+    pub opening_end: Option<DateTime<Local>>,
+    pub submissions_start: Option<DateTime<Local>>,
+    pub submissions_end: Option<DateTime<Local>>,
+    pub time_limit_minutes: Option<u32>,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct NewTestSession {
     pub test_id: u64,
     pub name: String,
+    pub registrations_start: Option<NaiveDateTime>,
+    pub registrations_end: Option<NaiveDateTime>,
+    pub opening_start: Option<NaiveDateTime>,
+    pub opening_end: Option<NaiveDateTime>,
+    pub submissions_start: Option<NaiveDateTime>,
+    pub submissions_end: Option<NaiveDateTime>,
+    pub time_limit_minutes: Option<u32>,
+}
+
+impl NewTestSession {
+    pub fn validate(&self) -> Result<(), Error> {
+        validate_window(self.registrations_start, self.registrations_end)?;
+        validate_window(self.opening_start, self.opening_end)?;
+        validate_window(self.submissions_start, self.submissions_end)
+    }
 }
 
 #[derive(AsChangeset, Serialize, Deserialize, Debug)]
 #[table_name = "test_sessions"]
 pub struct PartialTestSession {
-    pub registrations_enabled: Option<bool>,
-    pub opening_enabled: Option<bool>,
-    pub submissions_enabled: Option<bool>,
+    #[serde(default, deserialize_with = "deserialize_nullable")]
+    pub registrations_enabled: Option<Option<bool>>,
+    #[serde(default, deserialize_with = "deserialize_nullable")]
+    pub opening_enabled: Option<Option<bool>>,
+    #[serde(default, deserialize_with = "deserialize_nullable")]
+    pub submissions_enabled: Option<Option<bool>>,
+    #[serde(default, deserialize_with = "deserialize_nullable")]
+    pub registrations_start: Option<Option<NaiveDateTime>>,
+    #[serde(default, deserialize_with = "deserialize_nullable")]
+    pub registrations_end: Option<Option<NaiveDateTime>>,
+    #[serde(default, deserialize_with = "deserialize_nullable")]
+    pub opening_start: Option<Option<NaiveDateTime>>,
+    #[serde(default, deserialize_with = "deserialize_nullable")]
+    pub opening_end: Option<Option<NaiveDateTime>>,
+    #[serde(default, deserialize_with = "deserialize_nullable")]
+    pub submissions_start: Option<Option<NaiveDateTime>>,
+    #[serde(default, deserialize_with = "deserialize_nullable")]
+    pub submissions_end: Option<Option<NaiveDateTime>>,
+    #[serde(default, deserialize_with = "deserialize_nullable")]
+    pub time_limit_minutes: Option<Option<u32>>,
 }
 
+impl PartialTestSession {
+    /// Check the windows an existing test session ends up with once updated
+    pub fn validate(&self, existing: &TestSession) -> Result<(), Error> {
+        let naive = |datetime: Option<DateTime<Local>>| {
+            datetime.map(|datetime| datetime.naive_local())
+        };
+
+        validate_window(
+            self.registrations_start
+                .unwrap_or_else(|| naive(existing.registrations_start)),
+            self.registrations_end
+                .unwrap_or_else(|| naive(existing.registrations_end)),
+        )?;
+        validate_window(
+            self.opening_start
+                .unwrap_or_else(|| naive(existing.opening_start)),
+            self.opening_end
+                .unwrap_or_else(|| naive(existing.opening_end)),
+        )?;
+        validate_window(
+            self.submissions_start
+                .unwrap_or_else(|| naive(existing.submissions_start)),
+            self.submissions_end
+                .unwrap_or_else(|| naive(existing.submissions_end)),
+        )
+    }
+}
+
+/// Windows have to start before they end
+fn validate_window(
+    start: Option<NaiveDateTime>,
+    end: Option<NaiveDateTime>,
+) -> Result<(), Error> {
+    match (start, end) {
+        (Some(start), Some(end)) if start >= end => {
+            Err(Error::new(ErrorKind::InvalidSchedule))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// One page of test sessions, out of `total`
+///
+/// `next` is the offset of the page after this one, if there is one.
 #[derive(Serialize, Deserialize)]
 pub struct TestSessionList {
     pub test_sessions: Vec<TestSession>,
+    pub total: u64,
+    pub next: Option<u64>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -117,10 +276,39 @@ pub struct TestSessionRegistration {
     pub opened_test: Option<DateTime<Local>>,
     pub submitted_test: Option<DateTime<Local>>,
     pub score: Option<f32>,
+    pub passed: Option<bool>,
+}
+
+/// The questions of an opened test, and when it has to be submitted by
+///
+/// `deadline` and `remaining_seconds` are `None` when the test session has
+/// no time limit.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct OpenedTest {
+    pub questions: Vec<AnonymousQuestion>,
+    pub deadline: Option<DateTime<Local>>,
+    pub remaining_seconds: Option<i64>,
+}
+
+/// The answer given to one served question, `None` if it was left unanswered
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TestResponse {
+    pub question_id: u64,
+    pub title: String,
+    pub answer: Option<String>,
+    pub correct_answer: String,
+    pub correct: bool,
+}
+
+/// Every graded answer of a submitted registration
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AnswerSheet {
+    pub registration: TestSessionRegistration,
+    pub responses: Vec<TestResponse>,
 }
 
 pub enum TestSessionRequest {
-    GetTestSessions(Option<u64>),
+    GetTestSessions(Option<u64>, Option<bool>, Page),
     GetTestSession(u64),
     CreateTestSession(NewTestSession),
     UpdateTestSession(u64, PartialTestSession),
@@ -128,26 +316,33 @@ pub enum TestSessionRequest {
     Register(u64),
     Open(u64),
     Submit(u64, ResponseQuestionList),
+    GetAnswerSheet(u64, u64),
 }
 
 impl TestSessionRequest {
     pub fn from_rouille(
         request: &rouille::Request,
     ) -> Result<TestSessionRequest, Error> {
-        let mut url_queries =
+        let url_queries =
             form_urlencoded::parse(request.raw_query_string().as_bytes());
         router!(request,
             (GET) (/) => {
 
-                let test_id = url_queries.find_map(|q| {
-                    if q.0 == "test_id" {
-                        q.1.parse().ok()
-                    } else {
-                        None
+                let mut test_id = None;
+                let mut passed = None;
+                let mut page = Page::default();
+
+                for (field, query) in url_queries {
+                    match field.as_ref() {
+                        "test_id" => test_id = Some(query.parse()?),
+                        "passed" => passed = Some(query.parse()?),
+                        "limit" | "offset" | "sort" =>
+                            page.add_query(field.as_ref(), query.as_ref())?,
+                        _ => return Err(Error::new(ErrorKind::Url)),
                     }
-                });
+                }
 
-                Ok(TestSessionRequest::GetTestSessions(test_id))
+                Ok(TestSessionRequest::GetTestSessions(test_id, passed, page))
             },
 
             (GET) (/{id: u64}) => {
@@ -162,6 +357,10 @@ impl TestSessionRequest {
                 Ok(TestSessionRequest::Open(id))
             },
 
+            (GET) (/{id: u64}/registrations/{registration_id: u64}) => {
+                Ok(TestSessionRequest::GetAnswerSheet(id, registration_id))
+            },
+
             (POST) (/{id: u64}/submit) => {
                 let request_body = request.data()
                     .ok_or(Error::new(ErrorKind::Body))?;
@@ -175,6 +374,8 @@ impl TestSessionRequest {
                     .ok_or(Error::new(ErrorKind::Body))?;
                 let new_question: NewTestSession =
                     serde_json::from_reader(request_body)?;
+                new_question.validate()?;
+
                 Ok(TestSessionRequest::CreateTestSession(new_question))
             },
 
@@ -201,7 +402,8 @@ impl TestSessionRequest {
 pub enum TestSessionResponse {
     OneTestSession(TestSession),
     ManyTestSessions(TestSessionList),
-    AnonymousQuestions(AnonymousQuestionList),
+    OneOpenedTest(OpenedTest),
+    OneAnswerSheet(AnswerSheet),
     NoResponse,
 }
 
@@ -214,10 +416,45 @@ impl TestSessionResponse {
             TestSessionResponse::ManyTestSessions(test_sessions) => {
                 rouille::Response::json(&test_sessions)
             }
-            TestSessionResponse::AnonymousQuestions(questions) => {
-                rouille::Response::json(&questions)
+            TestSessionResponse::OneOpenedTest(opened_test) => {
+                rouille::Response::json(&opened_test)
+            }
+            TestSessionResponse::OneAnswerSheet(answer_sheet) => {
+                rouille::Response::json(&answer_sheet)
             }
             TestSessionResponse::NoResponse => rouille::Response::empty_204(),
         }
     }
 }
+
+#[test]
+fn validate_window_works() {
+    let datetime = |hour| {
+        chrono::NaiveDate::from_ymd_opt(2019, 10, 14)
+            .and_then(|date| date.and_hms_opt(hour, 0, 0))
+    };
+
+    assert!(validate_window(datetime(9), datetime(10)).is_ok());
+    assert!(validate_window(None, datetime(10)).is_ok());
+    assert!(validate_window(datetime(9), None).is_ok());
+    assert!(validate_window(datetime(10), datetime(10)).is_err());
+    assert!(validate_window(datetime(11), datetime(10)).is_err());
+}
+
+#[test]
+fn partial_test_session_clears_overrides_with_null() {
+    let partial: PartialTestSession =
+        serde_json::from_str(r#"{"registrations_enabled": null}"#).unwrap();
+
+    assert_eq!(partial.registrations_enabled, Some(None));
+    assert_eq!(partial.opening_enabled, None);
+    assert_eq!(partial.registrations_start, None);
+}
+
+#[test]
+fn partial_test_session_removes_time_limit_with_null() {
+    let partial: PartialTestSession =
+        serde_json::from_str(r#"{"time_limit_minutes": null}"#).unwrap();
+
+    assert_eq!(partial.time_limit_minutes, Some(None));
+}
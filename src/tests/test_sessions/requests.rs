@@ -1,45 +1,73 @@
+use std::collections::{HashMap, HashSet};
+
 use crate::diesel::NullableExpressionMethods;
 use diesel;
+use diesel::mysql::Mysql;
 use diesel::mysql::MysqlConnection;
 use diesel::BoolExpressionMethods;
+use diesel::Connection;
 use diesel::ExpressionMethods;
+use diesel::JoinOnDsl;
 use diesel::QueryDsl;
 use diesel::RunQueryDsl;
 
 use chrono::offset::Local;
 use chrono::offset::TimeZone;
+use chrono::DateTime;
+use chrono::Duration;
+use chrono::NaiveDateTime;
 
 use rand::seq::SliceRandom;
 
 use log::error;
 use log::trace;
 
+use crate::dates::to_local;
+
 use crate::errors::Error;
 use crate::errors::ErrorKind;
 
-use crate::access::requests::check_to_run;
+use crate::search::{Page, DEFAULT_LIMIT};
+
+use crate::access::models::Resource;
+use crate::access::requests::{check_to_run, check_to_search};
+
+use crate::audit::requests::record_change;
 
 use crate::tests::test_sessions::models::{
-    JoinedTestSession, NewRawTestSession, NewRawTestSessionRegistration,
-    NewTestSession, PartialRawTestSessionRegistration, PartialTestSession,
-    RawTestSession, RawTestSessionRegistration, TestSession, TestSessionList,
+    AnswerSheet, JoinedTestSession, NewRawServedQuestion, NewRawTestResponse,
+    NewRawTestSession, NewRawTestSessionRegistration, NewTestSession,
+    OpenedTest, PartialRawTestSessionRegistration, PartialTestSession,
+    RawServedQuestion, RawTestResponse, RawTestSession,
+    RawTestSessionRegistration, TestResponse, TestSession, TestSessionList,
     TestSessionRegistration, TestSessionRequest, TestSessionResponse,
 };
 
 use crate::tests::questions::models::AnonymousQuestion;
-use crate::tests::questions::models::AnonymousQuestionList;
 use crate::tests::questions::models::Question;
+use crate::tests::questions::models::ResponseQuestion;
 use crate::tests::questions::models::ResponseQuestionList;
 
 use crate::tests::question_categories::requests::get_question_category;
 
+use crate::certifications::requests::{
+    issue_certification, revoke_certifications,
+};
+
+use crate::tests::tests::models::{CountedAttempt, RawTest};
 use crate::tests::tests::requests::get_test;
 
+use crate::tests::test_sessions::schema::test_responses as test_responses_schema;
 use crate::tests::test_sessions::schema::test_session_registrations as test_session_registrations_schema;
+use crate::tests::test_sessions::schema::test_session_served_questions as test_session_served_questions_schema;
 use crate::tests::test_sessions::schema::test_sessions as test_sessions_schema;
+use crate::tests::tests::schema::tests as tests_schema;
 
 use crate::tests::questions::schema::questions as questions_schema;
 
+/// How long after the deadline a submission is still accepted
+const GRACE_PERIOD_SECONDS: i64 = 60;
+
 pub fn handle_test_session(
     request: TestSessionRequest,
     requested_user: Option<u64>,
@@ -52,7 +80,7 @@ pub fn handle_test_session(
         }
         TestSessionRequest::Open(test_session_id) => {
             open(test_session_id, requested_user, database_connection)
-                .map(|u| TestSessionResponse::AnonymousQuestions(u))
+                .map(|u| TestSessionResponse::OneOpenedTest(u))
         }
         TestSessionRequest::Submit(test_session_id, respose_questions) => {
             submit(
@@ -63,54 +91,184 @@ pub fn handle_test_session(
             )
             .map(|_| TestSessionResponse::NoResponse)
         }
-        TestSessionRequest::GetTestSessions(test_id) => {
+        TestSessionRequest::GetAnswerSheet(
+            test_session_id,
+            registration_id,
+        ) => {
+            check_to_search(
+                requested_user,
+                "GetTestResponses",
+                database_connection,
+            )?;
+            let test_session =
+                get_test_session(test_session_id, database_connection)?;
+            check_test_resource(
+                requested_user,
+                "GetTestResponses",
+                test_session.test_id,
+                database_connection,
+            )?;
+            get_answer_sheet(
+                test_session_id,
+                registration_id,
+                database_connection,
+            )
+            .map(|u| TestSessionResponse::OneAnswerSheet(u))
+        }
+        TestSessionRequest::GetTestSessions(test_id, passed, page) => {
             check_to_run(
                 requested_user,
                 "GetTestSessions",
+                None,
                 database_connection,
             )?;
-            get_test_sessions(test_id, database_connection)
+            get_test_sessions(test_id, passed, page, database_connection)
                 .map(|u| TestSessionResponse::ManyTestSessions(u))
         }
         TestSessionRequest::GetTestSession(id) => {
-            check_to_run(
+            check_to_search(
                 requested_user,
                 "GetTestSessions",
                 database_connection,
             )?;
-            get_test_session(id, database_connection)
-                .map(|u| TestSessionResponse::OneTestSession(u))
+            let test_session = get_test_session(id, database_connection)?;
+            check_test_resource(
+                requested_user,
+                "GetTestSessions",
+                test_session.test_id,
+                database_connection,
+            )?;
+            Ok(TestSessionResponse::OneTestSession(test_session))
         }
         TestSessionRequest::CreateTestSession(test_session) => {
-            check_to_run(
+            check_to_search(
+                requested_user,
+                "CreateTestSessions",
+                database_connection,
+            )?;
+            check_test_resource(
                 requested_user,
                 "CreateTestSessions",
+                test_session.test_id,
                 database_connection,
             )?;
-            create_test_session(test_session, database_connection)
+            database_connection
+                .transaction::<_, Error, _>(|| {
+                    let created =
+                        create_test_session(test_session, database_connection)?;
+                    record_change(
+                        requested_user,
+                        "CreateTestSessions",
+                        "test_sessions",
+                        created.id,
+                        None,
+                        Some(&created),
+                        database_connection,
+                    )?;
+                    Ok(created)
+                })
                 .map(|u| TestSessionResponse::OneTestSession(u))
         }
         TestSessionRequest::UpdateTestSession(id, test_session) => {
-            check_to_run(
+            check_to_search(
+                requested_user,
+                "UpdateTestSessions",
+                database_connection,
+            )?;
+            let existing = get_test_session(id, database_connection)?;
+            check_test_resource(
                 requested_user,
                 "UpdateTestSessions",
+                existing.test_id,
                 database_connection,
             )?;
-            update_test_session(id, test_session, database_connection)
+            test_session.validate(&existing)?;
+            database_connection
+                .transaction::<_, Error, _>(|| {
+                    update_test_session(id, test_session, database_connection)?;
+                    let after = get_test_session(id, database_connection)?;
+                    record_change(
+                        requested_user,
+                        "UpdateTestSessions",
+                        "test_sessions",
+                        id,
+                        Some(&existing),
+                        Some(&after),
+                        database_connection,
+                    )
+                })
                 .map(|_| TestSessionResponse::NoResponse)
         }
         TestSessionRequest::DeleteTestSession(id) => {
-            check_to_run(
+            check_to_search(
                 requested_user,
                 "DeleteTestSessions",
                 database_connection,
             )?;
-            delete_test_session(id, database_connection)
+            let existing = get_test_session(id, database_connection)?;
+            check_test_resource(
+                requested_user,
+                "DeleteTestSessions",
+                existing.test_id,
+                database_connection,
+            )?;
+            database_connection
+                .transaction::<_, Error, _>(|| {
+                    delete_test_session(id, database_connection)?;
+                    record_change(
+                        requested_user,
+                        "DeleteTestSessions",
+                        "test_sessions",
+                        id,
+                        Some(&existing),
+                        None,
+                        database_connection,
+                    )
+                })
                 .map(|_| TestSessionResponse::NoResponse)
         }
     }
 }
 
+/// Check that the requesting user may use a permission on a test's sessions
+/// and their registrations, which are owned by the test's creator
+///
+/// This runs after `check_to_search`, so only users with some grant of the
+/// permission get this far, and those whose grants do not cover the test are
+/// told it was not found.
+fn check_test_resource(
+    requested_user: Option<u64>,
+    permission_name: &str,
+    test_id: u64,
+    database_connection: &MysqlConnection,
+) -> Result<(), Error> {
+    let mut creator_ids = tests_schema::table
+        .filter(tests_schema::id.eq(test_id))
+        .select(tests_schema::creator_id)
+        .load::<u64>(database_connection)?;
+
+    let resource = match creator_ids.pop() {
+        Some(creator_id) => Resource {
+            owner_id: Some(creator_id),
+            room: None,
+        },
+        None => return Err(Error::new(ErrorKind::NotFound)),
+    };
+
+    match check_to_run(
+        requested_user,
+        permission_name,
+        Some(&resource),
+        database_connection,
+    ) {
+        Ok(()) => Ok(()),
+        Err(e) => match e.kind() {
+            ErrorKind::AccessDenied => Err(Error::new(ErrorKind::NotFound)),
+            _ => Err(e),
+        },
+    }
+}
+
 pub(crate) fn register(
     test_session_id: u64,
     requested_user: Option<u64>,
@@ -118,28 +276,39 @@ pub(crate) fn register(
 ) -> Result<(), Error> {
     let test_session = get_test_session(test_session_id, database_connection)?;
 
-    if test_session.registrations_enabled {
+    let now = Local::now();
+
+    if window_open(
+        test_session.registrations_enabled,
+        test_session.registrations_start,
+        test_session.registrations_end,
+        now,
+    ) {
         if let Some(user_id) = requested_user {
-            let existing_open_registrations =
-                test_session_registrations_schema::table
-                    .filter(
-                        test_session_registrations_schema::taker_id
-                            .eq(user_id)
-                            .and(
-                                test_session_registrations_schema::opened_test
-                                    .is_null(),
-                            )
-                            .or(test_session_registrations_schema::submitted_test
-                                .is_null()),
-                    )
-                    .load::<RawTestSessionRegistration>(database_connection)?;
+            let test = get_test(test_session.test_id, database_connection)?;
+            if test.deleted_at.is_some() {
+                return Err(Error::new(ErrorKind::RegistrationClosedForTest));
+            }
+
+            database_connection.transaction::<_, Error, _>(|| {
+                // Locking the user's attempts keeps two registrations at once
+                // from both passing the retake checks
+                let attempts =
+                    lock_attempts(test.id, user_id, database_connection)?;
+
+                check_retake(
+                    test_session_id,
+                    test.max_attempts,
+                    test.retake_cooldown_hours,
+                    &attempts,
+                    now.naive_local(),
+                )?;
 
-            if existing_open_registrations.len() == 0 {
                 let new_raw_test_session_registration =
                     NewRawTestSessionRegistration {
                         test_session_id: test_session_id,
                         taker_id: user_id,
-                        registered: Local::now().naive_local(),
+                        registered: now.naive_local(),
                         opened_test: None,
                         submitted_test: None,
                         score: None,
@@ -149,10 +318,20 @@ pub(crate) fn register(
                     .values(new_raw_test_session_registration)
                     .execute(database_connection)?;
 
-                Ok(())
-            } else {
-                Err(Error::new(ErrorKind::RegisteredTwiceForTest))
-            }
+                let registration = test_session_registrations_schema::table
+                    .filter(diesel::dsl::sql("id = LAST_INSERT_ID()"))
+                    .first::<RawTestSessionRegistration>(database_connection)?;
+
+                record_change(
+                    requested_user,
+                    "RegisterForTest",
+                    "test_session_registrations",
+                    registration.id,
+                    None,
+                    Some(&registration),
+                    database_connection,
+                )
+            })
         } else {
             Err(Error::new(ErrorKind::AccessDenied))
         }
@@ -165,109 +344,99 @@ pub(crate) fn open(
     test_session_id: u64,
     requested_user: Option<u64>,
     database_connection: &MysqlConnection,
-) -> Result<AnonymousQuestionList, Error> {
+) -> Result<OpenedTest, Error> {
     if let Some(user_id) = requested_user {
         let test_session =
             get_test_session(test_session_id, database_connection)?;
-        if test_session.opening_enabled {
-            let existing_open_registrations =
-                test_session_registrations_schema::table
-                    .filter(
-                        test_session_registrations_schema::taker_id
-                            .eq(user_id)
-                            .and(
-                                test_session_registrations_schema::opened_test
-                                    .is_null(),
-                            )
-                            .and(
-                                test_session_registrations_schema::submitted_test
-                                    .is_null(),
-                            ),
-                    )
-                    .load::<RawTestSessionRegistration>(database_connection)?;
+        if window_open(
+            test_session.opening_enabled,
+            test_session.opening_start,
+            test_session.opening_end,
+            Local::now(),
+        ) {
+            let registration = get_unsubmitted_registration(
+                test_session_id,
+                user_id,
+                database_connection,
+            )?;
 
-            if existing_open_registrations.len() == 1 {
-                let test_session =
-                    get_test_session(test_session_id, database_connection)?;
+            let opened_test = database_connection
+                .transaction::<_, Error, _>(|| {
+                    // Lock the registration, so that opening the test twice
+                    // at once can not serve two sets of questions
+                    let registration =
+                        lock_registration(registration.id, database_connection)?;
+
+                    if let Some(opened_test) = registration.opened_test {
+                        trace!(
+                            "Registration {} reopened the test, serving the same questions",
+                            registration.id
+                        );
 
-                let test = get_test(test_session.test_id, database_connection)?;
+                        return Ok(opened_test);
+                    }
 
-                let mut all_questions = Vec::new();
+                    let opened_test = Local::now().naive_local();
 
-                for test_question_category in test.questions {
-                    let question_category = get_question_category(
-                        test_question_category.question_category_id,
+                    serve_questions(
+                        registration.id,
+                        test_session.test_id,
                         database_connection,
                     )?;
 
-                    let questions = questions_schema::table
-                        .filter(
-                            questions_schema::category_id
-                                .eq(question_category.id),
-                        )
-                        .load::<Question>(database_connection)?;
-
-                    let mut chosen_questions: Vec<_> = questions
-                        .choose_multiple(
-                            &mut rand::thread_rng(),
-                            test_question_category.number_of_questions as usize,
-                        )
-                        .into_iter()
-                        .map(|q| {
-                            let mut answers = [
-                                q.correct_answer.clone(),
-                                q.incorrect_answer_1.clone(),
-                                q.incorrect_answer_2.clone(),
-                                q.incorrect_answer_3.clone(),
-                            ];
-
-                            answers.shuffle(&mut rand::thread_rng());
-
-                            AnonymousQuestion {
-                                id: q.id,
-                                title: q.title.clone(),
-                                answer_1: answers[0].clone(),
-                                answer_2: answers[1].clone(),
-                                answer_3: answers[2].clone(),
-                                answer_4: answers[3].clone(),
-                            }
-                        })
-                        .collect();
-
-                    all_questions.append(&mut chosen_questions);
-                }
-
-                let partial_raw_test_session_registration =
-                    PartialRawTestSessionRegistration {
-                        taker_id: None,
-                        registered: None,
-                        opened_test: Some(Some(Local::now().naive_local())),
-                        submitted_test: None,
-                        score: None,
-                    };
-
-                diesel::update(test_session_registrations_schema::table)
-                    .set(&partial_raw_test_session_registration)
-                    .filter(
-                        test_session_registrations_schema::taker_id
-                            .eq(user_id)
-                            .and(
-                                test_session_registrations_schema::test_session_id
-                                    .eq(test_session_id),
-                            )
-                            .and(
-                                test_session_registrations_schema::opened_test
-                                    .is_null(),
-                            ),
+                    let partial_raw_test_session_registration =
+                        PartialRawTestSessionRegistration {
+                            taker_id: None,
+                            registered: None,
+                            opened_test: Some(Some(opened_test)),
+                            submitted_test: None,
+                            score: None,
+                        };
+
+                    diesel::update(
+                        test_session_registrations_schema::table.filter(
+                            test_session_registrations_schema::id
+                                .eq(registration.id),
+                        ),
                     )
+                    .set(&partial_raw_test_session_registration)
                     .execute(database_connection)?;
 
-                Ok(AnonymousQuestionList {
-                    questions: all_questions,
-                })
-            } else {
-                Err(Error::new(ErrorKind::OpenedTestTwice))
-            }
+                    let after =
+                        lock_registration(registration.id, database_connection)?;
+                    record_change(
+                        requested_user,
+                        "OpenTest",
+                        "test_session_registrations",
+                        registration.id,
+                        Some(&registration),
+                        Some(&after),
+                        database_connection,
+                    )?;
+
+                    Ok(opened_test)
+                })?;
+
+            let questions =
+                get_served_questions(registration.id, database_connection)?
+                    .iter()
+                    .map(|(served_question, question)| {
+                        anonymize_question(served_question, question)
+                    })
+                    .collect();
+
+            let deadline = deadline(
+                to_local(opened_test)?,
+                test_session.time_limit_minutes,
+            );
+
+            Ok(OpenedTest {
+                questions,
+                deadline,
+                remaining_seconds: deadline.map(|deadline| {
+                    (deadline - Local::now()).num_seconds().max(0)
+                }),
+            })
         } else {
             Err(Error::new(ErrorKind::OpeningClosedForTest))
         }
@@ -285,88 +454,588 @@ pub(crate) fn submit(
     if let Some(user_id) = requested_user {
         let test_session =
             get_test_session(test_session_id, database_connection)?;
-        if test_session.submissions_enabled {
-            let existing_open_registrations =
-                test_session_registrations_schema::table
-                    .filter(
-                        test_session_registrations_schema::taker_id
-                            .eq(user_id)
-                            .and(
-                                test_session_registrations_schema::opened_test
-                                    .is_not_null(),
-                            )
-                            .and(
-                                test_session_registrations_schema::submitted_test
-                                    .is_null(),
-                            ),
-                    )
-                    .load::<RawTestSessionRegistration>(database_connection)?;
+        if window_open(
+            test_session.submissions_enabled,
+            test_session.submissions_start,
+            test_session.submissions_end,
+            Local::now(),
+        ) {
+            database_connection.transaction::<_, Error, _>(|| {
+                let registration = get_unsubmitted_registration(
+                    test_session_id,
+                    user_id,
+                    database_connection,
+                )?;
+
+                // Lock the registration and check it again, so that
+                // submitting the test twice at once can not record two sets
+                // of responses
+                let registration =
+                    lock_registration(registration.id, database_connection)?;
+
+                if registration.submitted_test.is_some() {
+                    return Err(Error::new(ErrorKind::OpenedTestTwice));
+                }
 
-            if existing_open_registrations.len() == 1 {
-                let n_questions = response_questions.questions.len();
+                let opened_test = match registration.opened_test {
+                    Some(opened_test) => to_local(opened_test)?,
+                    None => {
+                        return Err(Error::new(
+                            ErrorKind::SubmittedTestNotOpened,
+                        ))
+                    }
+                };
 
-                if n_questions == 0 {
-                    return Err(Error::new(ErrorKind::Body));
+                let deadline =
+                    deadline(opened_test, test_session.time_limit_minutes);
+
+                // A late attempt is refused before anything is recorded, so
+                // it stays unsubmitted
+                if past_deadline(deadline, Local::now()) {
+                    return Err(Error::new(ErrorKind::SubmittedAfterTimeLimit));
                 }
 
-                let mut n_correct = 0;
+                let served_questions: Vec<_> =
+                    get_served_questions(registration.id, database_connection)?
+                        .into_iter()
+                        .map(|(_, question)| question)
+                        .collect();
+
+                // Only the test's own row is needed, so tests without
+                // question categories can still be submitted
+                let test = tests_schema::table
+                    .filter(tests_schema::id.eq(test_session.test_id))
+                    .first::<RawTest>(database_connection)?;
+
+                let graded_responses = grade(
+                    registration.id,
+                    &served_questions,
+                    &response_questions.questions,
+                )?;
+
+                record_submission(
+                    &registration,
+                    &test,
+                    graded_responses,
+                    database_connection,
+                )
+            })
+        } else {
+            Err(Error::new(ErrorKind::SubmissionsClosedForTest))
+        }
+    } else {
+        Err(Error::new(ErrorKind::AccessDenied))
+    }
+}
+
+/// Save the graded responses of a registration and mark it submitted,
+/// moving the test's certification to whichever attempt now counts
+///
+/// This has to run in the same transaction that locked the registration.
+fn record_submission(
+    registration: &RawTestSessionRegistration,
+    test: &RawTest,
+    graded_responses: Vec<NewRawTestResponse>,
+    database_connection: &MysqlConnection,
+) -> Result<(), Error> {
+    let score = score(&graded_responses);
 
-                for response_question in response_questions.questions {
-                    let mut questions = questions_schema::table
-                        .filter(questions_schema::id.eq(response_question.id))
-                        .load::<Question>(database_connection)?;
+    trace!("Score: {}", score);
 
-                    if let Some(question) = questions.pop() {
-                        if question.correct_answer == response_question.answer {
-                            n_correct += 1;
-                        }
-                    } else {
-                        return Err(Error::new(ErrorKind::Database));
-                    }
-                }
+    let submitted_test = Local::now().naive_local();
 
-                let score = n_correct as f32 / n_questions as f32;
+    diesel::insert_into(test_responses_schema::table)
+        .values(graded_responses)
+        .execute(database_connection)?;
 
-                trace!("Score: {}", score);
+    let partial_raw_test_session_registration =
+        PartialRawTestSessionRegistration {
+            taker_id: None,
+            registered: None,
+            opened_test: None,
+            submitted_test: Some(Some(submitted_test)),
+            score: Some(Some(score)),
+        };
 
-                let partial_raw_test_session_registration =
-                    PartialRawTestSessionRegistration {
-                        taker_id: None,
-                        registered: None,
-                        opened_test: None,
-                        submitted_test: Some(Some(Local::now().naive_local())),
-                        score: Some(Some(score)),
-                    };
+    diesel::update(
+        test_session_registrations_schema::table
+            .filter(test_session_registrations_schema::id.eq(registration.id)),
+    )
+    .set(&partial_raw_test_session_registration)
+    .execute(database_connection)?;
 
-                diesel::update(test_session_registrations_schema::table)
-                    .filter(
-                        test_session_registrations_schema::taker_id
-                            .eq(user_id)
-                            .and(
-                                test_session_registrations_schema::test_session_id
-                                    .eq(test_session_id),
-                            )
-                            .and(
-                                test_session_registrations_schema::opened_test
-                                    .is_not_null(),
-                            )
-                            .and(
-                                test_session_registrations_schema::score.is_null(),
-                            ),
-                    )
-                    .set(&partial_raw_test_session_registration)
-                    .execute(database_connection)?;
+    let after = lock_registration(registration.id, database_connection)?;
+    record_change(
+        Some(registration.taker_id),
+        "SubmitTest",
+        "test_session_registrations",
+        registration.id,
+        Some(registration),
+        Some(&after),
+        database_connection,
+    )?;
+
+    if let Some(certification_id) = test.certification_id {
+        // The certification follows the attempt that counts, so a later
+        // attempt can move it or take it away
+        let attempts =
+            lock_attempts(test.id, registration.taker_id, database_connection)?;
+        let certified_id = certified_attempt(
+            CountedAttempt::from_name(&test.counted_attempt)?,
+            test.passing_score,
+            &attempts,
+        )
+        .map(|attempt| attempt.id);
+
+        let uncertified_ids: Vec<u64> = attempts
+            .iter()
+            .map(|attempt| attempt.id)
+            .filter(|&id| Some(id) != certified_id)
+            .collect();
+
+        let revoked = revoke_certifications(
+            certification_id,
+            &uncertified_ids,
+            database_connection,
+        )?;
+        for revoked in &revoked {
+            record_change(
+                Some(registration.taker_id),
+                "RevokeCertification",
+                "user_certifications",
+                revoked.id,
+                Some(revoked),
+                None,
+                database_connection,
+            )?;
+        }
+
+        if certified_id == Some(registration.id) {
+            let issued = issue_certification(
+                registration.taker_id,
+                certification_id,
+                Some(registration.id),
+                submitted_test,
+                database_connection,
+            )?;
+            record_change(
+                Some(registration.taker_id),
+                "IssueCertification",
+                "user_certifications",
+                issued.id,
+                None,
+                Some(&issued),
+                database_connection,
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Find the registration of a user for a test session that has not been submitted yet
+pub(crate) fn get_unsubmitted_registration(
+    test_session_id: u64,
+    user_id: u64,
+    database_connection: &MysqlConnection,
+) -> Result<RawTestSessionRegistration, Error> {
+    let registrations = test_session_registrations_schema::table
+        .filter(
+            test_session_registrations_schema::taker_id.eq(user_id).and(
+                test_session_registrations_schema::test_session_id
+                    .eq(test_session_id),
+            ),
+        )
+        .load::<RawTestSessionRegistration>(database_connection)?;
+
+    let n_registrations = registrations.len();
+
+    match registrations
+        .into_iter()
+        .find(|registration| registration.submitted_test.is_none())
+    {
+        Some(registration) => Ok(registration),
+        None if n_registrations > 0 => {
+            Err(Error::new(ErrorKind::OpenedTestTwice))
+        }
+        None => Err(Error::new(ErrorKind::OpenedTestNotRegistered)),
+    }
+}
+
+/// Get a user's registrations across every session of a test, locking them
+/// until the end of the transaction
+fn lock_attempts(
+    test_id: u64,
+    taker_id: u64,
+    database_connection: &MysqlConnection,
+) -> Result<Vec<RawTestSessionRegistration>, Error> {
+    let attempts = test_session_registrations_schema::table
+        .inner_join(test_sessions_schema::table)
+        .select(test_session_registrations_schema::all_columns)
+        .filter(test_sessions_schema::test_id.eq(test_id))
+        .filter(test_session_registrations_schema::taker_id.eq(taker_id))
+        .order(test_session_registrations_schema::id.asc())
+        .for_update()
+        .load::<RawTestSessionRegistration>(database_connection)?;
+
+    Ok(attempts)
+}
+
+/// Get a registration, locking it until the end of the transaction
+fn lock_registration(
+    id: u64,
+    database_connection: &MysqlConnection,
+) -> Result<RawTestSessionRegistration, Error> {
+    let mut found_registrations = test_session_registrations_schema::table
+        .filter(test_session_registrations_schema::id.eq(id))
+        .for_update()
+        .load::<RawTestSessionRegistration>(database_connection)?;
+
+    match found_registrations.pop() {
+        Some(registration) => Ok(registration),
+        None => Err(Error::new(ErrorKind::NotFound)),
+    }
+}
+
+/// Pick random questions for each category of a test, shuffle their answers,
+/// and record them against the registration
+fn serve_questions(
+    registration_id: u64,
+    test_id: u64,
+    database_connection: &MysqlConnection,
+) -> Result<(), Error> {
+    let test = get_test(test_id, database_connection)?;
+
+    let mut new_served_questions = Vec::new();
+
+    for test_question_category in test.questions {
+        let question_category = get_question_category(
+            test_question_category.question_category_id,
+            database_connection,
+        )?;
+
+        let questions = questions_schema::table
+            .filter(questions_schema::category_id.eq(question_category.id))
+            .load::<Question>(database_connection)?;
+
+        let chosen_questions = questions.choose_multiple(
+            &mut rand::thread_rng(),
+            test_question_category.number_of_questions as usize,
+        );
+
+        for question in chosen_questions {
+            let mut answers = [0, 1, 2, 3];
+            answers.shuffle(&mut rand::thread_rng());
+
+            new_served_questions.push(NewRawServedQuestion {
+                registration_id,
+                question_id: question.id,
+                position: new_served_questions.len() as u32,
+                answer_1: answers[0],
+                answer_2: answers[1],
+                answer_3: answers[2],
+                answer_4: answers[3],
+            });
+        }
+    }
+
+    diesel::insert_into(test_session_served_questions_schema::table)
+        .values(new_served_questions)
+        .execute(database_connection)?;
+
+    Ok(())
+}
+
+/// Get the questions served to a registration, in the order they were served
+pub(crate) fn get_served_questions(
+    registration_id: u64,
+    database_connection: &MysqlConnection,
+) -> Result<Vec<(RawServedQuestion, Question)>, Error> {
+    let served_questions = test_session_served_questions_schema::table
+        .inner_join(questions_schema::table)
+        .filter(
+            test_session_served_questions_schema::registration_id
+                .eq(registration_id),
+        )
+        .order(test_session_served_questions_schema::position.asc())
+        .load::<(RawServedQuestion, Question)>(database_connection)?;
+
+    Ok(served_questions)
+}
+
+/// The text of an answer, where `0` is the correct answer and `1` to `3` are
+/// the incorrect answers
+fn served_answer(question: &Question, index: u8) -> String {
+    match index {
+        0 => question.correct_answer.clone(),
+        1 => question.incorrect_answer_1.clone(),
+        2 => question.incorrect_answer_2.clone(),
+        _ => question.incorrect_answer_3.clone(),
+    }
+}
+
+fn anonymize_question(
+    served_question: &RawServedQuestion,
+    question: &Question,
+) -> AnonymousQuestion {
+    AnonymousQuestion {
+        id: question.id,
+        title: question.title.clone(),
+        answer_1: served_answer(question, served_question.answer_1),
+        answer_2: served_answer(question, served_question.answer_2),
+        answer_3: served_answer(question, served_question.answer_3),
+        answer_4: served_answer(question, served_question.answer_4),
+    }
+}
+
+/// Grade the responses to a set of served questions
+///
+/// Served questions without a response are graded as wrong, and responses to
+/// questions that were not served are rejected.
+pub(crate) fn grade(
+    registration_id: u64,
+    served_questions: &[Question],
+    response_questions: &[ResponseQuestion],
+) -> Result<Vec<NewRawTestResponse>, Error> {
+    if response_questions.iter().any(|response_question| {
+        !served_questions
+            .iter()
+            .any(|question| question.id == response_question.id)
+    }) {
+        return Err(Error::new(ErrorKind::SubmittedQuestionNotServed));
+    }
+
+    let mut answered_ids = HashSet::new();
 
-                Ok(())
-            } else {
-                Err(Error::new(ErrorKind::OpenedTestTwice))
+    if !response_questions
+        .iter()
+        .all(|response_question| answered_ids.insert(response_question.id))
+    {
+        return Err(Error::new(ErrorKind::SubmittedQuestionTwice));
+    }
+
+    let graded_responses = served_questions
+        .iter()
+        .map(|question| {
+            let answer = response_questions
+                .iter()
+                .find(|response_question| response_question.id == question.id)
+                .map(|response_question| response_question.answer.clone());
+
+            NewRawTestResponse {
+                registration_id,
+                question_id: question.id,
+                correct: answer.as_ref() == Some(&question.correct_answer),
+                answer,
             }
-        } else {
-            Err(Error::new(ErrorKind::SubmissionsClosedForTest))
+        })
+        .collect();
+
+    Ok(graded_responses)
+}
+
+/// The fraction of graded responses that are correct
+pub(crate) fn score(graded_responses: &[NewRawTestResponse]) -> f32 {
+    if graded_responses.is_empty() {
+        return 0.0;
+    }
+
+    let n_correct = graded_responses
+        .iter()
+        .filter(|graded_response| graded_response.correct)
+        .count();
+
+    n_correct as f32 / graded_responses.len() as f32
+}
+
+pub(crate) fn get_answer_sheet(
+    test_session_id: u64,
+    registration_id: u64,
+    database_connection: &MysqlConnection,
+) -> Result<AnswerSheet, Error> {
+    let registration = get_test_session(test_session_id, database_connection)?
+        .registrations
+        .into_iter()
+        .find(|registration| registration.id == registration_id)
+        .ok_or(Error::new(ErrorKind::NotFound))?;
+
+    // Responses are listed in the order their questions were served
+    let responses = test_responses_schema::table
+        .inner_join(questions_schema::table)
+        .inner_join(
+            test_session_served_questions_schema::table.on(
+                test_session_served_questions_schema::registration_id
+                    .eq(test_responses_schema::registration_id)
+                    .and(
+                        test_session_served_questions_schema::question_id
+                            .eq(test_responses_schema::question_id),
+                    ),
+            ),
+        )
+        .filter(test_responses_schema::registration_id.eq(registration_id))
+        .order(test_session_served_questions_schema::position.asc())
+        .select((
+            test_responses_schema::all_columns,
+            questions_schema::all_columns,
+        ))
+        .load::<(RawTestResponse, Question)>(database_connection)?
+        .into_iter()
+        .map(|(raw_test_response, question)| TestResponse {
+            question_id: question.id,
+            title: question.title,
+            answer: raw_test_response.answer,
+            correct_answer: question.correct_answer,
+            correct: raw_test_response.correct,
+        })
+        .collect();
+
+    Ok(AnswerSheet {
+        registration,
+        responses,
+    })
+}
+
+/// Check that a user may register for another attempt at a test
+///
+/// `attempts` are the user's registrations across every session of the test.
+pub(crate) fn check_retake(
+    test_session_id: u64,
+    max_attempts: Option<u32>,
+    retake_cooldown_hours: Option<u32>,
+    attempts: &[RawTestSessionRegistration],
+    now: NaiveDateTime,
+) -> Result<(), Error> {
+    if attempts.iter().any(|attempt| {
+        attempt.test_session_id == test_session_id
+            && attempt.submitted_test.is_none()
+    }) {
+        return Err(Error::new(ErrorKind::RegisteredTwiceForTest));
+    }
+
+    if let Some(max_attempts) = max_attempts {
+        if attempts.len() >= max_attempts as usize {
+            return Err(Error::new(ErrorKind::RetakeLimitReached));
         }
-    } else {
-        Err(Error::new(ErrorKind::AccessDenied))
     }
+
+    if let Some(retake_cooldown_hours) = retake_cooldown_hours {
+        // Attempts that are still open have not started their cooldown yet
+        let last_finished = attempts
+            .iter()
+            .map(|attempt| attempt.submitted_test.unwrap_or(now))
+            .max();
+
+        if let Some(last_finished) = last_finished {
+            let cooldown = Duration::hours(i64::from(retake_cooldown_hours));
+
+            if now < last_finished + cooldown {
+                return Err(Error::new(ErrorKind::RetakeCooldown));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Pick the submitted attempt that counts as a user's result
+pub(crate) fn counted_attempt(
+    policy: CountedAttempt,
+    attempts: &[RawTestSessionRegistration],
+) -> Option<&RawTestSessionRegistration> {
+    let submitted = attempts
+        .iter()
+        .filter(|attempt| attempt.submitted_test.is_some());
+
+    match policy {
+        CountedAttempt::First => {
+            submitted.min_by_key(|attempt| attempt.submitted_test)
+        }
+        CountedAttempt::Latest => {
+            submitted.max_by_key(|attempt| attempt.submitted_test)
+        }
+        CountedAttempt::Best => {
+            submitted.fold(None, |best, attempt| match best {
+                Some(best) if best_score(best) >= best_score(attempt) => {
+                    Some(best)
+                }
+                _ => Some(attempt),
+            })
+        }
+    }
+}
+
+/// Pick the attempt that holds a test's certification: the counted attempt,
+/// if it passed
+pub(crate) fn certified_attempt(
+    policy: CountedAttempt,
+    passing_score: Option<f32>,
+    attempts: &[RawTestSessionRegistration],
+) -> Option<&RawTestSessionRegistration> {
+    counted_attempt(policy, attempts)
+        .filter(|attempt| passed(attempt.score, passing_score) == Some(true))
+}
+
+fn best_score(attempt: &RawTestSessionRegistration) -> f32 {
+    attempt.score.unwrap_or(0.0)
+}
+
+/// Whether a score passes, if the test has a passing score and the attempt
+/// has been scored
+pub(crate) fn passed(
+    score: Option<f32>,
+    passing_score: Option<f32>,
+) -> Option<bool> {
+    match (score, passing_score) {
+        (Some(score), Some(passing_score)) => Some(score >= passing_score),
+        _ => None,
+    }
+}
+
+/// When an attempt opened at `opened_test` has to be submitted by
+pub(crate) fn deadline(
+    opened_test: DateTime<Local>,
+    time_limit_minutes: Option<u32>,
+) -> Option<DateTime<Local>> {
+    time_limit_minutes.map(|time_limit_minutes| {
+        opened_test + Duration::minutes(i64::from(time_limit_minutes))
+    })
+}
+
+/// Whether a submission at `now` misses the deadline, allowing for
+/// `GRACE_PERIOD_SECONDS` of network and clock slack
+pub(crate) fn past_deadline(
+    deadline: Option<DateTime<Local>>,
+    now: DateTime<Local>,
+) -> bool {
+    deadline.into_iter().any(|deadline| {
+        deadline + Duration::seconds(GRACE_PERIOD_SECONDS) < now
+    })
+}
+
+/// Whether a stage of a test session is allowed at the given time
+///
+/// A stage enabled or disabled by hand is always allowed or refused.
+/// Otherwise it is only allowed inside its scheduled window, where a missing
+/// start means the window is already open and a missing end means it never
+/// closes.
+pub(crate) fn window_open(
+    enabled: Option<bool>,
+    start: Option<DateTime<Local>>,
+    end: Option<DateTime<Local>>,
+    now: DateTime<Local>,
+) -> bool {
+    if let Some(enabled) = enabled {
+        return enabled;
+    }
+
+    if start.is_none() && end.is_none() {
+        return false;
+    }
+
+    let started = start.into_iter().all(|start| start <= now);
+    let ended = end.into_iter().any(|end| end <= now);
+
+    started && !ended
 }
 
 pub(crate) fn condense_join(
@@ -433,6 +1102,7 @@ pub(crate) fn condense_join(
                 opened_test: opened_test,
                 submitted_test: submitted_test,
                 score: score,
+                passed: passed(score, join.passing_score),
             }]
         } else {
             Vec::new()
@@ -451,6 +1121,37 @@ pub(crate) fn condense_join(
                 registrations_enabled: join.test_session.registrations_enabled,
                 opening_enabled: join.test_session.opening_enabled,
                 submissions_enabled: join.test_session.submissions_enabled,
+                registrations_start: join
+                    .test_session
+                    .registrations_start
+                    .map(to_local)
+                    .transpose()?,
+                registrations_end: join
+                    .test_session
+                    .registrations_end
+                    .map(to_local)
+                    .transpose()?,
+                opening_start: join
+                    .test_session
+                    .opening_start
+                    .map(to_local)
+                    .transpose()?,
+                opening_end: join
+                    .test_session
+                    .opening_end
+                    .map(to_local)
+                    .transpose()?,
+                submissions_start: join
+                    .test_session
+                    .submissions_start
+                    .map(to_local)
+                    .transpose()?,
+                submissions_end: join
+                    .test_session
+                    .submissions_end
+                    .map(to_local)
+                    .transpose()?,
+                time_limit_minutes: join.test_session.time_limit_minutes,
             };
 
             condensed.push(test_session);
@@ -459,11 +1160,66 @@ pub(crate) fn condense_join(
     Ok(condensed)
 }
 
+/// Get a page of test sessions, optionally only those of one test
+///
+/// Pages hold at most `DEFAULT_LIMIT` test sessions unless a limit is given.
+///
+/// When `passed` is given, only the registrations that passed or failed are
+/// kept, along with the test sessions they belong to.
 pub(crate) fn get_test_sessions(
     test_id: Option<u64>,
+    passed: Option<bool>,
+    page: Page,
     database_connection: &MysqlConnection,
 ) -> Result<TestSessionList, Error> {
+    let total = test_sessions_query(test_id, passed)
+        .count()
+        .get_result::<i64>(database_connection)? as u64;
+
+    // Every test session on the page is looked up by id, so pages are always
+    // limited
+    let page = page.or_limit(DEFAULT_LIMIT);
+
+    let mut test_sessions_query =
+        test_sessions_query(test_id, passed).select(test_sessions_schema::id);
+
+    if let Some(sort) = &page.sort {
+        test_sessions_query = match sort.field.as_ref() {
+            "id" => sort
+                .order
+                .order_by(test_sessions_query, test_sessions_schema::id),
+            "test_id" => sort
+                .order
+                .order_by(test_sessions_query, test_sessions_schema::test_id),
+            "name" => sort
+                .order
+                .order_by(test_sessions_query, test_sessions_schema::name),
+            "registrations_start" => sort.order.order_by(
+                test_sessions_query,
+                test_sessions_schema::registrations_start,
+            ),
+            "opening_start" => sort.order.order_by(
+                test_sessions_query,
+                test_sessions_schema::opening_start,
+            ),
+            "submissions_start" => sort.order.order_by(
+                test_sessions_query,
+                test_sessions_schema::submissions_start,
+            ),
+            _ => return Err(Error::new(ErrorKind::Url)),
+        };
+    }
+
+    // Page through test sessions rather than the joined rows, so that a test
+    // session's registrations are never split across pages
+    let test_session_ids = page
+        .paginate(
+            test_sessions_query.then_order_by(test_sessions_schema::id.asc()),
+        )
+        .load::<u64>(database_connection)?;
+
     let mut query = test_sessions_schema::table
+        .inner_join(tests_schema::table)
         .left_join(test_session_registrations_schema::table)
         .select((
             (
@@ -473,6 +1229,13 @@ pub(crate) fn get_test_sessions(
                 test_sessions_schema::registrations_enabled,
                 test_sessions_schema::opening_enabled,
                 test_sessions_schema::submissions_enabled,
+                test_sessions_schema::registrations_start,
+                test_sessions_schema::registrations_end,
+                test_sessions_schema::opening_start,
+                test_sessions_schema::opening_end,
+                test_sessions_schema::submissions_start,
+                test_sessions_schema::submissions_end,
+                test_sessions_schema::time_limit_minutes,
             ),
             (
                 test_session_registrations_schema::id,
@@ -484,11 +1247,25 @@ pub(crate) fn get_test_sessions(
                 test_session_registrations_schema::score,
             )
                 .nullable(),
+            tests_schema::passing_score,
         ))
+        .filter(test_sessions_schema::id.eq_any(&test_session_ids))
         .into_boxed();
 
-    if let Some(test_id) = test_id {
-        query = query.filter(test_sessions_schema::test_id.eq(test_id));
+    match passed {
+        Some(true) => {
+            query = query.filter(
+                test_session_registrations_schema::score
+                    .ge(tests_schema::passing_score),
+            );
+        }
+        Some(false) => {
+            query = query.filter(
+                test_session_registrations_schema::score
+                    .lt(tests_schema::passing_score),
+            );
+        }
+        None => {}
     };
 
     let joined_test_sessions =
@@ -496,18 +1273,85 @@ pub(crate) fn get_test_sessions(
 
     trace!("Joined Test Sessions: {:#?}", joined_test_sessions);
 
-    let test_sessions = condense_join(joined_test_sessions)?;
+    let positions: HashMap<u64, usize> = test_session_ids
+        .iter()
+        .enumerate()
+        .map(|(position, id)| (*id, position))
+        .collect();
+
+    let mut test_sessions = condense_join(joined_test_sessions)?;
+    test_sessions.sort_by_key(|t| positions.get(&t.id).copied());
 
     Ok(TestSessionList {
         test_sessions: test_sessions,
+        total,
+        next: page.next(total),
     })
 }
 
+/// Build a query for the test sessions of a test, in no particular order
+///
+/// When `passed` is given, only test sessions with a registration that passed
+/// or failed are kept.
+fn test_sessions_query(
+    test_id: Option<u64>,
+    passed: Option<bool>,
+) -> test_sessions_schema::BoxedQuery<'static, Mysql> {
+    let mut query = test_sessions_schema::table.into_boxed();
+
+    if let Some(test_id) = test_id {
+        query = query.filter(test_sessions_schema::test_id.eq(test_id));
+    };
+
+    match passed {
+        Some(true) => {
+            query = query.filter(
+                test_sessions_schema::id.eq_any(
+                    test_session_registrations_schema::table
+                        .inner_join(
+                            test_sessions_schema::table
+                                .inner_join(tests_schema::table),
+                        )
+                        .filter(
+                            test_session_registrations_schema::score
+                                .ge(tests_schema::passing_score),
+                        )
+                        .select(
+                            test_session_registrations_schema::test_session_id,
+                        ),
+                ),
+            );
+        }
+        Some(false) => {
+            query = query.filter(
+                test_sessions_schema::id.eq_any(
+                    test_session_registrations_schema::table
+                        .inner_join(
+                            test_sessions_schema::table
+                                .inner_join(tests_schema::table),
+                        )
+                        .filter(
+                            test_session_registrations_schema::score
+                                .lt(tests_schema::passing_score),
+                        )
+                        .select(
+                            test_session_registrations_schema::test_session_id,
+                        ),
+                ),
+            );
+        }
+        None => {}
+    };
+
+    query
+}
+
 pub(crate) fn get_test_session(
     id: u64,
     database_connection: &MysqlConnection,
 ) -> Result<TestSession, Error> {
     let joined_test_sessions = test_sessions_schema::table
+        .inner_join(tests_schema::table)
         .left_join(test_session_registrations_schema::table)
         .select((
             (
@@ -517,6 +1361,13 @@ pub(crate) fn get_test_session(
                 test_sessions_schema::registrations_enabled,
                 test_sessions_schema::opening_enabled,
                 test_sessions_schema::submissions_enabled,
+                test_sessions_schema::registrations_start,
+                test_sessions_schema::registrations_end,
+                test_sessions_schema::opening_start,
+                test_sessions_schema::opening_end,
+                test_sessions_schema::submissions_start,
+                test_sessions_schema::submissions_end,
+                test_sessions_schema::time_limit_minutes,
             ),
             (
                 test_session_registrations_schema::id,
@@ -528,6 +1379,7 @@ pub(crate) fn get_test_session(
                 test_session_registrations_schema::score,
             )
                 .nullable(),
+            tests_schema::passing_score,
         ))
         .filter(test_sessions_schema::id.eq(id))
         .load::<JoinedTestSession>(database_connection)?;
@@ -550,9 +1402,16 @@ pub(crate) fn create_test_session(
     let new_raw_test_session = NewRawTestSession {
         test_id: test_session.test_id,
         name: test_session.name,
-        registrations_enabled: false,
-        opening_enabled: false,
-        submissions_enabled: false,
+        registrations_enabled: None,
+        opening_enabled: None,
+        submissions_enabled: None,
+        registrations_start: test_session.registrations_start,
+        registrations_end: test_session.registrations_end,
+        opening_start: test_session.opening_start,
+        opening_end: test_session.opening_end,
+        submissions_start: test_session.submissions_start,
+        submissions_end: test_session.submissions_end,
+        time_limit_minutes: test_session.time_limit_minutes,
     };
 
     diesel::insert_into(test_sessions_schema::table)
@@ -572,6 +1431,31 @@ pub(crate) fn create_test_session(
             registrations_enabled: inserted_test_session.registrations_enabled,
             opening_enabled: inserted_test_session.opening_enabled,
             submissions_enabled: inserted_test_session.submissions_enabled,
+            registrations_start: inserted_test_session
+                .registrations_start
+                .map(to_local)
+                .transpose()?,
+            registrations_end: inserted_test_session
+                .registrations_end
+                .map(to_local)
+                .transpose()?,
+            opening_start: inserted_test_session
+                .opening_start
+                .map(to_local)
+                .transpose()?,
+            opening_end: inserted_test_session
+                .opening_end
+                .map(to_local)
+                .transpose()?,
+            submissions_start: inserted_test_session
+                .submissions_start
+                .map(to_local)
+                .transpose()?,
+            submissions_end: inserted_test_session
+                .submissions_end
+                .map(to_local)
+                .transpose()?,
+            time_limit_minutes: inserted_test_session.time_limit_minutes,
         })
     } else {
         Err(Error::new(ErrorKind::Database))
@@ -601,3 +1485,335 @@ pub(crate) fn delete_test_session(
 
     Ok(())
 }
+
+#[cfg(test)]
+fn grading_question(id: u64) -> Question {
+    Question {
+        id,
+        category_id: 1,
+        title: format!("Question {}", id),
+        correct_answer: "right".to_owned(),
+        incorrect_answer_1: "wrong 1".to_owned(),
+        incorrect_answer_2: "wrong 2".to_owned(),
+        incorrect_answer_3: "wrong 3".to_owned(),
+    }
+}
+
+#[test]
+fn grade_counts_unanswered_as_wrong() {
+    let served_questions = vec![grading_question(1), grading_question(2)];
+    let response_questions = vec![ResponseQuestion {
+        id: 1,
+        answer: "right".to_owned(),
+    }];
+
+    let graded_responses =
+        grade(7, &served_questions, &response_questions).unwrap();
+
+    assert_eq!(graded_responses.len(), 2);
+    assert!(graded_responses[0].correct);
+    assert_eq!(graded_responses[1].answer, None);
+    assert!(!graded_responses[1].correct);
+    assert_eq!(score(&graded_responses), 0.5);
+}
+
+#[test]
+fn grade_rejects_unserved_questions() {
+    let served_questions = vec![grading_question(1)];
+    let response_questions = vec![
+        ResponseQuestion {
+            id: 1,
+            answer: "right".to_owned(),
+        },
+        ResponseQuestion {
+            id: 2,
+            answer: "right".to_owned(),
+        },
+    ];
+
+    assert!(grade(7, &served_questions, &response_questions).is_err());
+}
+
+#[test]
+fn grade_rejects_questions_answered_twice() {
+    let served_questions = vec![grading_question(1), grading_question(2)];
+    let response_questions = vec![
+        ResponseQuestion {
+            id: 1,
+            answer: "wrong 1".to_owned(),
+        },
+        ResponseQuestion {
+            id: 1,
+            answer: "right".to_owned(),
+        },
+    ];
+
+    assert!(grade(7, &served_questions, &response_questions).is_err());
+}
+
+#[test]
+fn grade_keeps_the_chosen_answer() {
+    let served_questions = vec![grading_question(1), grading_question(2)];
+    let response_questions = vec![
+        ResponseQuestion {
+            id: 2,
+            answer: "right".to_owned(),
+        },
+        ResponseQuestion {
+            id: 1,
+            answer: "wrong 2".to_owned(),
+        },
+    ];
+
+    let graded_responses =
+        grade(7, &served_questions, &response_questions).unwrap();
+
+    assert_eq!(graded_responses[0].registration_id, 7);
+    assert_eq!(graded_responses[0].answer, Some("wrong 2".to_owned()));
+    assert!(!graded_responses[0].correct);
+    assert!(graded_responses[1].correct);
+    assert_eq!(score(&graded_responses), 0.5);
+}
+
+#[test]
+fn window_open_follows_flag_without_schedule() {
+    let now = Local::now();
+
+    assert!(window_open(Some(true), None, None, now));
+    assert!(!window_open(Some(false), None, None, now));
+    assert!(!window_open(None, None, None, now));
+}
+
+#[test]
+fn window_open_follows_schedule() {
+    let now = Local::now();
+    let hour = Duration::hours(1);
+
+    assert!(window_open(None, Some(now - hour), Some(now + hour), now));
+    assert!(window_open(None, Some(now - hour), None, now));
+    assert!(window_open(None, None, Some(now + hour), now));
+    assert!(!window_open(None, Some(now + hour), None, now));
+    assert!(!window_open(None, None, Some(now - hour), now));
+}
+
+#[test]
+fn window_open_flag_overrides_schedule() {
+    let now = Local::now();
+    let hour = Duration::hours(1);
+
+    assert!(window_open(Some(true), Some(now + hour), None, now));
+    assert!(!window_open(Some(false), Some(now - hour), None, now));
+}
+
+#[test]
+fn deadline_without_time_limit_is_none() {
+    assert_eq!(deadline(Local::now(), None), None);
+    assert!(!past_deadline(None, Local::now()));
+}
+
+#[test]
+fn past_deadline_allows_grace_period() {
+    let opened_test = Local::now();
+    let deadline = deadline(opened_test, Some(30));
+
+    assert_eq!(deadline, Some(opened_test + Duration::minutes(30)));
+    assert!(!past_deadline(
+        deadline,
+        opened_test + Duration::minutes(29)
+    ));
+    assert!(!past_deadline(
+        deadline,
+        opened_test + Duration::minutes(30) + Duration::seconds(30)
+    ));
+    assert!(past_deadline(deadline, opened_test + Duration::minutes(32)));
+}
+
+#[test]
+fn passed_needs_score_and_passing_score() {
+    assert_eq!(passed(None, Some(0.7)), None);
+    assert_eq!(passed(Some(0.7), None), None);
+    assert_eq!(passed(Some(0.7), Some(0.7)), Some(true));
+    assert_eq!(passed(Some(0.69), Some(0.7)), Some(false));
+}
+
+#[cfg(test)]
+fn retake_attempt(
+    id: u64,
+    test_session_id: u64,
+    submitted_hour: Option<u32>,
+    score: Option<f32>,
+) -> RawTestSessionRegistration {
+    let day = chrono::NaiveDate::from_ymd_opt(2019, 7, 15).unwrap();
+
+    RawTestSessionRegistration {
+        id,
+        test_session_id,
+        taker_id: 1,
+        registered: day.and_hms_opt(0, 0, 0).unwrap(),
+        opened_test: submitted_hour.map(|_| day.and_hms_opt(0, 30, 0).unwrap()),
+        submitted_test: submitted_hour
+            .map(|hour| day.and_hms_opt(hour, 0, 0).unwrap()),
+        score,
+    }
+}
+
+#[test]
+fn check_retake_rejects_open_registration_in_same_session() {
+    let attempts = vec![retake_attempt(1, 1, None, None)];
+    let now = chrono::NaiveDate::from_ymd_opt(2019, 7, 16)
+        .unwrap()
+        .and_hms_opt(0, 0, 0)
+        .unwrap();
+
+    assert!(check_retake(1, None, None, &attempts, now).is_err());
+    assert!(check_retake(2, None, None, &attempts, now).is_ok());
+}
+
+#[test]
+fn check_retake_enforces_limit_and_cooldown() {
+    let attempts = vec![retake_attempt(1, 1, Some(10), Some(0.5))];
+    let now = chrono::NaiveDate::from_ymd_opt(2019, 7, 15)
+        .unwrap()
+        .and_hms_opt(12, 0, 0)
+        .unwrap();
+
+    assert!(check_retake(2, Some(1), None, &attempts, now).is_err());
+    assert!(check_retake(2, Some(2), None, &attempts, now).is_ok());
+    assert!(check_retake(2, None, Some(4), &attempts, now).is_err());
+    assert!(check_retake(2, None, Some(2), &attempts, now).is_ok());
+}
+
+#[test]
+fn check_retake_counts_open_attempts_toward_cooldown() {
+    let attempts = vec![retake_attempt(1, 1, None, None)];
+    let now = chrono::NaiveDate::from_ymd_opt(2019, 7, 16)
+        .unwrap()
+        .and_hms_opt(0, 0, 0)
+        .unwrap();
+
+    assert!(check_retake(2, None, Some(1), &attempts, now).is_err());
+    assert!(check_retake(2, None, Some(0), &attempts, now).is_ok());
+}
+
+#[test]
+fn counted_attempt_follows_policy() {
+    let attempts = vec![
+        retake_attempt(1, 1, Some(1), Some(0.5)),
+        retake_attempt(2, 2, Some(3), Some(0.9)),
+        retake_attempt(3, 3, Some(5), Some(0.7)),
+        retake_attempt(4, 4, None, None),
+    ];
+
+    let counted_id =
+        |policy| counted_attempt(policy, &attempts).map(|attempt| attempt.id);
+
+    assert_eq!(counted_id(CountedAttempt::First), Some(1));
+    assert_eq!(counted_id(CountedAttempt::Latest), Some(3));
+    assert_eq!(counted_id(CountedAttempt::Best), Some(2));
+    assert!(counted_attempt(CountedAttempt::Best, &attempts[3..]).is_none());
+}
+
+#[test]
+fn certified_attempt_follows_counted_attempt() {
+    let attempts = vec![
+        retake_attempt(1, 1, Some(1), Some(0.9)),
+        retake_attempt(2, 2, Some(3), Some(0.5)),
+    ];
+
+    let certified_id = |policy, passing_score| {
+        certified_attempt(policy, passing_score, &attempts)
+            .map(|attempt| attempt.id)
+    };
+
+    assert_eq!(certified_id(CountedAttempt::First, Some(0.7)), Some(1));
+    assert_eq!(certified_id(CountedAttempt::Best, Some(0.7)), Some(1));
+    assert_eq!(certified_id(CountedAttempt::Latest, Some(0.7)), None);
+    assert_eq!(certified_id(CountedAttempt::Latest, Some(0.5)), Some(2));
+    assert_eq!(certified_id(CountedAttempt::Best, None), None);
+}
+
+#[test]
+#[ignore]
+fn submit_after_time_limit_records_nothing() {
+    use crate::tests::tests::models::NewTest;
+    use crate::tests::tests::requests::create_test;
+    use crate::users::requests::create_user;
+
+    let database_connection = crate::testing::database_connection();
+
+    database_connection.test_transaction::<_, Error, _>(|| {
+        let taker =
+            create_user(crate::testing::new_user(), &database_connection)?;
+
+        let test = create_test(
+            NewTest {
+                name: "Transaction Test".to_owned(),
+                passing_score: None,
+                max_attempts: None,
+                retake_cooldown_hours: None,
+                counted_attempt: CountedAttempt::Best,
+                certification_id: None,
+                questions: Vec::new(),
+            },
+            Some(taker.id),
+            &database_connection,
+        )?;
+
+        let test_session = create_test_session(
+            NewTestSession {
+                test_id: test.id,
+                name: "Transaction Test Session".to_owned(),
+                registrations_start: None,
+                registrations_end: None,
+                opening_start: None,
+                opening_end: None,
+                submissions_start: Some(
+                    Local::now().naive_local() - Duration::days(1),
+                ),
+                submissions_end: None,
+                time_limit_minutes: Some(30),
+            },
+            &database_connection,
+        )?;
+
+        let opened_test = Local::now().naive_local() - Duration::hours(1);
+
+        diesel::insert_into(test_session_registrations_schema::table)
+            .values(NewRawTestSessionRegistration {
+                test_session_id: test_session.id,
+                taker_id: taker.id,
+                registered: opened_test,
+                opened_test: Some(opened_test),
+                submitted_test: None,
+                score: None,
+            })
+            .execute(&database_connection)?;
+
+        match submit(
+            test_session.id,
+            ResponseQuestionList {
+                questions: Vec::new(),
+            },
+            Some(taker.id),
+            &database_connection,
+        ) {
+            Err(ref e) => match e.kind() {
+                ErrorKind::SubmittedAfterTimeLimit => (),
+                _ => panic!("Late submission was not refused: {}", e),
+            },
+            Ok(()) => panic!("Late submission was accepted"),
+        }
+
+        let registration = test_session_registrations_schema::table
+            .filter(
+                test_session_registrations_schema::test_session_id
+                    .eq(test_session.id),
+            )
+            .first::<RawTestSessionRegistration>(&database_connection)?;
+
+        assert!(registration.submitted_test.is_none());
+        assert_eq!(registration.score, None);
+
+        Ok(())
+    });
+}
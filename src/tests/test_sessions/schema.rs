@@ -1,11 +1,21 @@
+use crate::tests::questions::schema::questions;
+use crate::tests::tests::schema::tests;
+
 table! {
     test_sessions (id) {
         id -> Unsigned<Bigint>,
         test_id -> Unsigned<Bigint>,
         name -> Varchar,
-        registrations_enabled -> Bool,
-        opening_enabled -> Bool,
-        submissions_enabled -> Bool,
+        registrations_enabled -> Nullable<Bool>,
+        opening_enabled -> Nullable<Bool>,
+        submissions_enabled -> Nullable<Bool>,
+        registrations_start -> Nullable<Timestamp>,
+        registrations_end -> Nullable<Timestamp>,
+        opening_start -> Nullable<Timestamp>,
+        opening_end -> Nullable<Timestamp>,
+        submissions_start -> Nullable<Timestamp>,
+        submissions_end -> Nullable<Timestamp>,
+        time_limit_minutes -> Nullable<Unsigned<Integer>>,
     }
 }
 
@@ -21,8 +31,40 @@ table! {
     }
 }
 
+table! {
+    test_session_served_questions (id) {
+        id -> Unsigned<Bigint>,
+        registration_id -> Unsigned<Bigint>,
+        question_id -> Unsigned<Bigint>,
+        position -> Unsigned<Integer>,
+        answer_1 -> Unsigned<Tinyint>,
+        answer_2 -> Unsigned<Tinyint>,
+        answer_3 -> Unsigned<Tinyint>,
+        answer_4 -> Unsigned<Tinyint>,
+    }
+}
+
+table! {
+    test_responses (id) {
+        id -> Unsigned<Bigint>,
+        registration_id -> Unsigned<Bigint>,
+        question_id -> Unsigned<Bigint>,
+        answer -> Nullable<Varchar>,
+        correct -> Bool,
+    }
+}
+
+joinable!(test_sessions -> tests (test_id));
 joinable!(test_session_registrations -> test_sessions (test_session_id));
+joinable!(test_session_served_questions -> test_session_registrations (registration_id));
+joinable!(test_session_served_questions -> questions (question_id));
+joinable!(test_responses -> test_session_registrations (registration_id));
+joinable!(test_responses -> questions (question_id));
 allow_tables_to_appear_in_same_query!(
     test_sessions,
-    test_session_registrations
+    test_session_registrations,
+    test_session_served_questions,
+    test_responses,
+    questions,
+    tests
 );
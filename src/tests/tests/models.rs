@@ -4,6 +4,10 @@ use serde::Deserialize;
 use serde::Serialize;
 use serde_json;
 
+use url::form_urlencoded;
+
+use chrono::NaiveDateTime;
+
 use log::warn;
 
 use crate::errors::Error;
@@ -17,6 +21,12 @@ pub struct RawTest {
     pub id: u64,
     pub creator_id: u64,
     pub name: String,
+    pub passing_score: Option<f32>,
+    pub max_attempts: Option<u32>,
+    pub retake_cooldown_hours: Option<u32>,
+    pub counted_attempt: String,
+    pub certification_id: Option<u64>,
+    pub deleted_at: Option<NaiveDateTime>,
 }
 
 #[derive(Insertable)]
@@ -24,6 +34,11 @@ pub struct RawTest {
 pub struct NewRawTest {
     pub creator_id: u64,
     pub name: String,
+    pub passing_score: Option<f32>,
+    pub max_attempts: Option<u32>,
+    pub retake_cooldown_hours: Option<u32>,
+    pub counted_attempt: String,
+    pub certification_id: Option<u64>,
 }
 
 #[derive(Queryable, Insertable, Debug)]
@@ -39,19 +54,97 @@ pub struct JoinedTest {
     pub id: u64,
     pub creator_id: u64,
     pub name: String,
+    pub passing_score: Option<f32>,
+    pub max_attempts: Option<u32>,
+    pub retake_cooldown_hours: Option<u32>,
+    pub counted_attempt: String,
+    pub certification_id: Option<u64>,
+    pub deleted_at: Option<NaiveDateTime>,
     pub test_id: u64,
     pub question_category_id: u64,
     pub number_of_questions: u32,
 }
 
+/// A test built from questions of some question categories
+///
+/// `passing_score` is the lowest score, from `0.0` to `1.0`, that passes the
+/// test. Tests without one have no pass or fail.
+///
+/// A user may take the test up to `max_attempts` times across all of its
+/// sessions, waiting `retake_cooldown_hours` after submitting one attempt
+/// before registering for the next. Leaving either out removes that limit.
+///
+/// The certification `certification_id`, if any, is issued for the attempt
+/// that counts if it passed, and taken back once another attempt counts
+/// instead.
+///
+/// Deleted tests have `deleted_at` set, and are only listed when asked for
+/// with `?include_deleted=true`.
 #[derive(Serialize, Deserialize)]
 pub struct Test {
     pub id: u64,
     pub creator_id: u64,
     pub name: String,
+    pub passing_score: Option<f32>,
+    pub max_attempts: Option<u32>,
+    pub retake_cooldown_hours: Option<u32>,
+    pub counted_attempt: CountedAttempt,
+    pub certification_id: Option<u64>,
+    pub deleted_at: Option<NaiveDateTime>,
     pub questions: Vec<TestQuestionCategory>,
 }
 
+/// Which of a user's submitted attempts at a test is their result
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CountedAttempt {
+    #[default]
+    Best,
+    Latest,
+    First,
+}
+
+impl CountedAttempt {
+    /// The name stored in the `counted_attempt` column
+    pub fn name(self) -> &'static str {
+        match self {
+            CountedAttempt::Best => "best",
+            CountedAttempt::Latest => "latest",
+            CountedAttempt::First => "first",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Result<CountedAttempt, Error> {
+        match name {
+            "best" => Ok(CountedAttempt::Best),
+            "latest" => Ok(CountedAttempt::Latest),
+            "first" => Ok(CountedAttempt::First),
+            _ => {
+                warn!("Unknown counted attempt in the database: {}", name);
+                Err(Error::new(ErrorKind::Database))
+            }
+        }
+    }
+}
+
+/// The result that counts for one user who has taken a test
+///
+/// `registration_id`, `score` and `passed` are `None` until the user has
+/// submitted an attempt.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TestResult {
+    pub taker_id: u64,
+    pub attempts: u32,
+    pub registration_id: Option<u64>,
+    pub score: Option<f32>,
+    pub passed: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TestResultList {
+    pub results: Vec<TestResult>,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct TestQuestionCategory {
     pub question_category_id: u64,
@@ -61,6 +154,12 @@ pub struct TestQuestionCategory {
 #[derive(Serialize, Deserialize)]
 pub struct NewTest {
     pub name: String,
+    pub passing_score: Option<f32>,
+    pub max_attempts: Option<u32>,
+    pub retake_cooldown_hours: Option<u32>,
+    #[serde(default)]
+    pub counted_attempt: CountedAttempt,
+    pub certification_id: Option<u64>,
     pub questions: Vec<TestQuestionCategory>,
 }
 
@@ -70,25 +169,40 @@ pub struct TestList {
 }
 
 pub enum TestRequest {
-    GetTests,
+    GetTests(bool),
     GetTest(u64),
+    GetTestResults(u64),
     CreateTest(NewTest),
     DeleteTest(u64),
+    RestoreTest(u64),
 }
 
 impl TestRequest {
     pub fn from_rouille(
         request: &rouille::Request,
     ) -> Result<TestRequest, Error> {
+        let mut url_queries =
+            form_urlencoded::parse(request.raw_query_string().as_bytes());
+
         router!(request,
             (GET) (/) => {
-                Ok(TestRequest::GetTests)
+                let include_deleted =
+                    match url_queries.find(|q| q.0 == "include_deleted") {
+                        Some((_, include_deleted)) => include_deleted.parse()?,
+                        None => false,
+                    };
+
+                Ok(TestRequest::GetTests(include_deleted))
             },
 
             (GET) (/{id: u64}) => {
                 Ok(TestRequest::GetTest(id))
             },
 
+            (GET) (/{id: u64}/results) => {
+                Ok(TestRequest::GetTestResults(id))
+            },
+
             (POST) (/) => {
                 let request_body = request.data().ok_or(Error::new(ErrorKind::Body))?;
                 let new_test: NewTest = serde_json::from_reader(request_body)?;
@@ -100,6 +214,10 @@ impl TestRequest {
                 Ok(TestRequest::DeleteTest(id))
             },
 
+            (POST) (/{id: u64}/restore) => {
+                Ok(TestRequest::RestoreTest(id))
+            },
+
             _ => {
                 warn!("Could not create a test request for the given rouille request");
                 Err(Error::new(ErrorKind::NotFound))
@@ -111,6 +229,7 @@ impl TestRequest {
 pub enum TestResponse {
     OneTest(Test),
     ManyTests(TestList),
+    ManyTestResults(TestResultList),
     NoResponse,
 }
 
@@ -119,6 +238,9 @@ impl TestResponse {
         match self {
             TestResponse::OneTest(test) => rouille::Response::json(&test),
             TestResponse::ManyTests(tests) => rouille::Response::json(&tests),
+            TestResponse::ManyTestResults(results) => {
+                rouille::Response::json(&results)
+            }
             TestResponse::NoResponse => rouille::Response::empty_204(),
         }
     }
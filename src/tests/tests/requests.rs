@@ -1,62 +1,170 @@
 use diesel;
 use diesel::mysql::MysqlConnection;
+use diesel::Connection;
 use diesel::ExpressionMethods;
 use diesel::QueryDsl;
 use diesel::RunQueryDsl;
 
+use chrono::offset::Local;
+use chrono::NaiveDateTime;
+
 use crate::errors::Error;
 use crate::errors::ErrorKind;
 
 use crate::access::requests::check_to_run;
 
+use crate::audit::requests::record_change;
+
 use crate::tests::tests::models::{
-    JoinedTest, NewRawTest, NewTest, RawTest, RawTestQuestionCategory, Test,
-    TestList, TestQuestionCategory, TestRequest, TestResponse,
+    CountedAttempt, JoinedTest, NewRawTest, NewTest, RawTest,
+    RawTestQuestionCategory, Test, TestList, TestQuestionCategory, TestRequest,
+    TestResponse, TestResult, TestResultList,
 };
 use crate::tests::tests::schema::test_question_categories as test_question_categories_schema;
 use crate::tests::tests::schema::tests as tests_schema;
 
+use crate::tests::test_sessions::models::RawTestSessionRegistration;
+use crate::tests::test_sessions::requests::{counted_attempt, passed};
+use crate::tests::test_sessions::schema::test_session_registrations as test_session_registrations_schema;
+use crate::tests::test_sessions::schema::test_sessions as test_sessions_schema;
+
 pub fn handle_test(
     request: TestRequest,
     requested_user: Option<u64>,
     database_connection: &MysqlConnection,
 ) -> Result<TestResponse, Error> {
     match request {
-        TestRequest::GetTests => {
-            check_to_run(requested_user, "GetTests", database_connection)?;
-            get_tests(database_connection).map(|u| TestResponse::ManyTests(u))
+        TestRequest::GetTests(include_deleted) => {
+            check_to_run(
+                requested_user,
+                "GetTests",
+                None,
+                database_connection,
+            )?;
+            get_tests(include_deleted, database_connection)
+                .map(|u| TestResponse::ManyTests(u))
         }
         TestRequest::GetTest(id) => {
-            check_to_run(requested_user, "GetTests", database_connection)?;
+            check_to_run(
+                requested_user,
+                "GetTests",
+                None,
+                database_connection,
+            )?;
             get_test(id, database_connection).map(|u| TestResponse::OneTest(u))
         }
+        TestRequest::GetTestResults(id) => {
+            check_to_run(
+                requested_user,
+                "GetTestSessionRegistrations",
+                None,
+                database_connection,
+            )?;
+            get_test_results(id, database_connection)
+                .map(|u| TestResponse::ManyTestResults(u))
+        }
         TestRequest::CreateTest(test) => {
-            check_to_run(requested_user, "CreateTests", database_connection)?;
-            create_test(test, requested_user, database_connection)
+            check_to_run(
+                requested_user,
+                "CreateTests",
+                None,
+                database_connection,
+            )?;
+            database_connection
+                .transaction::<_, Error, _>(|| {
+                    let created =
+                        create_test(test, requested_user, database_connection)?;
+                    record_change(
+                        requested_user,
+                        "CreateTests",
+                        "tests",
+                        created.id,
+                        None,
+                        Some(&created),
+                        database_connection,
+                    )?;
+                    Ok(created)
+                })
                 .map(|u| TestResponse::OneTest(u))
         }
         TestRequest::DeleteTest(id) => {
-            check_to_run(requested_user, "DeleteTests", database_connection)?;
-            delete_test(id, database_connection)
+            check_to_run(
+                requested_user,
+                "DeleteTests",
+                None,
+                database_connection,
+            )?;
+            database_connection
+                .transaction::<_, Error, _>(|| {
+                    let before = get_test(id, database_connection)?;
+                    delete_test(id, database_connection)?;
+                    let after = get_test(id, database_connection)?;
+                    record_change(
+                        requested_user,
+                        "DeleteTests",
+                        "tests",
+                        id,
+                        Some(&before),
+                        Some(&after),
+                        database_connection,
+                    )
+                })
+                .map(|_| TestResponse::NoResponse)
+        }
+        TestRequest::RestoreTest(id) => {
+            check_to_run(
+                requested_user,
+                "RestoreTests",
+                None,
+                database_connection,
+            )?;
+            database_connection
+                .transaction::<_, Error, _>(|| {
+                    let before = get_test(id, database_connection)?;
+                    restore_test(id, database_connection)?;
+                    let after = get_test(id, database_connection)?;
+                    record_change(
+                        requested_user,
+                        "RestoreTests",
+                        "tests",
+                        id,
+                        Some(&before),
+                        Some(&after),
+                        database_connection,
+                    )
+                })
                 .map(|_| TestResponse::NoResponse)
         }
     }
 }
 
 pub(crate) fn get_tests(
+    include_deleted: bool,
     database_connection: &MysqlConnection,
 ) -> Result<TestList, Error> {
-    let joined_tests = tests_schema::table
+    let mut tests_query = tests_schema::table
         .inner_join(test_question_categories_schema::table)
         .select((
             tests_schema::id,
             tests_schema::creator_id,
             tests_schema::name,
+            tests_schema::passing_score,
+            tests_schema::max_attempts,
+            tests_schema::retake_cooldown_hours,
+            tests_schema::counted_attempt,
+            tests_schema::certification_id,
+            tests_schema::deleted_at,
             test_question_categories_schema::test_id,
             test_question_categories_schema::question_category_id,
             test_question_categories_schema::number_of_questions,
         ))
-        .load::<JoinedTest>(database_connection)?;
+        .into_boxed();
+
+    if !include_deleted {
+        tests_query = tests_query.filter(tests_schema::deleted_at.is_null());
+    }
+
+    let joined_tests = tests_query.load::<JoinedTest>(database_connection)?;
 
     let mut tests: Vec<Test> = Vec::new();
 
@@ -71,6 +179,14 @@ pub(crate) fn get_tests(
                 id: joined_test.id,
                 creator_id: joined_test.creator_id,
                 name: joined_test.name,
+                passing_score: joined_test.passing_score,
+                max_attempts: joined_test.max_attempts,
+                retake_cooldown_hours: joined_test.retake_cooldown_hours,
+                counted_attempt: CountedAttempt::from_name(
+                    &joined_test.counted_attempt,
+                )?,
+                certification_id: joined_test.certification_id,
+                deleted_at: joined_test.deleted_at,
                 questions: vec![TestQuestionCategory {
                     question_category_id: joined_test.question_category_id,
                     number_of_questions: joined_test.number_of_questions,
@@ -89,67 +205,89 @@ pub(crate) fn create_test(
     requesting_user: Option<u64>,
     database_connection: &MysqlConnection,
 ) -> Result<Test, Error> {
-    let creator_id = match requesting_user {
-        Some(user) => user,
-        None => return Err(Error::new(ErrorKind::AccessDenied)),
-    };
-
-    let new_raw_test = NewRawTest {
-        creator_id: creator_id,
-        name: test.name,
-    };
-
-    diesel::insert_into(tests_schema::table)
-        .values(new_raw_test)
-        .execute(database_connection)?;
+    database_connection.transaction(|| {
+        let creator_id = match requesting_user {
+            Some(user) => user,
+            None => return Err(Error::new(ErrorKind::AccessDenied)),
+        };
 
-    let mut raw_inserted_tests = tests_schema::table
-        .filter(diesel::dsl::sql("id = LAST_INSERT_ID()"))
-        .load::<RawTest>(database_connection)?;
-
-    if let Some(raw_inserted_test) = raw_inserted_tests.pop() {
-        let test_question_categories: Vec<_> = test
-            .questions
-            .iter()
-            .map(|test_question_category| RawTestQuestionCategory {
-                test_id: raw_inserted_test.id,
-                number_of_questions: test_question_category.number_of_questions,
-                question_category_id: test_question_category
-                    .question_category_id,
-            })
-            .collect();
-
-        diesel::insert_into(test_question_categories_schema::table)
-            .values(test_question_categories)
+        if let Some(passing_score) = test.passing_score {
+            if !(0.0..=1.0).contains(&passing_score) {
+                return Err(Error::new(ErrorKind::Body));
+            }
+        }
+
+        let new_raw_test = NewRawTest {
+            creator_id: creator_id,
+            name: test.name,
+            passing_score: test.passing_score,
+            max_attempts: test.max_attempts,
+            retake_cooldown_hours: test.retake_cooldown_hours,
+            counted_attempt: test.counted_attempt.name().to_owned(),
+            certification_id: test.certification_id,
+        };
+
+        diesel::insert_into(tests_schema::table)
+            .values(new_raw_test)
             .execute(database_connection)?;
 
-        let inserted_test_question_categories =
-            test_question_categories_schema::table
-                .filter(
-                    test_question_categories_schema::test_id
-                        .eq(raw_inserted_test.id),
-                )
-                .load::<RawTestQuestionCategory>(database_connection)?
+        let mut raw_inserted_tests = tests_schema::table
+            .filter(diesel::dsl::sql("id = LAST_INSERT_ID()"))
+            .load::<RawTest>(database_connection)?;
+
+        if let Some(raw_inserted_test) = raw_inserted_tests.pop() {
+            let test_question_categories: Vec<_> = test
+                .questions
                 .iter()
-                .map(|raw_test_question_category| TestQuestionCategory {
-                    number_of_questions: raw_test_question_category
+                .map(|test_question_category| RawTestQuestionCategory {
+                    test_id: raw_inserted_test.id,
+                    number_of_questions: test_question_category
                         .number_of_questions,
-                    question_category_id: raw_test_question_category
+                    question_category_id: test_question_category
                         .question_category_id,
                 })
                 .collect();
 
-        let inserted_test = Test {
-            id: raw_inserted_test.id,
-            creator_id: raw_inserted_test.creator_id,
-            name: raw_inserted_test.name,
-            questions: inserted_test_question_categories,
-        };
+            diesel::insert_into(test_question_categories_schema::table)
+                .values(test_question_categories)
+                .execute(database_connection)?;
 
-        Ok(inserted_test)
-    } else {
-        Err(Error::new(ErrorKind::Database))
-    }
+            let inserted_test_question_categories =
+                test_question_categories_schema::table
+                    .filter(
+                        test_question_categories_schema::test_id
+                            .eq(raw_inserted_test.id),
+                    )
+                    .load::<RawTestQuestionCategory>(database_connection)?
+                    .iter()
+                    .map(|raw_test_question_category| TestQuestionCategory {
+                        number_of_questions: raw_test_question_category
+                            .number_of_questions,
+                        question_category_id: raw_test_question_category
+                            .question_category_id,
+                    })
+                    .collect();
+
+            let inserted_test = Test {
+                id: raw_inserted_test.id,
+                creator_id: raw_inserted_test.creator_id,
+                name: raw_inserted_test.name,
+                passing_score: raw_inserted_test.passing_score,
+                max_attempts: raw_inserted_test.max_attempts,
+                retake_cooldown_hours: raw_inserted_test.retake_cooldown_hours,
+                counted_attempt: CountedAttempt::from_name(
+                    &raw_inserted_test.counted_attempt,
+                )?,
+                certification_id: raw_inserted_test.certification_id,
+                deleted_at: raw_inserted_test.deleted_at,
+                questions: inserted_test_question_categories,
+            };
+
+            Ok(inserted_test)
+        } else {
+            Err(Error::new(ErrorKind::Database))
+        }
+    })
 }
 
 pub(crate) fn get_test(
@@ -163,6 +301,12 @@ pub(crate) fn get_test(
             tests_schema::id,
             tests_schema::creator_id,
             tests_schema::name,
+            tests_schema::passing_score,
+            tests_schema::max_attempts,
+            tests_schema::retake_cooldown_hours,
+            tests_schema::counted_attempt,
+            tests_schema::certification_id,
+            tests_schema::deleted_at,
             test_question_categories_schema::test_id,
             test_question_categories_schema::question_category_id,
             test_question_categories_schema::number_of_questions,
@@ -174,6 +318,14 @@ pub(crate) fn get_test(
             id: first_joined_test.id,
             creator_id: first_joined_test.creator_id,
             name: first_joined_test.name,
+            passing_score: first_joined_test.passing_score,
+            max_attempts: first_joined_test.max_attempts,
+            retake_cooldown_hours: first_joined_test.retake_cooldown_hours,
+            counted_attempt: CountedAttempt::from_name(
+                &first_joined_test.counted_attempt,
+            )?,
+            certification_id: first_joined_test.certification_id,
+            deleted_at: first_joined_test.deleted_at,
             questions: vec![TestQuestionCategory {
                 question_category_id: first_joined_test.question_category_id,
                 number_of_questions: first_joined_test.number_of_questions,
@@ -193,12 +345,199 @@ pub(crate) fn get_test(
     }
 }
 
+/// Get the attempts of every user at a test, across all of its sessions, in
+/// the order they registered
+pub(crate) fn get_attempts(
+    test_id: u64,
+    taker_id: Option<u64>,
+    database_connection: &MysqlConnection,
+) -> Result<Vec<RawTestSessionRegistration>, Error> {
+    let mut query = test_session_registrations_schema::table
+        .inner_join(test_sessions_schema::table)
+        .select(test_session_registrations_schema::all_columns)
+        .filter(test_sessions_schema::test_id.eq(test_id))
+        .order(test_session_registrations_schema::id.asc())
+        .into_boxed();
+
+    if let Some(taker_id) = taker_id {
+        query = query
+            .filter(test_session_registrations_schema::taker_id.eq(taker_id));
+    }
+
+    let attempts =
+        query.load::<RawTestSessionRegistration>(database_connection)?;
+
+    Ok(attempts)
+}
+
+/// Get the result that counts for each user who has taken a test
+pub(crate) fn get_test_results(
+    id: u64,
+    database_connection: &MysqlConnection,
+) -> Result<TestResultList, Error> {
+    let test = get_test(id, database_connection)?;
+
+    let mut attempts_by_taker: Vec<(u64, Vec<RawTestSessionRegistration>)> =
+        Vec::new();
+
+    for attempt in get_attempts(id, None, database_connection)? {
+        if let Some((_, attempts)) = attempts_by_taker
+            .iter_mut()
+            .find(|(taker_id, _)| *taker_id == attempt.taker_id)
+        {
+            attempts.push(attempt);
+        } else {
+            attempts_by_taker.push((attempt.taker_id, vec![attempt]));
+        }
+    }
+
+    let results = attempts_by_taker
+        .iter()
+        .map(|(taker_id, attempts)| {
+            let counted = counted_attempt(test.counted_attempt, attempts);
+            let score = counted.and_then(|counted| counted.score);
+
+            TestResult {
+                taker_id: *taker_id,
+                attempts: attempts.len() as u32,
+                registration_id: counted.map(|counted| counted.id),
+                score,
+                passed: passed(score, test.passing_score),
+            }
+        })
+        .collect();
+
+    Ok(TestResultList { results })
+}
+
+/// Mark a test as deleted, keeping its sessions and results around until it
+/// is restored
 pub(crate) fn delete_test(
     id: u64,
     database_connection: &MysqlConnection,
 ) -> Result<(), Error> {
-    diesel::delete(tests_schema::table.filter(tests_schema::id.eq(id)))
+    diesel::update(tests_schema::table)
+        .filter(tests_schema::id.eq(id))
+        .set(tests_schema::deleted_at.eq(Some(Local::now().naive_local())))
+        .execute(database_connection)?;
+
+    Ok(())
+}
+
+pub(crate) fn restore_test(
+    id: u64,
+    database_connection: &MysqlConnection,
+) -> Result<(), Error> {
+    diesel::update(tests_schema::table)
+        .filter(tests_schema::id.eq(id))
+        .set(tests_schema::deleted_at.eq(None::<NaiveDateTime>))
         .execute(database_connection)?;
 
     Ok(())
 }
+
+#[test]
+#[ignore]
+fn create_test_with_bad_category_creates_nothing() {
+    use crate::users::requests::create_user;
+
+    let database_connection = crate::testing::database_connection();
+
+    database_connection.test_transaction::<_, Error, _>(|| {
+        let creator =
+            create_user(crate::testing::new_user(), &database_connection)?;
+
+        let new_test = NewTest {
+            name: "Transaction Test".to_owned(),
+            passing_score: None,
+            max_attempts: None,
+            retake_cooldown_hours: None,
+            counted_attempt: CountedAttempt::Best,
+            certification_id: None,
+            questions: vec![TestQuestionCategory {
+                question_category_id: u64::max_value(),
+                number_of_questions: 1,
+            }],
+        };
+
+        assert!(
+            create_test(new_test, Some(creator.id), &database_connection)
+                .is_err()
+        );
+
+        let found_tests = tests_schema::table
+            .filter(tests_schema::name.eq("Transaction Test"))
+            .load::<RawTest>(&database_connection)?;
+
+        assert!(found_tests.is_empty());
+
+        Ok(())
+    });
+}
+
+#[test]
+#[ignore]
+fn delete_test_hides_test_until_restored() {
+    use crate::tests::question_categories::models::NewQuestionCategory;
+    use crate::tests::question_categories::requests::create_question_category;
+    use crate::tests::questions::models::NewQuestion;
+    use crate::users::requests::create_user;
+
+    let database_connection = crate::testing::database_connection();
+
+    database_connection.test_transaction::<_, Error, _>(|| {
+        let creator =
+            create_user(crate::testing::new_user(), &database_connection)?;
+
+        let question_category = create_question_category(
+            NewQuestionCategory {
+                title: "Transaction Test".to_owned(),
+                questions: vec![NewQuestion {
+                    title: "Transaction Test".to_owned(),
+                    correct_answer: "Correct".to_owned(),
+                    incorrect_answer_1: "Incorrect 1".to_owned(),
+                    incorrect_answer_2: "Incorrect 2".to_owned(),
+                    incorrect_answer_3: "Incorrect 3".to_owned(),
+                }],
+            },
+            &database_connection,
+        )?;
+
+        let new_test = NewTest {
+            name: "Transaction Test".to_owned(),
+            passing_score: None,
+            max_attempts: None,
+            retake_cooldown_hours: None,
+            counted_attempt: CountedAttempt::Best,
+            certification_id: None,
+            questions: vec![TestQuestionCategory {
+                question_category_id: question_category.id,
+                number_of_questions: 1,
+            }],
+        };
+
+        let test =
+            create_test(new_test, Some(creator.id), &database_connection)?;
+
+        let listed = |include_deleted| -> Result<bool, Error> {
+            Ok(get_tests(include_deleted, &database_connection)?
+                .tests
+                .iter()
+                .any(|t| t.id == test.id))
+        };
+
+        delete_test(test.id, &database_connection)?;
+
+        assert!(!listed(false)?);
+        assert!(listed(true)?);
+
+        restore_test(test.id, &database_connection)?;
+
+        assert!(listed(false)?);
+        assert!(get_test(test.id, &database_connection)?
+            .deleted_at
+            .is_none());
+
+        Ok(())
+    });
+}
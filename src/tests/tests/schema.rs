@@ -3,6 +3,12 @@ table! {
         id -> Unsigned<Bigint>,
         creator_id -> Unsigned<Bigint>,
         name -> Varchar,
+        passing_score -> Nullable<Float>,
+        max_attempts -> Nullable<Unsigned<Integer>>,
+        retake_cooldown_hours -> Nullable<Unsigned<Integer>>,
+        counted_attempt -> Varchar,
+        certification_id -> Nullable<Unsigned<Bigint>>,
+        deleted_at -> Nullable<Timestamp>,
     }
 }
 
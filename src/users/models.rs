@@ -4,16 +4,22 @@ use serde::Deserialize;
 use serde::Serialize;
 use url::form_urlencoded;
 
+use chrono::NaiveDateTime;
+
 use log::warn;
 
 use super::schema::users;
 
 use crate::access::models::Permission;
 
+use crate::certifications::models::UserCertification;
+
 use crate::errors::Error;
 use crate::errors::ErrorKind;
 
-use crate::search::Search;
+use crate::nullable::deserialize_nullable;
+
+use crate::search::{Digits, Page, Search};
 
 #[derive(Queryable, Debug)]
 pub struct RawUser {
@@ -22,6 +28,8 @@ pub struct RawUser {
     pub last_name: String,
     pub banner_id: u32,
     pub email: String,
+    pub department: Option<String>,
+    pub deleted_at: Option<NaiveDateTime>,
 }
 
 #[derive(Insertable, Debug)]
@@ -31,6 +39,7 @@ pub struct NewRawUser {
     pub last_name: String,
     pub banner_id: u32,
     pub email: String,
+    pub department: Option<String>,
 }
 
 #[derive(Queryable, Debug)]
@@ -39,6 +48,11 @@ pub struct JoinedUser {
     pub access: Option<Permission>,
 }
 
+/// A user, with their certifications only when asked for with
+/// `?expand=certifications`
+///
+/// Deleted users keep their records, but have `deleted_at` set and can no
+/// longer log in.
 #[derive(Serialize, Deserialize, Debug)]
 pub struct User {
     pub id: u64,
@@ -46,7 +60,11 @@ pub struct User {
     pub last_name: String,
     pub banner_id: u32,
     pub email: String,
+    pub department: Option<String>,
+    pub deleted_at: Option<NaiveDateTime>,
     pub accesses: Vec<Permission>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub certifications: Option<Vec<UserCertification>>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -55,7 +73,11 @@ pub struct NewUser {
     pub last_name: String,
     pub banner_id: u32,
     pub email: String,
+    #[serde(default)]
+    pub department: Option<String>,
     pub accesses: Vec<u64>,
+    #[serde(default)]
+    pub roles: Vec<u64>,
 }
 
 #[derive(Debug, AsChangeset, Serialize, Deserialize)]
@@ -65,29 +87,61 @@ pub struct PartialUser {
     pub last_name: Option<String>,
     pub banner_id: Option<u32>,
     pub email: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_nullable")]
+    pub department: Option<Option<String>>,
 }
 
 #[derive(Debug)]
 pub struct SearchUser {
     pub first_name: Search<String>,
     pub last_name: Search<String>,
-    pub banner_id: Search<u32>,
+    pub banner_id: Search<Digits<u32>>,
     pub email: Search<String>,
+    pub include_deleted: bool,
+    pub page: Page,
 }
 
+/// One page of users, out of `total` matching a search
+///
+/// `next` is the offset of the page after this one, if there is one.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct UserList {
     pub users: Vec<User>,
+    pub total: u64,
+    pub next: Option<u64>,
+}
+
+/// Related records to include with users
+#[derive(Debug, Default, Clone, Copy)]
+pub struct UserExpansions {
+    pub certifications: bool,
+}
+
+impl UserExpansions {
+    /// Read the comma separated `expand` query, e.g. `?expand=certifications`
+    pub fn from_query(query: &str) -> Result<UserExpansions, Error> {
+        let mut expansions = UserExpansions::default();
+
+        for expansion in query.split(',').filter(|e| !e.is_empty()) {
+            match expansion {
+                "certifications" => expansions.certifications = true,
+                _ => return Err(Error::new(ErrorKind::Url)),
+            }
+        }
+
+        Ok(expansions)
+    }
 }
 
 #[derive(Debug)]
 pub enum UserRequest {
-    SearchUsers(SearchUser),
-    Current,
-    GetUser(u64),
+    SearchUsers(SearchUser, UserExpansions),
+    Current(UserExpansions),
+    GetUser(u64, UserExpansions),
     CreateUser(NewUser),
     UpdateUser(u64, PartialUser),
     DeleteUser(u64),
+    RestoreUser(u64),
 }
 
 impl UserRequest {
@@ -104,6 +158,9 @@ impl UserRequest {
                 let mut last_name_search = Search::NoSearch;
                 let mut banner_id_search = Search::NoSearch;
                 let mut email_search = Search::NoSearch;
+                let mut include_deleted = false;
+                let mut page = Page::default();
+                let mut expansions = UserExpansions::default();
 
                 for (field, query) in url_queries {
                     match field.as_ref() {
@@ -115,6 +172,12 @@ impl UserRequest {
                             Search::from_query(query.as_ref())?,
                         "email" => email_search =
                             Search::from_query(query.as_ref())?,
+                        "include_deleted" => include_deleted =
+                            query.parse()?,
+                        "limit" | "offset" | "sort" =>
+                            page.add_query(field.as_ref(), query.as_ref())?,
+                        "expand" => expansions =
+                            UserExpansions::from_query(query.as_ref())?,
                         _ => return Err(Error::new(ErrorKind::Url)),
                     }
                 }
@@ -124,15 +187,17 @@ impl UserRequest {
                     last_name: last_name_search,
                     banner_id: banner_id_search,
                     email: email_search,
-                }))
+                    include_deleted,
+                    page,
+                }, expansions))
             },
 
             (GET) (/current) => {
-                Ok(UserRequest::Current)
+                Ok(UserRequest::Current(expansions(request)?))
             },
 
             (GET) (/{id: u64}) => {
-                Ok(UserRequest::GetUser(id))
+                Ok(UserRequest::GetUser(id, expansions(request)?))
             },
 
             (POST) (/) => {
@@ -156,6 +221,10 @@ impl UserRequest {
                 Ok(UserRequest::DeleteUser(id))
             },
 
+            (POST) (/{id: u64}/restore) => {
+                Ok(UserRequest::RestoreUser(id))
+            },
+
             _ => {
                 warn!("Could not create a user request for the given rouille request");
                 Err(Error::new(ErrorKind::NotFound))
@@ -164,6 +233,15 @@ impl UserRequest {
     }
 }
 
+fn expansions(request: &rouille::Request) -> Result<UserExpansions, Error> {
+    match form_urlencoded::parse(request.raw_query_string().as_bytes())
+        .find(|q| q.0 == "expand")
+    {
+        Some((_, query)) => UserExpansions::from_query(query.as_ref()),
+        None => Ok(UserExpansions::default()),
+    }
+}
+
 #[derive(Debug)]
 pub enum UserResponse {
     OneUser(User),
@@ -180,3 +258,23 @@ impl UserResponse {
         }
     }
 }
+
+#[test]
+fn user_expansions_from_query_works() {
+    assert!(
+        UserExpansions::from_query("certifications")
+            .unwrap()
+            .certifications
+    );
+    assert!(!UserExpansions::from_query("").unwrap().certifications);
+    assert!(UserExpansions::from_query("nonsense").is_err());
+}
+
+#[test]
+fn partial_user_clears_department_with_null() {
+    let partial: PartialUser =
+        serde_json::from_str(r#"{"department": null}"#).unwrap();
+
+    assert_eq!(partial.department, Some(None));
+    assert_eq!(partial.first_name, None);
+}
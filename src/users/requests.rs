@@ -1,31 +1,41 @@
+use std::collections::HashMap;
+
 use crate::diesel::NullableExpressionMethods;
 use diesel;
+use diesel::mysql::Mysql;
 use diesel::mysql::MysqlConnection;
 use diesel::query_builder::AsQuery;
+use diesel::Connection;
 use diesel::ExpressionMethods;
 use diesel::QueryDsl;
 use diesel::RunQueryDsl;
-use diesel::TextExpressionMethods;
 
-use log::trace;
-use log::warn;
+use chrono::offset::Local;
+use chrono::NaiveDateTime;
 
 use crate::errors::Error;
 use crate::errors::ErrorKind;
 
-use crate::search::Search;
+use crate::search::DEFAULT_LIMIT;
 
 use crate::access::requests::check_to_run;
 
-use crate::access::models::NewUserAccess;
+use crate::audit::requests::record_change;
+
+use crate::access::models::{NewUserAccess, UserRole};
+
+use crate::certifications::requests::get_users_certifications;
+
+use crate::sessions::requests::revoke_user_sessions;
 
 use crate::users::models::{
     JoinedUser, NewRawUser, NewUser, PartialUser, RawUser, SearchUser, User,
-    UserList, UserRequest, UserResponse,
+    UserExpansions, UserList, UserRequest, UserResponse,
 };
 
 use crate::access::schema::permission as permission_schema;
 use crate::access::schema::user_access as user_access_schema;
+use crate::access::schema::user_roles as user_roles_schema;
 use crate::users::schema::users as users_schema;
 
 pub fn handle_user(
@@ -34,25 +44,58 @@ pub fn handle_user(
     database_connection: &MysqlConnection,
 ) -> Result<UserResponse, Error> {
     match request {
-        UserRequest::SearchUsers(user) => {
-            match check_to_run(requested_user, "GetUsers", database_connection)
-            {
+        UserRequest::SearchUsers(user, expansions) => {
+            match check_to_run(
+                requested_user,
+                "GetUsers",
+                None,
+                database_connection,
+            ) {
                 Ok(()) => search_users(user, database_connection)
+                    .and_then(|mut u| {
+                        expand_users(
+                            &mut u.users,
+                            expansions,
+                            requested_user,
+                            database_connection,
+                        )?;
+                        Ok(u)
+                    })
                     .map(|u| UserResponse::ManyUsers(u)),
                 Err(e) => Err(e),
             }
         }
 
-        UserRequest::Current => match requested_user {
+        UserRequest::Current(expansions) => match requested_user {
             Some(id) => get_user(id, database_connection)
+                .and_then(|u| {
+                    expand_user(
+                        u,
+                        expansions,
+                        requested_user,
+                        database_connection,
+                    )
+                })
                 .map(|u| UserResponse::OneUser(u)),
             None => Ok(UserResponse::NoResponse),
         },
 
-        UserRequest::GetUser(id) => {
-            match check_to_run(requested_user, "GetUsers", database_connection)
-            {
+        UserRequest::GetUser(id, expansions) => {
+            match check_to_run(
+                requested_user,
+                "GetUsers",
+                None,
+                database_connection,
+            ) {
                 Ok(()) => get_user(id, database_connection)
+                    .and_then(|u| {
+                        expand_user(
+                            u,
+                            expansions,
+                            requested_user,
+                            database_connection,
+                        )
+                    })
                     .map(|u| UserResponse::OneUser(u)),
                 Err(e) => Err(e),
             }
@@ -61,9 +104,23 @@ pub fn handle_user(
             match check_to_run(
                 requested_user,
                 "CreateUsers",
+                None,
                 database_connection,
             ) {
-                Ok(()) => create_user(user, database_connection)
+                Ok(()) => database_connection
+                    .transaction::<_, Error, _>(|| {
+                        let created = create_user(user, database_connection)?;
+                        record_change(
+                            requested_user,
+                            "CreateUsers",
+                            "users",
+                            created.id,
+                            None,
+                            Some(&created),
+                            database_connection,
+                        )?;
+                        Ok(created)
+                    })
                     .map(|u| UserResponse::OneUser(u)),
                 Err(e) => Err(e),
             }
@@ -71,22 +128,79 @@ pub fn handle_user(
         UserRequest::UpdateUser(id, user) => {
             match check_to_run(
                 requested_user,
-                "DeleteUsers",
+                "UpdateUsers",
+                None,
                 database_connection,
             ) {
-                Ok(()) => update_user(id, user, database_connection)
+                Ok(()) => database_connection
+                    .transaction::<_, Error, _>(|| {
+                        let before = get_user(id, database_connection)?;
+                        update_user(id, user, database_connection)?;
+                        let after = get_user(id, database_connection)?;
+                        record_change(
+                            requested_user,
+                            "UpdateUsers",
+                            "users",
+                            id,
+                            Some(&before),
+                            Some(&after),
+                            database_connection,
+                        )
+                    })
                     .map(|_| UserResponse::NoResponse),
                 Err(e) => Err(e),
             }
         }
         UserRequest::DeleteUser(id) => {
-            match check_to_run(requested_user, "GetUsers", database_connection)
-            {
-                Ok(()) => delete_user(id, database_connection)
+            match check_to_run(
+                requested_user,
+                "DeleteUsers",
+                None,
+                database_connection,
+            ) {
+                Ok(()) => database_connection
+                    .transaction::<_, Error, _>(|| {
+                        let before = get_user(id, database_connection)?;
+                        delete_user(id, database_connection)?;
+                        let after = get_user(id, database_connection)?;
+                        record_change(
+                            requested_user,
+                            "DeleteUsers",
+                            "users",
+                            id,
+                            Some(&before),
+                            Some(&after),
+                            database_connection,
+                        )
+                    })
                     .map(|_| UserResponse::NoResponse),
                 Err(e) => Err(e),
             }
         }
+        UserRequest::RestoreUser(id) => {
+            check_to_run(
+                requested_user,
+                "RestoreUsers",
+                None,
+                database_connection,
+            )?;
+            database_connection
+                .transaction::<_, Error, _>(|| {
+                    let before = get_user(id, database_connection)?;
+                    restore_user(id, database_connection)?;
+                    let after = get_user(id, database_connection)?;
+                    record_change(
+                        requested_user,
+                        "RestoreUsers",
+                        "users",
+                        id,
+                        Some(&before),
+                        Some(&after),
+                        database_connection,
+                    )
+                })
+                .map(|_| UserResponse::NoResponse)
+        }
     }
 }
 
@@ -110,7 +224,10 @@ fn condense_join(joined: Vec<JoinedUser>) -> Vec<User> {
                 last_name: join.user.last_name,
                 banner_id: join.user.banner_id,
                 email: join.user.email,
+                department: join.user.department,
+                deleted_at: join.user.deleted_at,
                 accesses: access,
+                certifications: None,
             };
 
             condensed.push(user);
@@ -119,11 +236,100 @@ fn condense_join(joined: Vec<JoinedUser>) -> Vec<User> {
     condensed
 }
 
+fn expand_user(
+    mut user: User,
+    expansions: UserExpansions,
+    requested_user: Option<u64>,
+    database_connection: &MysqlConnection,
+) -> Result<User, Error> {
+    expand_users(
+        std::slice::from_mut(&mut user),
+        expansions,
+        requested_user,
+        database_connection,
+    )?;
+    Ok(user)
+}
+
+/// Fill in the related records that were asked for
+fn expand_users(
+    users: &mut [User],
+    expansions: UserExpansions,
+    requested_user: Option<u64>,
+    database_connection: &MysqlConnection,
+) -> Result<(), Error> {
+    if expansions.certifications {
+        // Users can always see their own certifications
+        if users.iter().any(|u| Some(u.id) != requested_user) {
+            check_to_run(
+                requested_user,
+                "GetUserCertifications",
+                None,
+                database_connection,
+            )?;
+        }
+
+        let user_ids: Vec<u64> = users.iter().map(|u| u.id).collect();
+        let certifications =
+            get_users_certifications(&user_ids, database_connection)?;
+
+        for user in users.iter_mut() {
+            user.certifications = Some(
+                certifications
+                    .iter()
+                    .filter(|c| c.user_id == user.id)
+                    .cloned()
+                    .collect(),
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Get a page of the users matching a search
+///
+/// Pages hold at most `DEFAULT_LIMIT` users unless a limit is given.
 pub(crate) fn search_users(
     user: SearchUser,
     database_connection: &MysqlConnection,
 ) -> Result<UserList, Error> {
-    let mut users_query = users_schema::table
+    let total = users_query(&user)
+        .count()
+        .get_result::<i64>(database_connection)? as u64;
+
+    // Every user on the page is looked up by id, so pages are always limited
+    let page = user.page.clone().or_limit(DEFAULT_LIMIT);
+
+    let mut users_query = users_query(&user).select(users_schema::id);
+
+    if let Some(sort) = &page.sort {
+        users_query = match sort.field.as_ref() {
+            "id" => sort.order.order_by(users_query, users_schema::id),
+            "first_name" => {
+                sort.order.order_by(users_query, users_schema::first_name)
+            }
+            "last_name" => {
+                sort.order.order_by(users_query, users_schema::last_name)
+            }
+            "banner_id" => {
+                sort.order.order_by(users_query, users_schema::banner_id)
+            }
+            "email" => sort.order.order_by(users_query, users_schema::email),
+            "department" => {
+                sort.order.order_by(users_query, users_schema::department)
+            }
+            _ => return Err(Error::new(ErrorKind::Url)),
+        };
+    }
+
+    // Page through users rather than the joined rows, so that a user's
+    // accesses are never split across pages
+    let user_ids = page
+        .paginate(users_query.then_order_by(users_schema::id.asc()))
+        .load::<u64>(database_connection)?;
+
+    let joined_users = users_schema::table
         .left_join(user_access_schema::table.left_join(permission_schema::table))
         .select((
             (
@@ -132,71 +338,51 @@ pub(crate) fn search_users(
                 users_schema::last_name,
                 users_schema::banner_id,
                 users_schema::email,
+                users_schema::department,
+                users_schema::deleted_at,
             ),
             (permission_schema::id, permission_schema::permission_name).nullable(),
         ))
-        .into_boxed();
-
-    match user.first_name {
-        Search::Partial(s) => {
-            users_query = users_query
-                .filter(users_schema::first_name.like(format!("%{}%", s)))
-        }
-
-        Search::Exact(s) => {
-            users_query = users_query.filter(users_schema::first_name.eq(s))
-        }
+        .filter(users_schema::id.eq_any(&user_ids))
+        .load::<JoinedUser>(database_connection)?;
 
-        Search::NoSearch => {}
-    }
+    let positions: HashMap<u64, usize> = user_ids
+        .iter()
+        .enumerate()
+        .map(|(position, id)| (*id, position))
+        .collect();
 
-    match user.last_name {
-        Search::Partial(s) => {
-            users_query = users_query
-                .filter(users_schema::last_name.like(format!("%{}%", s)))
-        }
+    let mut users = condense_join(joined_users);
+    users.sort_by_key(|u| positions.get(&u.id).copied());
 
-        Search::Exact(s) => {
-            users_query = users_query.filter(users_schema::last_name.eq(s))
-        }
+    let user_list = UserList {
+        users,
+        total,
+        next: page.next(total),
+    };
 
-        Search::NoSearch => {}
-    }
+    Ok(user_list)
+}
 
-    match user.banner_id {
-        Search::Partial(s) => {
-            warn!("Trying to partial search by banner id. This is not currently supported, so performing exact search instead");
-            trace!("Partial search required the field to be a text field, but banner id is currently an integet");;
-            users_query = users_query.filter(users_schema::banner_id.eq(s))
-        }
+/// Build a query for the users matching a search, in no particular order
+fn users_query(user: &SearchUser) -> users_schema::BoxedQuery<'_, Mysql> {
+    let mut users_query = users_schema::table.as_query().into_boxed();
 
-        Search::Exact(s) => {
-            users_query = users_query.filter(users_schema::banner_id.eq(s))
-        }
+    users_query =
+        user.first_name.filter_text(users_query, users_schema::first_name);
 
-        Search::NoSearch => {}
-    }
+    users_query =
+        user.last_name.filter_text(users_query, users_schema::last_name);
 
-    match user.email {
-        Search::Partial(s) => {
-            users_query =
-                users_query.filter(users_schema::email.like(format!("%{}%", s)))
-        }
+    users_query = user.banner_id.filter(users_query, users_schema::banner_id);
 
-        Search::Exact(s) => {
-            users_query = users_query.filter(users_schema::email.eq(s))
-        }
+    users_query = user.email.filter_text(users_query, users_schema::email);
 
-        Search::NoSearch => {}
+    if !user.include_deleted {
+        users_query = users_query.filter(users_schema::deleted_at.is_null());
     }
 
-    let joined_users = users_query.load::<JoinedUser>(database_connection)?;
-
-    let mut users = condense_join(joined_users);
-
-    let user_list = UserList { users };
-
-    Ok(user_list)
+    users_query
 }
 
 pub(crate) fn get_user(
@@ -212,6 +398,8 @@ pub(crate) fn get_user(
                 users_schema::last_name,
                 users_schema::banner_id,
                 users_schema::email,
+                users_schema::department,
+                users_schema::deleted_at,
             ),
             (permission_schema::id, permission_schema::permission_name).nullable(),
         ))
@@ -230,43 +418,59 @@ pub(crate) fn create_user(
     user: NewUser,
     database_connection: &MysqlConnection,
 ) -> Result<User, Error> {
-    let new_raw_user = NewRawUser {
-        first_name: user.first_name,
-        last_name: user.last_name,
-        banner_id: user.banner_id,
-        email: user.email,
-    };
-
-    diesel::insert_into(users_schema::table)
-        .values(new_raw_user)
-        .execute(database_connection)?;
-
-    let mut inserted_users = users_schema::table
-        .filter(diesel::dsl::sql("id = LAST_INSERT_ID()"))
-        .load::<RawUser>(database_connection)?;
-
-    if let Some(inserted_user) = inserted_users.pop() {
-
-        let new_user_accesses: Vec<_> = user
-            .accesses
-            .into_iter()
-            .map(|access_id| NewUserAccess {
-                access_id: access_id,
-                user_id: inserted_user.id,
-                permission_level: None,
-            })
-            .collect();
+    database_connection.transaction(|| {
+        let new_raw_user = NewRawUser {
+            first_name: user.first_name,
+            last_name: user.last_name,
+            banner_id: user.banner_id,
+            email: user.email,
+            department: user.department,
+        };
 
-        diesel::insert_into(user_access_schema::table)
-            .values(new_user_accesses)
+        diesel::insert_into(users_schema::table)
+            .values(new_raw_user)
             .execute(database_connection)?;
 
-        let inserted_user = get_user(inserted_user.id, database_connection)?;
-
-        Ok(inserted_user)
-    } else {
-        Err(Error::new(ErrorKind::Database))
-    }
+        let mut inserted_users = users_schema::table
+            .filter(diesel::dsl::sql("id = LAST_INSERT_ID()"))
+            .load::<RawUser>(database_connection)?;
+
+        if let Some(inserted_user) = inserted_users.pop() {
+            let new_user_accesses: Vec<_> = user
+                .accesses
+                .into_iter()
+                .map(|access_id| NewUserAccess {
+                    permission_id: access_id,
+                    user_id: inserted_user.id,
+                    access_level: None,
+                })
+                .collect();
+
+            diesel::insert_into(user_access_schema::table)
+                .values(new_user_accesses)
+                .execute(database_connection)?;
+
+            let new_user_roles: Vec<_> = user
+                .roles
+                .into_iter()
+                .map(|role_id| UserRole {
+                    user_id: inserted_user.id,
+                    role_id,
+                })
+                .collect();
+
+            diesel::insert_into(user_roles_schema::table)
+                .values(new_user_roles)
+                .execute(database_connection)?;
+
+            let inserted_user =
+                get_user(inserted_user.id, database_connection)?;
+
+            Ok(inserted_user)
+        } else {
+            Err(Error::new(ErrorKind::Database))
+        }
+    })
 }
 
 pub(crate) fn update_user(
@@ -281,12 +485,132 @@ pub(crate) fn update_user(
     Ok(())
 }
 
+/// Mark a user as deleted and log them out, keeping their records around
+/// until they are restored
 pub(crate) fn delete_user(
     id: u64,
     database_connection: &MysqlConnection,
 ) -> Result<(), Error> {
-    diesel::delete(users_schema::table.filter(users_schema::id.eq(id)))
+    diesel::update(users_schema::table)
+        .filter(users_schema::id.eq(id))
+        .set(users_schema::deleted_at.eq(Some(Local::now().naive_local())))
+        .execute(database_connection)?;
+
+    revoke_user_sessions(id, database_connection)
+}
+
+pub(crate) fn restore_user(
+    id: u64,
+    database_connection: &MysqlConnection,
+) -> Result<(), Error> {
+    diesel::update(users_schema::table)
+        .filter(users_schema::id.eq(id))
+        .set(users_schema::deleted_at.eq(None::<NaiveDateTime>))
         .execute(database_connection)?;
 
     Ok(())
 }
+
+#[test]
+#[ignore]
+fn create_user_with_bad_access_creates_nothing() {
+    let database_connection = crate::testing::database_connection();
+
+    database_connection.test_transaction::<_, Error, _>(|| {
+        let new_user = NewUser {
+            accesses: vec![u64::max_value()],
+            ..crate::testing::new_user()
+        };
+
+        assert!(create_user(new_user, &database_connection).is_err());
+
+        let found_users = users_schema::table
+            .filter(users_schema::email.eq("transaction_test@rowan.edu"))
+            .load::<RawUser>(&database_connection)?;
+
+        assert!(found_users.is_empty());
+
+        Ok(())
+    });
+}
+
+#[test]
+#[ignore]
+fn delete_user_hides_user_and_revokes_sessions() {
+    use crate::search::{Page, Search};
+    use crate::sessions::models::NewRawSession;
+    use crate::sessions::requests::get_sessions;
+    use crate::sessions::schema::sessions as sessions_schema;
+
+    let database_connection = crate::testing::database_connection();
+
+    database_connection.test_transaction::<_, Error, _>(|| {
+        let user =
+            create_user(crate::testing::new_user(), &database_connection)?;
+
+        let now = Local::now().naive_local();
+
+        diesel::insert_into(sessions_schema::table)
+            .values(NewRawSession {
+                token_hash: "0".repeat(64),
+                user_id: user.id,
+                created: now,
+                expires: now + chrono::Duration::hours(1),
+                revoked: false,
+            })
+            .execute(&database_connection)?;
+
+        let search = |include_deleted| SearchUser {
+            first_name: Search::NoSearch,
+            last_name: Search::NoSearch,
+            banner_id: Search::NoSearch,
+            email: Search::Exact("transaction_test@rowan.edu".to_owned()),
+            include_deleted,
+            page: Page::default(),
+        };
+
+        delete_user(user.id, &database_connection)?;
+
+        assert!(search_users(search(false), &database_connection)?
+            .users
+            .is_empty());
+        assert_eq!(
+            search_users(search(true), &database_connection)?.users.len(),
+            1
+        );
+        assert!(get_sessions(user.id, &database_connection)?
+            .sessions
+            .is_empty());
+
+        restore_user(user.id, &database_connection)?;
+
+        let found_users = search_users(search(false), &database_connection)?;
+
+        assert_eq!(found_users.users.len(), 1);
+        assert!(found_users.users[0].deleted_at.is_none());
+
+        Ok(())
+    });
+}
+
+#[test]
+fn users_query_partially_searches_banner_id() {
+    use crate::search::{Page, Search};
+
+    let user = SearchUser {
+        first_name: Search::NoSearch,
+        last_name: Search::NoSearch,
+        banner_id: Search::from_query("partial,01234").unwrap(),
+        email: Search::NoSearch,
+        include_deleted: true,
+        page: Page::default(),
+    };
+
+    let query = users_query(&user).select(users_schema::id);
+
+    assert_eq!(
+        diesel::debug_query::<Mysql, _>(&query).to_string(),
+        "SELECT `users`.`id` FROM `users` \
+         WHERE CAST(`users`.`banner_id` AS CHAR) LIKE ? -- binds: [\"%01234%\"]"
+    );
+}
@@ -5,5 +5,7 @@ table! {
         last_name -> Varchar,
         banner_id -> Unsigned<Integer>,
         email -> Varchar,
+        department -> Nullable<Varchar>,
+        deleted_at -> Nullable<Timestamp>,
     }
 }